@@ -0,0 +1,29 @@
+CREATE TABLE IF NOT EXISTS users (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    location TEXT NOT NULL,
+    title TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS authors (
+    id TEXT PRIMARY KEY,
+    author_name TEXT NOT NULL,
+    contact TEXT NOT NULL,
+    author_email TEXT NOT NULL,
+    created_at TEXT,
+    updated_at TEXT
+);
+
+CREATE TABLE IF NOT EXISTS author_profiles (
+    id TEXT PRIMARY KEY,
+    author_id TEXT NOT NULL,
+    profile_image TEXT NOT NULL,
+    created_at TEXT
+);
+
+CREATE TABLE IF NOT EXISTS books (
+    id TEXT PRIMARY KEY,
+    book_name TEXT NOT NULL,
+    book_author TEXT NOT NULL,
+    total_page INTEGER NOT NULL
+);
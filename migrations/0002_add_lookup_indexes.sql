@@ -0,0 +1,3 @@
+CREATE INDEX IF NOT EXISTS idx_users_name ON users (name);
+CREATE INDEX IF NOT EXISTS idx_author_profiles_author_id ON author_profiles (author_id);
+CREATE INDEX IF NOT EXISTS idx_books_book_author ON books (book_author);
@@ -0,0 +1,2 @@
+ALTER TABLE books ADD COLUMN created_by TEXT;
+ALTER TABLE authors ADD COLUMN created_by TEXT;
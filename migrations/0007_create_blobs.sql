@@ -0,0 +1,8 @@
+ALTER TABLE author_profiles ADD COLUMN digest TEXT NOT NULL DEFAULT '';
+ALTER TABLE author_profiles ADD COLUMN size BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE author_profiles ADD COLUMN content_type TEXT NOT NULL DEFAULT '';
+
+CREATE TABLE IF NOT EXISTS blobs (
+    file_key TEXT PRIMARY KEY,
+    refs BIGINT NOT NULL
+);
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_users_name;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_name ON users (name);
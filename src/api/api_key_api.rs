@@ -0,0 +1,60 @@
+use crate::{middleware::{auth_user::AuthUser, role_guard::RequireRole}, model::{api_key_model::{ApiKey, ApiKeyData, NewApiKey}, user_model::Role}};
+use crate::repository::store::ApiKeyStore;
+use crate::utils::{password::random_token, response::ResponseBuilder, validation::ValidJson};
+use actix_web::{delete, get, post, web::{Data, Path}, HttpResponse};
+use mongodb::bson::{self, oid::ObjectId};
+
+use super::app_errors::AppError;
+
+// every key starts with this, so leaked keys are easy to spot
+const API_KEY_PREFIX:&str = "bk_";
+
+#[post("/keys", wrap = "RequireRole::new(Role::Admin)")]
+pub async fn create_api_key(db:Data<dyn ApiKeyStore>, auth:AuthUser, new_key:ValidJson<NewApiKey>) -> Result<HttpResponse, AppError> {
+    let key = format!("{}{}", API_KEY_PREFIX, random_token());
+    // dedup only drops adjacent repeats, `["books:read", "authors:read", "books:read"]` has none
+    let mut scopes = new_key.scopes.to_owned();
+    scopes.sort_unstable();
+    scopes.dedup();
+
+    let mut api_key = ApiKey {
+        id:None,
+        name:new_key.name.trim().to_string(),
+        prefix:key.chars().take(API_KEY_PREFIX.len() + 8).collect(),
+        key_hash:ApiKey::hash(&key),
+        scopes,
+        created_by:auth.id,
+        created_at:bson::DateTime::now(),
+        last_used_at:None,
+        revoked:false,
+    };
+    let result = db.create_api_key(api_key.clone()).await?;
+    api_key.id = Some(result.inserted_id);
+
+    // the only time the plain key is handed out
+    let mut api_key_data = ApiKeyData::set_data(api_key);
+    api_key_data.key = Some(key);
+    let response = ResponseBuilder::BuildSuccessResponse(String::from("API key has been created"), Some(api_key_data));
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[get("/keys", wrap = "RequireRole::new(Role::Admin)")]
+pub async fn get_api_keys(db:Data<dyn ApiKeyStore>) -> Result<HttpResponse, AppError> {
+    let api_keys:Vec<ApiKeyData> = db.get_api_keys().await?.into_iter().map(ApiKeyData::set_data).collect();
+
+    let response = ResponseBuilder::BuildSuccessResponse(String::from("API keys fetch successfully"), Some(api_keys));
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[delete("/keys/{id}", wrap = "RequireRole::new(Role::Admin)")]
+pub async fn revoke_api_key(db:Data<dyn ApiKeyStore>, path:Path<String>) -> Result<HttpResponse, AppError> {
+    let obj_id = ObjectId::parse_str(path.into_inner()).map_err(|_| AppError::InvalidIdError)?;
+
+    // revoked keys are kept, so listings still show what a key was used for
+    let result = db.revoke_api_key(obj_id).await?;
+    if result.matched_count != 1 {
+        return Err(AppError::NotFoundError(String::from("No API key found with specific id")));
+    }
+    let response = ResponseBuilder::<()>::BuildSuccessResponse(String::from("API key has been revoked"), None);
+    Ok(HttpResponse::Ok().json(response))
+}
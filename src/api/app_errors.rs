@@ -1,17 +1,136 @@
-use derive_more::{Display, Error};
+use std::collections::BTreeMap;
 
-#[derive(Debug, Display, Error)]
+use actix_web::{http::{header, StatusCode}, HttpResponse, ResponseError};
+use derive_more::Display;
+
+use crate::utils::response::ResponseBuilder;
+
+// sent as `Retry-After` when the storage is unavailable
+const RETRY_AFTER_SECS:u64 = 1;
+
+// Every failure a handler or a store can report. Each variant maps to one HTTP status
+// and to the machine readable `code` of the error body.
+#[derive(Debug, Display)]
+#[allow(clippy::enum_variant_names)]
 pub enum AppError {
+    #[display(fmt = "{}", _0)]
+    ValidationError(String),
+
+    // payload rejected by its validation rules, messages keyed by field name
+    #[display(fmt = "Request validation failed")]
+    FieldValidationError(BTreeMap<String, Vec<String>>),
+
     #[display(fmt = "Invalid Id found")]
     InvalidIdError,
 
-    #[display(fmt = "Data not found")]
-    DataNotFoundError,
+    #[display(fmt = "{}", _0)]
+    NotFoundError(String),
+
+    #[display(fmt = "{}", _0)]
+    ConflictError(String),
+
+    #[display(fmt = "{}", _0)]
+    UnprocessableError(String),
+
+    #[display(fmt = "{}", _0)]
+    UnauthorizedError(String),
+
+    // authenticated, but the caller's role does not allow the request
+    #[display(fmt = "{}", _0)]
+    ForbiddenError(String),
+
+    // an upload over the size limit of its endpoint
+    #[display(fmt = "{}", _0)]
+    PayloadTooLargeError(String),
+
+    // an upload whose content is not one of the types its endpoint accepts
+    #[display(fmt = "{}", _0)]
+    UnsupportedMediaTypeError(String),
 
-    #[display(fmt= "User not found")]
-    UserNotFoundError
+    #[display(fmt = "{}", _0)]
+    StorageError(String),
+
+    #[display(fmt = "{}", _0)]
+    InternalError(String),
+
+    // the storage could not be reached, the same request may succeed later
+    #[display(fmt = "{}", _0)]
+    UnavailableError(String),
+
+    // rate limit or login lockout, with the seconds until the caller may try again
+    #[display(fmt = "{}", _0)]
+    TooManyRequestsError(String, u64),
+}
+
+impl AppError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::ValidationError(_) => "validation_error",
+            AppError::FieldValidationError(_) => "validation_failed",
+            AppError::InvalidIdError => "invalid_id",
+            AppError::NotFoundError(_) => "not_found",
+            AppError::ConflictError(_) => "conflict",
+            AppError::UnprocessableError(_) => "unprocessable_entity",
+            AppError::UnauthorizedError(_) => "unauthorized",
+            AppError::ForbiddenError(_) => "forbidden",
+            AppError::PayloadTooLargeError(_) => "payload_too_large",
+            AppError::UnsupportedMediaTypeError(_) => "unsupported_media_type",
+            AppError::StorageError(_) => "storage_error",
+            AppError::InternalError(_) => "internal_error",
+            AppError::UnavailableError(_) => "storage_unavailable",
+            AppError::TooManyRequestsError(..) => "too_many_requests",
+        }
+    }
+
+    // seconds to send as `Retry-After`, for errors where the same request may succeed later
+    pub fn retry_after(&self) -> Option<u64> {
+        match self {
+            AppError::UnavailableError(_) => Some(RETRY_AFTER_SECS),
+            AppError::TooManyRequestsError(_, secs) => Some(*secs),
+            _ => None,
+        }
+    }
 }
-#[derive(Debug, Display)]    
+
+impl std::error::Error for AppError {}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::ValidationError(_) | AppError::InvalidIdError => StatusCode::BAD_REQUEST,
+            AppError::NotFoundError(_) => StatusCode::NOT_FOUND,
+            AppError::ConflictError(_) => StatusCode::CONFLICT,
+            AppError::FieldValidationError(_) | AppError::UnprocessableError(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::UnauthorizedError(_) => StatusCode::UNAUTHORIZED,
+            AppError::ForbiddenError(_) => StatusCode::FORBIDDEN,
+            AppError::PayloadTooLargeError(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::UnsupportedMediaTypeError(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            AppError::StorageError(_) | AppError::InternalError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::UnavailableError(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::TooManyRequestsError(..) => StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        let mut response = ResponseBuilder::<()>::BuildErrorResponse(status, self.code(), self.to_string());
+        if let AppError::FieldValidationError(fields) = self {
+            response.fields = Some(fields.clone());
+        }
+        let mut builder = HttpResponse::build(status);
+        if let Some(secs) = self.retry_after() {
+            builder.insert_header((header::RETRY_AFTER, secs.to_string()));
+        }
+        // RFC 6750: a 401 names the scheme the client should authenticate with
+        if let AppError::UnauthorizedError(_) = self {
+            builder.insert_header((header::WWW_AUTHENTICATE, "Bearer"));
+        }
+        builder.json(response)
+    }
+}
+
+#[derive(Debug, Display)]
+#[allow(clippy::enum_variant_names)]
 pub enum AppMessage {
     #[display(fmt = "Data has been inserted!")]
     InsertSuccessMsg,
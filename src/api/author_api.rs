@@ -1,265 +1,366 @@
-use std::{fs::File, io::{Read, Write}};
-
-use actix_multipart::Multipart;
-use actix_web::{web::{Data, Json,Path}, HttpResponse, Responder};
+use actix_multipart::{Field, Multipart};
+use actix_web::{web::{Data, Path}, HttpRequest, HttpResponse};
 use futures::StreamExt;
 use mongodb::bson::oid::ObjectId;
 
-use crate::model::{self, author_model::*};
-use crate::utils::response::ResponseBuilder;
-use crate::repository::*;
+use crate::{middleware::auth_user::AuthUser, model::{self, author_model::*}};
+use crate::utils::{pagination::ListQuery, response::ResponseBuilder, validation::ValidJson};
+use crate::repository::store::{AuthorStore, BlobStore, BookStore, FileStorage};
+use crate::config::{app_config::AppConfig, db_config::AuthorDeletePolicy};
+
+use super::{app_errors::{AppError, AppMessage}, file_api::{release_file, store_profile_image, UploadPolicy}};
+
+
+pub async fn create_author(db:Data<dyn AuthorStore>, auth:AuthUser, author:ValidJson<Author>) -> Result<HttpResponse, AppError> {
+    let new_author = Author {
+        id:None,
+        author_name:author.author_name.to_owned(),
+        author_email:author.author_email.to_owned(),
+        contact:author.contact.to_owned(),
+        created_at:None,
+        created_by:Some(auth.id),
+    };
+
+    let result = db.create_author(new_author).await?;
+    let response = ResponseBuilder::BuildSuccessResponse(AppMessage::InsertSuccessMsg.to_string(), Some(result));
+    Ok(HttpResponse::Ok().json(response))
+}
+
+pub async fn get_authors(db:Data<dyn AuthorStore>, req:HttpRequest) -> Result<HttpResponse, AppError> {
+    let query = ListQuery::parse(req.query_string(), Author::LIST_FIELDS).map_err(AppError::ValidationError)?;
 
-use self::author_repo::AuthorRepo;
+    // an empty page is an answer too, e.g. past the last author matching the filters
+    let authors = db.get_authors(&query).await?;
 
-use super::app_errors::{AppError, AppMessage};
+    let last_id = authors.items.last().and_then(|author| author.id);
+    let pagination = query.pagination(req.path(), &authors, last_id);
+    let result:Vec<AuthorData> = authors.items.into_iter().map(AuthorData::set_data).collect();
 
+    let response = ResponseBuilder::<Vec<model::author_model::AuthorData>>::BuildPageResponse(AppMessage::FetchSuccessMsg.to_string(), Some(result), pagination);
+    Ok(HttpResponse::Ok().json(response))
+}
+
+pub async fn get_author(db:Data<dyn AuthorStore>, path: Path<String>) -> Result<HttpResponse, AppError> {
+    let id = ObjectId::parse_str(path.into_inner()).map_err(|_| AppError::InvalidIdError)?;
+
+    let author = db.get_author(id).await?;
+    let author_data = AuthorData::set_data(author);
+    let response = ResponseBuilder::BuildSuccessResponse("author found".to_string(), Some(author_data));
+    Ok(HttpResponse::Ok().json(response))
+}
+
+pub async fn update_author(db:Data<dyn AuthorStore>, path: Path<String>, author:ValidJson<Author>) -> Result<HttpResponse, AppError> {
+    let id = ObjectId::parse_str(path.into_inner()).map_err(|_| AppError::InvalidIdError)?;
 
-pub async fn create_author(db:Data<AuthorRepo>, author:Json<Author>) -> impl Responder {
     let new_author = Author {
         id:None,
         author_name:author.author_name.to_owned(),
         author_email:author.author_email.to_owned(),
         contact:author.contact.to_owned(),
         created_at:None,
+        created_by:None,
     };
 
-    let author_result = db.create_author(new_author).await;
-    match author_result {
-        Ok(result) => {
-            let response = ResponseBuilder::BuildSuccessResponse(AppMessage::InsertSuccessMsg.to_string(), Some(result));
-            HttpResponse::Ok().json(response)
-        },
-        Err(err) => {
-            let response = ResponseBuilder::<()>::BuildFailedResponse(err.to_string());
-            HttpResponse::InternalServerError().json(response)
-        }
+    let update_result = db.update_author(id, new_author).await?;
+    if update_result.matched_count != 1 {
+        return Err(AppError::NotFoundError(AppMessage::UpdateFailedMsg.to_string()));
     }
+    let response = ResponseBuilder::<()>::BuildSuccessResponse(AppMessage::UpdateSuccessMsg.to_string(), None);
+    Ok(HttpResponse::Ok().json(response))
 }
 
-pub async fn get_authors(db:Data<AuthorRepo>) -> impl Responder {
-    let authors = db.get_authors().await;
+pub async fn patch_author(db:Data<dyn AuthorStore>, path: Path<String>, author:ValidJson<AuthorPatch>) -> Result<HttpResponse, AppError> {
+    let id = ObjectId::parse_str(path.into_inner()).map_err(|_| AppError::InvalidIdError)?;
+    let patch = author.into_inner();
+    if patch.is_empty() {
+        return Err(AppError::ValidationError("no fields to update".to_string()));
+    }
 
-    if authors.as_ref().unwrap().len() as i32 == 0 {
-        return HttpResponse::NotFound().body(AppError::DataNotFoundError.to_string());
+    let update_result = db.patch_author(id, patch).await?;
+    if update_result.matched_count != 1 {
+        return Err(AppError::NotFoundError(AppMessage::UpdateFailedMsg.to_string()));
     }
+    let response = ResponseBuilder::<()>::BuildSuccessResponse(AppMessage::UpdateSuccessMsg.to_string(), None);
+    Ok(HttpResponse::Ok().json(response))
+}
 
-    let mut result:Vec<AuthorData> = Vec::new();
-    let data = authors.unwrap();
-    for i in data {
-        if !i.created_at.is_none() {
-            let temp = AuthorData::set_data(i);
-            result.push(temp);
-        }
+pub async fn delete_author(db:Data<dyn AuthorStore>, books:Data<dyn BookStore>, files:Data<dyn FileStorage>, blobs:Data<dyn BlobStore>, policy:Data<AuthorDeletePolicy>, path: Path<String>) -> Result<HttpResponse, AppError> {
+    let id = ObjectId::parse_str(path.into_inner()).map_err(|_| AppError::InvalidIdError)?;
+
+    if *policy.get_ref() == AuthorDeletePolicy::Reject && !books.get_book_by_author(id.to_hex()).await?.is_empty() {
+        return Err(AppError::ConflictError("Author still has books".to_string()));
+    }
+
+    let count = db.delete_author(id).await?;
+    if count.deleted_count != 1 {
+        return Err(AppError::NotFoundError(AppMessage::DeleteFailedMsg.to_string()));
     }
 
-    let response = ResponseBuilder::<Vec<model::author_model::AuthorData>>::BuildSuccessResponse(AppMessage::FetchSuccessMsg.to_string(), Some(result));
-    HttpResponse::Ok().json(response)
+    // books are only touched once the author is really gone
+    match policy.get_ref() {
+        AuthorDeletePolicy::Reject => {},
+        AuthorDeletePolicy::Cascade => { books.delete_books_by_author(id.to_hex()).await?; },
+        AuthorDeletePolicy::Nullify => { books.unset_book_author(id.to_hex()).await?; },
+    }
+    // the profiles go with the author, and their pictures once nothing else refers to them
+    for profile in db.delete_profile_pics(id).await?.iter().filter(|profile| !profile.profile_imgae.is_empty()) {
+        release_file(files.as_ref(), blobs.as_ref(), &profile.profile_imgae).await;
+    }
+
+    let response = ResponseBuilder::<()>::BuildSuccessResponse(AppMessage::DeleteSuccessMsg.to_string(), None);
+    Ok(HttpResponse::Ok().json(response))
 }
 
-pub async fn get_author(db:Data<AuthorRepo>, path: Path<String>) -> impl Responder {
-    let id = path.into_inner();
-
-    return match ObjectId::parse_str(id) {
-        Ok(obj) => {
-            let author_details = db.get_author(obj).await;
-            match author_details {
-                Ok(author) => {
-                    let author_data = AuthorData::set_data(author);
-                    let response = ResponseBuilder::BuildSuccessResponse("author found".to_string(), Some(author_data));
-                    HttpResponse::Ok().json(response)
-                },
-                Err(err) => {
-                    let response = ResponseBuilder::<()>::BuildFailedResponse(err.to_string());
-                    if err.to_string() == AppError::DataNotFoundError.to_string() {
-                        return HttpResponse::NotFound().json(response)
-                    }
-                    HttpResponse::InternalServerError().json(response)
-                }
-            }
-        },
-        Err(_) => {
-            let response = ResponseBuilder::<()>::BuildFailedResponse(AppError::InvalidIdError.to_string());
-            HttpResponse::BadRequest().json(response)
+// longest `author_id` field read, an ObjectId is 24 hex digits
+const AUTHOR_ID_LIMIT:usize = 64;
+
+// The text of a small form field
+async fn read_text_field(field:&mut Field, name:&str, limit:usize) -> Result<String, AppError> {
+    let mut data = Vec::new();
+    while let Some(chunk) = field.next().await {
+        let chunk = chunk.map_err(|e| AppError::ValidationError(e.to_string()))?;
+        if data.len() + chunk.len() > limit {
+            return Err(AppError::ValidationError(format!("{} is too long", name)));
         }
-    };
+        data.extend_from_slice(&chunk);
+    }
+    String::from_utf8(data).map_err(|_| AppError::ValidationError(format!("{} is not text", name)))
 }
 
-pub async fn update_author(db:Data<AuthorRepo>, path: Path<String>, author:Json<Author>) -> impl Responder {
-    let id = path.into_inner();
-
-    return match ObjectId::parse_str(id) {
-        Ok(obj_id) => {
-            let new_author = Author {
-                id:None,
-                author_name:author.author_name.to_owned(),
-                author_email:author.author_email.to_owned(),
-                contact:author.contact.to_owned(),
-                created_at:None
-            };
-
-            let result = db.update_author(obj_id, new_author).await;
-
-            match result {
-                Ok(update_result) => {
-                    if update_result.matched_count == 1 {
-                        let response = ResponseBuilder::<()>::BuildSuccessResponse(AppMessage::UpdateSuccessMsg.to_string(), None);
-                        return HttpResponse::Ok().json(response)
+// Takes an `author_id` field and a single `file` field, in any order. The picture replaces
+// the author's previous one, which is released once the new profile is stored. On every
+// error the picture of this upload is released again.
+pub async fn upload_author_profile(db:Data<dyn AuthorStore>, files:Data<dyn FileStorage>, blobs:Data<dyn BlobStore>, config:Data<AppConfig>, mut payload:Multipart) -> Result<HttpResponse, AppError> {
+    let policy = UploadPolicy::profile_image(&config.uploads);
+    let mut image = None;
+
+    let uploaded = async {
+        let mut author_id = None;
+        while let Some(item) = payload.next().await {
+            let mut field = item.map_err(|e| AppError::ValidationError(e.to_string()))?;
+            match field.content_disposition().get_name() {
+                Some("author_id") => {
+                    if author_id.is_some() {
+                        return Err(AppError::ValidationError("author_id is sent more than once".to_string()));
                     }
-                    let response = ResponseBuilder::<()>::BuildFailedResponse(AppMessage::UpdateFailedMsg.to_string());
-                    HttpResponse::BadRequest().json(response)
+                    let text = read_text_field(&mut field, "author_id", AUTHOR_ID_LIMIT).await?;
+                    author_id = Some(ObjectId::parse_str(text.trim()).map_err(|_| AppError::InvalidIdError)?);
                 },
-                Err(e) => {
-                    let response = ResponseBuilder::<()>::BuildFailedResponse(e.to_string());
-                    HttpResponse::InternalServerError().json(response)
-                }
+                Some("file") if field.content_disposition().get_filename().is_some() => {
+                    if image.is_some() {
+                        return Err(AppError::ValidationError("only one file can be uploaded".to_string()));
+                    }
+                    // the key of the stored file, not a path
+                    image = Some(store_profile_image(files.as_ref(), blobs.as_ref(), &mut field, &policy).await?);
+                },
+                _ => {},
             }
-        },
-        Err(_) => {
-            let response = ResponseBuilder::<()>::BuildFailedResponse(AppError::InvalidIdError.to_string());
-            HttpResponse::BadRequest().json(response)
         }
-    };
-}
 
-pub async fn delete_author(db:Data<AuthorRepo>, path: Path<String>) -> impl Responder {
-    let id = path.into_inner();
-
-    return match ObjectId::parse_str(id) {
-        Ok(obj_id) => {
-            let delete_result = db.delete_author(obj_id).await;
+        let author_id = author_id.ok_or(AppError::ValidationError("author_id is required".to_string()))?;
+        let stored = image.as_ref().ok_or(AppError::ValidationError("no file found in the form".to_string()))?;
+        db.get_author(author_id).await?;
+        db.upload_profile_pic(AuthorProfile {
+            id: None,
+            author_id,
+            profile_imgae: stored.key.clone(),
+            digest: stored.digest.clone(),
+            size: stored.size,
+            content_type: stored.content_type.to_string(),
+            created_at: None,
+        })
+        .await
+    }
+    .await;
 
-            match delete_result {
-                Ok(count) => {
-                    if count.deleted_count == 1 {
-                        let response = ResponseBuilder::<()>::BuildSuccessResponse(AppMessage::DeleteSuccessMsg.to_string(), None);
-                        HttpResponse::Ok().json(response);
-                    }
-                    let response = ResponseBuilder::<()>::BuildFailedResponse(AppMessage::DeleteFailedMsg.to_string());
-                    HttpResponse::BadRequest().json(response)
-                },
-                Err(e) => {
-                    let response = ResponseBuilder::<()>::BuildFailedResponse(e.to_string());
-                    HttpResponse::InternalServerError().json(response)
-                }
+    let replaced = match uploaded {
+        Ok(replaced) => replaced,
+        Err(err) => {
+            // without its profile this upload no longer refers to the file
+            if let Some(image) = image {
+                release_file(files.as_ref(), blobs.as_ref(), &image.key).await;
             }
+            return Err(err);
         },
-        Err(_) => {
-            let response = ResponseBuilder::<()>::BuildFailedResponse(AppError::InvalidIdError.to_string());
-            HttpResponse::BadRequest().json(response)
-        }
     };
+    for profile in replaced.iter().filter(|profile| !profile.profile_imgae.is_empty()) {
+        release_file(files.as_ref(), blobs.as_ref(), &profile.profile_imgae).await;
+    }
+    let response = ResponseBuilder::<()>::BuildSuccessResponse("Author Profile has been uploaded".to_string(), None);
+    Ok(HttpResponse::Ok().json(response))
 }
 
-pub async fn upload_author_profile(db:Data<AuthorRepo>, mut payload:Multipart) -> impl Responder {
-    let mut author_profile = AuthorProfile {
-        id: None,
-        author_id: ObjectId::new(),
-        profile_imgae: "".to_string(),
-        created_at: None,
-    };
+pub async fn get_author_profile(db:Data<dyn AuthorStore>, config:Data<AppConfig>, path:Path<String>) -> Result<HttpResponse, AppError> {
+    let id = ObjectId::parse_str(path.into_inner()).map_err(|_| AppError::InvalidIdError)?;
 
-    while let Some(item) = payload.next().await {
-        let mut field = item.unwrap();
+    let profile_data = db.get_profile_pic(id).await?;
+    let author_profile_data = AuthorProfileData::set_profile_data(profile_data, &config.server.public_url);
+    let response = ResponseBuilder::BuildSuccessResponse("Author Profile found".to_string(), Some(author_profile_data));
+    Ok(HttpResponse::Ok().json(response))
+}
 
-        if field.content_disposition().get_name() == Some("author_id") {
-            let mut text = String::new();
-            if let Some(content_data) = field.next().await {
-                let data = content_data.unwrap();
-                text.push_str(&String::from_utf8_lossy(&data));
-            }
-            
-            // check the Auther ID
-            match ObjectId::parse_str(text) {
-                Ok(obj_id) => author_profile.author_id = obj_id,
-                Err(_) => {
-                    let response = ResponseBuilder::<()>::BuildFailedResponse("Invalid id provided".to_string());
-                    return HttpResponse::BadRequest().json(response);
-                },
-            }
-            
-        }
+// Removes every profile of the author. A picture is deleted with its last profile, the same
+// content may still be referred to by others.
+pub async fn delete_author_profile(db:Data<dyn AuthorStore>, files:Data<dyn FileStorage>, blobs:Data<dyn BlobStore>, path:Path<String>) -> Result<HttpResponse, AppError> {
+    let id = ObjectId::parse_str(path.into_inner()).map_err(|_| AppError::InvalidIdError)?;
 
-        // check for file
-        if field.content_disposition().get_name() == Some("file") {
-            let file_name = field.content_disposition().get_filename();
-
-            if let Some(file_val) = file_name {
-                let file_path = format!("./tmp/{}", file_val);
-                author_profile.profile_imgae = file_path.clone().chars().skip(1).collect();
-
-                match std::fs::File::create(file_path) {
-                    Ok(mut file) => {
-                        while let Some(chunk) = field.next().await {
-                            let data = chunk.unwrap();
-                            if let Some(err) = file.write_all(&data).err(){
-                                let response = ResponseBuilder::<()>::BuildFailedResponse(err.to_string());
-                                return HttpResponse::InternalServerError().json(response);
-                            }
-                            
-                        }
-                    },
-                    Err(e) => {
-                        let response = ResponseBuilder::<()>::BuildFailedResponse(e.to_string());
-                        return HttpResponse::UnprocessableEntity().json(response);
-                    },
-                }
-            }
-        }
-        
+    let profiles = db.delete_profile_pics(id).await?;
+    if profiles.is_empty() {
+        return Err(AppError::NotFoundError("Author profile not found".to_string()));
     }
-    let res = db.upload_profile_pic(author_profile).await;
-    match res {
-        Ok(_) => {
-            let response = ResponseBuilder::<()>::BuildSuccessResponse("Author Profile has been uploaded".to_string(), None);
-            HttpResponse::Ok().json(response)
-        },
-        Err(e) => {
-            let response = ResponseBuilder::<()>::BuildFailedResponse(e.to_string());
-            HttpResponse::InternalServerError().json(response)
-        },
+    for profile in profiles.iter().filter(|profile| !profile.profile_imgae.is_empty()) {
+        release_file(files.as_ref(), blobs.as_ref(), &profile.profile_imgae).await;
     }
+
+    let response = ResponseBuilder::<()>::BuildSuccessResponse(AppMessage::DeleteSuccessMsg.to_string(), None);
+    Ok(HttpResponse::Ok().json(response))
 }
 
-pub async fn get_author_profile(db:Data<AuthorRepo>, path:Path<String>) -> impl Responder {
-    let id = path.into_inner();
-
-    return match ObjectId::parse_str(id) {
-        Ok(obj_id)=>{
-            let result = db.get_profile_pic(obj_id).await;
-            match result {
-                Ok(profile_data) => {
-                    let author_profile_data = AuthorProfileData::set_profile_data(profile_data);
-                    let response = ResponseBuilder::BuildSuccessResponse("Author Profile not found".to_string(), Some(author_profile_data));
-                    HttpResponse::Ok().json(response)
-                },
-                Err(e) => {
-                    let response = ResponseBuilder::<()>::BuildFailedResponse(e.to_string());
-                    HttpResponse::BadRequest().json(response)
-                },
-            }
-        },
-        Err(_) => {
-            let response = ResponseBuilder::<()>::BuildFailedResponse("Invalid Id passed".to_string());
-            HttpResponse::BadRequest().json(response)
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{http::{header, StatusCode}, test, App};
+    use serde_json::Value;
+
+    use crate::{repository::memory_repo::{MemoryAuthorRepo, MemoryBookRepo}, routers::author_router::author_router};
+    use super::super::testing::{multipart, png, Part, Uploads};
+    use super::*;
+
+    async fn author(db:&Data<dyn AuthorStore>) -> ObjectId {
+        let author = Author {
+            id: None,
+            author_name: "Ann".to_string(),
+            contact: String::new(),
+            author_email: "ann@example.com".to_string(),
+            created_at: None,
+            created_by: None,
+        };
+        db.create_author(author).await.unwrap().inserted_id
+    }
+
+    // sends `req` to the author routes and returns the status and the body
+    async fn call(db:&Data<dyn AuthorStore>, uploads:&Uploads, req:test::TestRequest) -> (StatusCode, Value) {
+        let books:Data<dyn BookStore> = Data::from(Arc::new(MemoryBookRepo::init()) as Arc<dyn BookStore>);
+        let app = test::init_service(
+            App::new()
+                .app_data(db.clone())
+                .app_data(books)
+                .app_data(uploads.files.clone())
+                .app_data(uploads.blobs.clone())
+                .app_data(uploads.config.clone())
+                .service(author_router()),
+        )
+        .await;
+        let res = test::call_service(&app, req.to_request()).await;
+        let status = res.status();
+        let body = test::read_body(res).await;
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    // posts the form to the profile upload and returns the status
+    async fn upload(db:&Data<dyn AuthorStore>, uploads:&Uploads, parts:&[Part<'_>]) -> StatusCode {
+        let (content_type, body) = multipart(parts);
+        let req = test::TestRequest::post()
+            .uri("/author/author/profile")
+            .insert_header((header::CONTENT_TYPE, content_type))
+            .set_payload(body);
+        call(db, uploads, req).await.0
+    }
+
+    // the key of the picture served as the author's profile
+    async fn profile_key(db:&Data<dyn AuthorStore>, uploads:&Uploads, id:ObjectId) -> String {
+        let req = test::TestRequest::get().uri(&format!("/author/auther_profile/{}", id));
+        let (status, body) = call(db, uploads, req).await;
+        assert_eq!(status, StatusCode::OK);
+        // the picture is served from a URL ending in its key
+        body["data"]["profile_image"].as_str().unwrap().rsplit('/').next().unwrap().to_string()
+    }
+
+    fn authors() -> Data<dyn AuthorStore> {
+        Data::from(Arc::new(MemoryAuthorRepo::init()) as Arc<dyn AuthorStore>)
+    }
+
+    #[actix_web::test]
+    async fn stores_the_picture_of_an_existing_author() {
+        let (db, uploads) = (authors(), Uploads::new());
+        let id = author(&db).await;
+        let picture = png(16, 16, 1);
+
+        let status = upload(&db, &uploads, &[("author_id", None, id.to_hex().as_bytes()), ("file", Some("me.png"), &picture)]).await;
+        assert_eq!(status, StatusCode::OK);
+        let profile = db.get_profile_pic(id).await.unwrap();
+        assert!(uploads.has(&profile.profile_imgae).await);
+        assert_eq!(uploads.staged(), 0);
+    }
+
+    #[actix_web::test]
+    async fn stores_nothing_for_a_missing_or_unknown_author() {
+        let (db, uploads) = (authors(), Uploads::new());
+        let picture = png(16, 16, 1);
+        let unknown = ObjectId::new().to_hex();
+
+        let forms:[(&[Part], StatusCode); 4] = [
+            (&[("file", Some("me.png"), &picture)], StatusCode::BAD_REQUEST),
+            (&[("author_id", None, b"not an id"), ("file", Some("me.png"), &picture)], StatusCode::BAD_REQUEST),
+            (&[("author_id", None, unknown.as_bytes()), ("file", Some("me.png"), &picture)], StatusCode::NOT_FOUND),
+            (&[("file", Some("me.png"), &picture), ("author_id", None, unknown.as_bytes())], StatusCode::NOT_FOUND),
+        ];
+        for (parts, expected) in forms {
+            assert_eq!(upload(&db, &uploads, parts).await, expected);
         }
-    };
-}
+        assert_eq!(uploads.stored(), 0);
+    }
 
-pub async fn get_files(path:Path<(String, String)>) -> impl Responder {
-    let (directory, file_name) = path.into_inner();
-    let file_path = format!("./{}/{}", directory, file_name);
+    #[actix_web::test]
+    async fn releases_the_picture_when_the_form_is_refused_later() {
+        let (db, uploads) = (authors(), Uploads::new());
+        let id = author(&db).await.to_hex();
+        let (first, second) = (png(16, 16, 1), png(16, 16, 2));
+
+        let forms:[&[Part]; 2] = [
+            &[("author_id", None, id.as_bytes()), ("file", Some("a.png"), &first), ("file", Some("b.png"), &second)],
+            &[("author_id", None, id.as_bytes()), ("file", Some("a.png"), &first), ("author_id", None, id.as_bytes())],
+        ];
+        for parts in forms {
+            assert_eq!(upload(&db, &uploads, parts).await, StatusCode::BAD_REQUEST);
+        }
+        assert_eq!(uploads.stored(), 0);
+        assert!(db.get_profile_pic(ObjectId::parse_str(&id).unwrap()).await.is_err());
+    }
 
-    let mut file = match File::open(file_path) {
-        Ok(file) => file,
-        Err(e) => return HttpResponse::NotFound().body(e.to_string()),
-    };
+    #[actix_web::test]
+    async fn takes_the_fields_in_any_order() {
+        let (db, uploads) = (authors(), Uploads::new());
+        let id = author(&db).await;
+        let picture = png(16, 16, 1);
 
-    let mut buffer = Vec::new();
-    if let Err(_) = file.read_to_end(&mut buffer) {
-        return HttpResponse::InternalServerError().finish();
+        let status = upload(&db, &uploads, &[("file", Some("me.png"), &picture), ("author_id", None, id.to_hex().as_bytes())]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(uploads.has(&profile_key(&db, &uploads, id).await).await);
     }
 
-    HttpResponse::Ok()
-        .content_type("image/jpeg")
-        .body(buffer)
-}
\ No newline at end of file
+    #[actix_web::test]
+    async fn a_new_picture_replaces_the_previous_one() {
+        let (db, uploads) = (authors(), Uploads::new());
+        let id = author(&db).await;
+        let (first, second) = (png(16, 16, 1), png(16, 16, 2));
+
+        assert_eq!(upload(&db, &uploads, &[("author_id", None, id.to_hex().as_bytes()), ("file", Some("a.png"), &first)]).await, StatusCode::OK);
+        let first_key = profile_key(&db, &uploads, id).await;
+        // another reference to the first picture, so it outlives the profile
+        assert_eq!(uploads.blobs.acquire_blob(&first_key).await.unwrap(), 2);
+
+        assert_eq!(upload(&db, &uploads, &[("author_id", None, id.to_hex().as_bytes()), ("file", Some("b.png"), &second)]).await, StatusCode::OK);
+        let second_key = profile_key(&db, &uploads, id).await;
+        assert_ne!(second_key, first_key);
+        assert!(uploads.has(&second_key).await);
+        // only the other reference is left
+        assert_eq!(uploads.blobs.release_blob(&first_key).await.unwrap(), 0);
+
+        // the same picture again drops the reference of the profile it replaces
+        assert_eq!(upload(&db, &uploads, &[("author_id", None, id.to_hex().as_bytes()), ("file", Some("b.png"), &second)]).await, StatusCode::OK);
+        assert_eq!(profile_key(&db, &uploads, id).await, second_key);
+        assert_eq!(uploads.blobs.release_blob(&second_key).await.unwrap(), 0);
+    }
+}
@@ -1,195 +1,211 @@
-use actix_web::{ delete, get, post, put, web::{Data, Json, Path}, HttpResponse};
+use actix_web::{ delete, get, patch, post, put, web::{Data, Path}, HttpRequest, HttpResponse};
 use mongodb::bson::oid::ObjectId;
-use crate::{model, repository::book_repo::BookRepo};
-use crate::model::book_model::Book;
-use crate::utils::response::ResponseBuilder;
+use crate::{middleware::auth_user::AuthUser, model, repository::store::{AuthorStore, BookStore}};
+use crate::model::book_model::{Book, BookPatch};
+use crate::utils::{pagination::ListQuery, response::ResponseBuilder, validation::ValidJson};
+
+use super::app_errors::AppError;
+
+// `book_author` has to hold the id of an existing author
+async fn check_author(authors:&dyn AuthorStore, author_id:&str) -> Result<(), AppError> {
+    let not_found = || AppError::UnprocessableError("Referenced author does not exist".to_string());
+
+    let id = ObjectId::parse_str(author_id).map_err(|_| not_found())?;
+    match authors.get_author(id).await {
+        Ok(_) => Ok(()),
+        Err(AppError::NotFoundError(_)) => Err(not_found()),
+        Err(err) => Err(err),
+    }
+}
 
 #[post("/book")]
-pub async fn create_book(db:Data<BookRepo>, book:Json<Book>) -> HttpResponse {
+pub async fn create_book(db:Data<dyn BookStore>, authors:Data<dyn AuthorStore>, auth:AuthUser, book:ValidJson<Book>) -> Result<HttpResponse, AppError> {
+    check_author(authors.as_ref(), &book.book_author).await?;
+
     let data = Book {
         id:None,
         book_name:book.book_name.to_owned(),
         book_author:book.book_author.to_owned(),
         total_page:book.total_page.to_owned(),
+        created_by:Some(auth.id),
     };
 
-    let book_detail = db.create_book(data).await;
-    match book_detail {
-        Ok(book) => {
-            let response = ResponseBuilder::BuildSuccessResponse(String::from("Book has been added"), Some(book));
-            HttpResponse::Ok().json(response)
-        },
-        Err(err) => {
-            let response = ResponseBuilder::<()>::BuildFailedResponse(String::from(err.to_string()));
-            HttpResponse::InternalServerError().json(response)
-        }
-    } 
+    let book = db.create_book(data).await?;
+    let response = ResponseBuilder::BuildSuccessResponse(String::from("Book has been added"), Some(book));
+    Ok(HttpResponse::Ok().json(response))
 }
 
 #[get("/book/{id}")]
-pub async fn get_book(db:Data<BookRepo>, path: Path<String>) -> HttpResponse {
-    let id = path.into_inner();
-    return match ObjectId::parse_str(id) {
-        Ok(obj) => {
-            let book_details = db.get_book(obj).await;
-            match book_details {
-                Ok(book) => {
-                    let response = ResponseBuilder::BuildSuccessResponse(String::from("Book fetched!"), Some(book));
-                    HttpResponse::Ok().json(response)
-                },
-                Err(err) => {
-                    let response = ResponseBuilder::<()>::BuildFailedResponse(err.to_string());
-                    HttpResponse::InternalServerError().json(response)
-                }
-            }
-        },
-        Err(_) => {
-            let response = ResponseBuilder::<()>::BuildFailedResponse(String::from("invalid id found"));
-            HttpResponse::BadRequest().json(response)
-        }
-    };
+pub async fn get_book(db:Data<dyn BookStore>, path: Path<String>) -> Result<HttpResponse, AppError> {
+    let id = ObjectId::parse_str(path.into_inner()).map_err(|_| AppError::InvalidIdError)?;
+
+    let book = db.get_book(id).await?;
+    let response = ResponseBuilder::BuildSuccessResponse(String::from("Book fetched!"), Some(book));
+    Ok(HttpResponse::Ok().json(response))
 }
 
 #[get("/books")]
-pub async fn get_books(db:Data<BookRepo>) -> HttpResponse {
-    let books = db.get_books().await;
+pub async fn get_books(db:Data<dyn BookStore>, req:HttpRequest) -> Result<HttpResponse, AppError> {
+    let query = ListQuery::parse(req.query_string(), Book::LIST_FIELDS).map_err(AppError::ValidationError)?;
+
+    // an empty page is an answer too, e.g. past the last book matching the filters
+    let books = db.get_books(&query).await?;
+    let pagination = query.pagination(req.path(), &books, books.items.last().and_then(|book| book.id));
+    let response = ResponseBuilder::<Vec<model::book_model::Book>>::BuildPageResponse(String::from("Books fetched !"), Some(books.items), pagination);
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[put("/book/{id}")]
+pub async fn update_book(db:Data<dyn BookStore>, authors:Data<dyn AuthorStore>, path: Path<String>, book:ValidJson<Book>) -> Result<HttpResponse, AppError> {
+    let id = ObjectId::parse_str(path.into_inner()).map_err(|_| AppError::InvalidIdError)?;
+    check_author(authors.as_ref(), &book.book_author).await?;
 
-    if books.as_ref().unwrap().len() as i32 == 0 {
-        return HttpResponse::NotFound().body("Books not available");
+    let new_book = Book {
+        id:None,
+        book_name:book.book_name.to_owned(),
+        book_author:book.book_author.to_owned(),
+        total_page:book.total_page.to_owned(),
+        created_by:None,
+    };
+
+    let update = db.update_book(id, new_book).await?;
+    if update.matched_count != 1 {
+        return Err(AppError::NotFoundError("no book found with id".to_string()));
     }
-    let result = books.unwrap();
-    let response = ResponseBuilder::<Vec<model::book_model::Book>>::BuildSuccessResponse(String::from("Books fetched !"), Some(result));
-    HttpResponse::Ok().json(response)
+
+    let book = db.get_book(id).await?;
+    let response = ResponseBuilder::BuildSuccessResponse(String::from("Book has been updated !"), Some(book));
+    Ok(HttpResponse::Ok().json(response))
 }
 
-#[put("/book/{id}")]
-pub async fn update_book(db:Data<BookRepo>, path: Path<String>, book:Json<Book>) -> HttpResponse {
-    let id = path.into_inner();
-
-    return match ObjectId::parse_str(id) {
-        Ok(obj)=>{
-
-            let new_book = Book {
-                id:None,
-                book_name:book.book_name.to_owned(),
-                book_author:book.book_author.to_owned(),
-                total_page:book.total_page.to_owned(),
-            };
-            let update_result = db.update_book(obj, new_book).await;
-
-            match update_result {
-                Ok(update) => {
-                    if update.matched_count == 1 {
-                        let updated_book_info = db.get_book(obj).await;
-                        match updated_book_info {
-                            Ok(book) => {
-                                let response = ResponseBuilder::BuildSuccessResponse(String::from("Book has been updated !"), Some(book));
-                                return HttpResponse::Ok().json(response)
-                            },
-                            Err(err) => {
-                                let response = ResponseBuilder::<()>::BuildFailedResponse(err.to_string()); 
-                                return HttpResponse::InternalServerError().json(response)
-                            }
-                        }
-                    }
-                    let response = ResponseBuilder::<()>::BuildFailedResponse(String::from("no book found with id"));
-                    HttpResponse::NotFound().json(response)
-
-                },
-                Err(err) => {
-                    let response = ResponseBuilder::<()>::BuildFailedResponse(err.to_string());
-                    HttpResponse::InternalServerError().json(response)
-                }
-            }
-
-        },
-        Err(_) => {
-            let response = ResponseBuilder::<()>::BuildFailedResponse(String::from("Invalid id!"));
-            HttpResponse::BadRequest().json(response)
-        }
-        
-    };    
+#[patch("/book/{id}")]
+pub async fn patch_book(db:Data<dyn BookStore>, authors:Data<dyn AuthorStore>, path: Path<String>, book:ValidJson<BookPatch>) -> Result<HttpResponse, AppError> {
+    let id = ObjectId::parse_str(path.into_inner()).map_err(|_| AppError::InvalidIdError)?;
+    let patch = book.into_inner();
+    if patch.is_empty() {
+        return Err(AppError::ValidationError("no fields to update".to_string()));
+    }
+    if let Some(author_id) = &patch.book_author {
+        check_author(authors.as_ref(), author_id).await?;
+    }
+
+    let update = db.patch_book(id, patch).await?;
+    if update.matched_count != 1 {
+        return Err(AppError::NotFoundError("no book found with id".to_string()));
+    }
+
+    let book = db.get_book(id).await?;
+    let response = ResponseBuilder::BuildSuccessResponse(String::from("Book has been updated !"), Some(book));
+    Ok(HttpResponse::Ok().json(response))
 }
 
 #[delete("/book/{id}")]
-pub async fn delete_book(db:Data<BookRepo>, path: Path<String>) -> HttpResponse {
-    let id = path.into_inner();
-
-    return match ObjectId::parse_str(id) {
-        Ok(obj) => {
-            let delete_book = db.delete_book(obj).await;
-            match delete_book {
-                Ok(result) => {
-                    if result.deleted_count == 1 {
-                        let response = ResponseBuilder::<()>::BuildSuccessResponse(String::from("Book has been deleted"), None);
-                        return HttpResponse::Ok().json(response)
-                    }
-                    let response = ResponseBuilder::<()>::BuildFailedResponse(String::from("failed to delete book"));
-                    HttpResponse::BadRequest().json(response) 
-                },
-                Err(err) => {
-                    let response = ResponseBuilder::<()>::BuildFailedResponse(err.to_string());
-                    HttpResponse::InternalServerError().json(response)
-                }
-            }
-        },
-        Err(_) => {
-            let response = ResponseBuilder::<()>::BuildFailedResponse(String::from("invalid id"));
-            HttpResponse::BadRequest().json(response)
-        }
-    };
+pub async fn delete_book(db:Data<dyn BookStore>, path: Path<String>) -> Result<HttpResponse, AppError> {
+    let id = ObjectId::parse_str(path.into_inner()).map_err(|_| AppError::InvalidIdError)?;
+
+    let result = db.delete_book(id).await?;
+    if result.deleted_count != 1 {
+        return Err(AppError::NotFoundError("no book found with id".to_string()));
+    }
+    let response = ResponseBuilder::<()>::BuildSuccessResponse(String::from("Book has been deleted"), None);
+    Ok(HttpResponse::Ok().json(response))
 }
 
 #[get("/books/author/{id}")]
-pub async fn get_author_books(db:Data<BookRepo>, path: Path<String>) -> HttpResponse {
+pub async fn get_author_books(db:Data<dyn BookStore>, path: Path<String>) -> Result<HttpResponse, AppError> {
     let author = path.into_inner();
 
     if author.trim().is_empty() {
-        let response = ResponseBuilder::<()>::BuildFailedResponse("author id required".to_string());
-        return HttpResponse::BadRequest().json(response);
+        return Err(AppError::ValidationError("author id required".to_string()));
     }
 
-    let result = db.get_book_by_author(author).await;
-
-    match result {
-        Ok(books) => {
-            if books.len() as i32 == 0 {
-                let response = ResponseBuilder::<()>::BuildFailedResponse("Books Not Found".to_string());
-                return HttpResponse::NotFound().json(response);
-            }
-            let response = ResponseBuilder::BuildSuccessResponse("Book fetched!".to_string(), Some(books));
-            HttpResponse::Ok().json(response)
-        },
-        Err(err) => {
-            let response = ResponseBuilder::<()>::BuildFailedResponse(err.to_string());
-            HttpResponse::InternalServerError().json(response)
-        }
+    let books = db.get_book_by_author(author).await?;
+    if books.is_empty() {
+        return Err(AppError::NotFoundError("Books Not Found".to_string()));
     }
+    let response = ResponseBuilder::BuildSuccessResponse("Book fetched!".to_string(), Some(books));
+    Ok(HttpResponse::Ok().json(response))
 }
 
 #[delete("books/author/{id}")]
-pub async fn delete_books_by_author(db:Data<BookRepo>, path: Path<String>) -> HttpResponse {
+pub async fn delete_books_by_author(db:Data<dyn BookStore>, path: Path<String>) -> Result<HttpResponse, AppError> {
     let author = path.into_inner();
     if author.trim().is_empty() {
-        let response = ResponseBuilder::<()>::BuildFailedResponse("author id required".to_string());
-        return HttpResponse::BadRequest().json(response);
+        return Err(AppError::ValidationError("author id required".to_string()));
     }
 
-    let result = db.delete_books_by_author(author).await;
-
-    match result {
-        Ok(count) => {
-            if count.deleted_count == 1 {
-                let response = ResponseBuilder::<()>::BuildSuccessResponse("books has been deleted".to_string(), None);
-                return HttpResponse::Ok().json(response)
-            }
-
-            let response = ResponseBuilder::<()>::BuildFailedResponse("failed to delete books".to_string());
-            HttpResponse::InternalServerError().json(response)
-        },
-        Err(err) => {
-            let response = ResponseBuilder::<()>::BuildFailedResponse(err.to_string());
-            HttpResponse::BadRequest().json(response)
+    let count = db.delete_books_by_author(author).await?;
+    if count.deleted_count == 0 {
+        return Err(AppError::NotFoundError("Books Not Found".to_string()));
+    }
+    let response = ResponseBuilder::<()>::BuildSuccessResponse("books has been deleted".to_string(), None);
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{http::StatusCode, test, App};
+    use serde_json::Value;
+
+    use crate::{repository::memory_repo::MemoryBookRepo, routers::book_router::book_router};
+    use super::*;
+
+    async fn books() -> Data<dyn BookStore> {
+        let db:Data<dyn BookStore> = Data::from(Arc::new(MemoryBookRepo::init()) as Arc<dyn BookStore>);
+        for (name, pages) in [("Dune", 600), ("Emma", 400), ("Ubik", 200), ("Solaris", 300)] {
+            let book = Book { id: None, book_name: name.to_string(), book_author: "someone".to_string(), total_page: pages, created_by: None };
+            db.create_book(book).await.unwrap();
         }
+        db
     }
-}
\ No newline at end of file
+
+    // gets the book list with `query` and returns the status and the body
+    async fn list(db:&Data<dyn BookStore>, query:&str) -> (StatusCode, Value) {
+        let app = test::init_service(App::new().app_data(db.clone()).service(book_router())).await;
+        let req = test::TestRequest::get().uri(&format!("/book/books?{}", query)).to_request();
+        let res = test::call_service(&app, req).await;
+        (res.status(), test::read_body_json(res).await)
+    }
+
+    #[actix_web::test]
+    async fn lists_filtered_and_sorted_books_page_by_page() {
+        let db = books().await;
+
+        let (status, body) = list(&db, "total_page%3E=300&sort=-total_page&per_page=2").await;
+        assert_eq!(status, StatusCode::OK);
+        let names:Vec<&str> = body["data"].as_array().unwrap().iter().map(|book| book["book_name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["Dune", "Emma"]);
+        assert_eq!(body["pagination"]["total"], 3);
+        let next = body["pagination"]["next"].as_str().unwrap().to_string();
+
+        let (status, body) = list(&db, next.split_once('?').unwrap().1).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"][0]["book_name"], "Solaris");
+        assert!(body["pagination"]["next"].is_null());
+    }
+
+    #[actix_web::test]
+    async fn refuses_filters_it_can_not_apply() {
+        let db = books().await;
+
+        for query in ["created_by=ann", "total_page%3Emany", "sort=created_by", "per_page=1000"] {
+            assert_eq!(list(&db, query).await.0, StatusCode::BAD_REQUEST, "{}", query);
+        }
+    }
+
+    #[actix_web::test]
+    async fn answers_an_empty_page_with_an_empty_list() {
+        let db = books().await;
+
+        for query in ["book_name=Missing", "per_page=2&page=3"] {
+            let (status, body) = list(&db, query).await;
+            assert_eq!(status, StatusCode::OK, "{}", query);
+            assert_eq!(body["data"], serde_json::json!([]));
+            assert!(body["pagination"]["next"].is_null());
+        }
+        assert_eq!(list(&db, "book_name=Missing").await.1["pagination"]["total"], 0);
+        assert_eq!(list(&db, "per_page=2&page=3").await.1["pagination"]["total"], 4);
+    }
+}
@@ -0,0 +1,495 @@
+use std::{collections::HashMap, future::Future, io, path::{Path as FsPath, PathBuf}, sync::{Arc, Mutex, PoisonError}, time::{SystemTime, UNIX_EPOCH}};
+
+use actix_multipart::{Field, Multipart};
+use actix_web::{http::{header::{self, ByteRangeSpec, ContentRangeSpec, EntityTag, Header, HttpDate}, Method, StatusCode}, web::{self, Bytes, Data, Path, Query}, HttpRequest, HttpResponse};
+use futures::{lock::Mutex as AsyncMutex, stream, StreamExt};
+use lazy_static::lazy_static;
+use ring::digest;
+use tokio::io::AsyncWriteExt;
+
+use crate::{config::app_config::{AppConfig, UploadConfig}, model::file_model::{FileQuery, ImageSize, UploadedFile}, utils::password::random_token};
+use crate::repository::{file_storage::{content_key, upload_content_type, variant_key, PROFILE_IMAGE_TYPES, SNIFF_LEN, UPLOAD_TYPES}, store::{BlobStore, FileInfo, FileStorage}};
+use crate::utils::{image::{process_profile_image, ProcessedImage}, response::ResponseBuilder};
+
+use super::app_errors::AppError;
+
+// What an upload endpoint accepts
+pub struct UploadPolicy {
+    max_size:u64,
+    types:&'static [&'static str],
+    staging_dir:PathBuf,
+}
+
+impl UploadPolicy {
+    pub fn files(config:&UploadConfig) -> Self {
+        UploadPolicy { max_size: config.max_file_size, types: &UPLOAD_TYPES, staging_dir: config.staging_dir() }
+    }
+
+    pub fn profile_image(config:&UploadConfig) -> Self {
+        UploadPolicy { max_size: config.max_profile_image_size, types: &PROFILE_IMAGE_TYPES, staging_dir: config.staging_dir() }
+    }
+
+    // a fresh file in the staging directory
+    fn staged_path(&self) -> PathBuf {
+        self.staging_dir.join(format!("{}.partial", &random_token()[..32]))
+    }
+
+    fn content_type(&self, file_name:Option<&str>, head:&[u8]) -> Result<&'static str, AppError> {
+        let content_type = upload_content_type(file_name, head)?;
+        if !self.types.contains(&content_type) {
+            return Err(AppError::UnsupportedMediaTypeError(format!(
+                "{} is not accepted here, expected one of {}",
+                content_type,
+                self.types.join(", ")
+            )));
+        }
+        Ok(content_type)
+    }
+}
+
+fn staging_error(e:io::Error) -> AppError {
+    AppError::StorageError(format!("could not stage the upload: {}", e))
+}
+
+// An upload written to the staging directory
+struct Staged {
+    content_type:&'static str,
+    // hex SHA-256 of the content
+    digest:String,
+}
+
+fn to_hex(bytes:&[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Writes the file of a multipart field to `staged` as it arrives, hashing it on the way. Its
+// size and type are checked as well, so an oversized or unacceptable file is refused without
+// being read to the end.
+async fn stage_field(field:&mut Field, staged:&FsPath, policy:&UploadPolicy) -> Result<Staged, AppError> {
+    let file_name = field.content_disposition().get_filename().map(str::to_string);
+    let mut file = tokio::fs::File::create(staged).await.map_err(staging_error)?;
+    let mut hasher = digest::Context::new(&digest::SHA256);
+    let mut size = 0;
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    let mut content_type = None;
+    while let Some(chunk) = field.next().await {
+        let chunk = chunk.map_err(|e| AppError::ValidationError(e.to_string()))?;
+        size += chunk.len() as u64;
+        if size > policy.max_size {
+            return Err(AppError::PayloadTooLargeError(format!("the file is larger than {} bytes", policy.max_size)));
+        }
+        if content_type.is_none() {
+            head.extend_from_slice(&chunk[..chunk.len().min(SNIFF_LEN - head.len())]);
+            if head.len() == SNIFF_LEN {
+                content_type = Some(policy.content_type(file_name.as_deref(), &head)?);
+            }
+        }
+        hasher.update(&chunk);
+        file.write_all(&chunk).await.map_err(staging_error)?;
+    }
+    if size == 0 {
+        return Err(AppError::ValidationError("the file is empty".to_string()));
+    }
+    let content_type = match content_type {
+        Some(content_type) => content_type,
+        None => policy.content_type(file_name.as_deref(), &head)?,
+    };
+    // on disk before the storage takes it over
+    file.sync_all().await.map_err(staging_error)?;
+    Ok(Staged { content_type, digest: to_hex(hasher.finish().as_ref()) })
+}
+
+// already gone when the storage moved it
+async fn remove_staged(staged:&FsPath) {
+    if let Err(e) = tokio::fs::remove_file(staged).await {
+        if e.kind() != io::ErrorKind::NotFound {
+            log::warn!("could not remove staged upload {}: {}", staged.display(), e);
+        }
+    }
+}
+
+lazy_static! {
+    // one lock per stored key that is being referenced or released right now
+    static ref KEY_LOCKS:Mutex<HashMap<String, Arc<AsyncMutex<()>>>> = Mutex::new(HashMap::new());
+}
+
+// Runs `f` while no other upload or release of `key` runs in this process. Otherwise a
+// release could delete a file right after an upload of the same content found it stored.
+async fn with_key_locked<T>(key:&str, f:impl Future<Output = T>) -> T {
+    let lock = KEY_LOCKS.lock().unwrap_or_else(PoisonError::into_inner).entry(key.to_string()).or_default().clone();
+    let result = {
+        let _guard = lock.lock().await;
+        f.await
+    };
+    let mut locks = KEY_LOCKS.lock().unwrap_or_else(PoisonError::into_inner);
+    // nobody but the map and this call holds it, nobody waits for it
+    if Arc::strong_count(&lock) == 2 {
+        locks.remove(key);
+    }
+    result
+}
+
+// Adds a reference to `key` and runs `put` unless the same content is stored already
+async fn store_once(
+    files:&dyn FileStorage,
+    blobs:&dyn BlobStore,
+    key:&str,
+    put:impl Future<Output = Result<(), AppError>>,
+) -> Result<(), AppError> {
+    with_key_locked(key, async {
+        blobs.acquire_blob(key).await?;
+        let stored = match files.stat(key).await {
+            Ok(_) => Ok(()),
+            Err(AppError::NotFoundError(_)) => put.await,
+            Err(e) => Err(e),
+        };
+        if stored.is_err() {
+            release_locked(files, blobs, key).await;
+        }
+        stored
+    })
+    .await
+}
+
+// Stores the file of a multipart field under the key of its content and returns the key
+pub async fn store_field(files:&dyn FileStorage, blobs:&dyn BlobStore, field:&mut Field, policy:&UploadPolicy) -> Result<String, AppError> {
+    let staged = policy.staged_path();
+    let stored = match stage_field(field, &staged, policy).await {
+        Ok(upload) => {
+            let key = content_key(&upload.digest, upload.content_type);
+            store_once(files, blobs, &key, files.put_file(&key, &staged, upload.content_type)).await.map(|_| key)
+        },
+        Err(e) => Err(e),
+    };
+    remove_staged(&staged).await;
+    stored
+}
+
+// A picture as `store_profile_image` stored it
+pub struct StoredImage {
+    pub key:String,
+    pub digest:String,
+    pub size:u64,
+    pub content_type:&'static str,
+}
+
+// Stores the picture of a multipart field cleaned up, with its smaller renditions under
+// `variant_key`s. The key is made from the digest of the cleaned up picture, not of the
+// upload, so it matches what is served.
+pub async fn store_profile_image(files:&dyn FileStorage, blobs:&dyn BlobStore, field:&mut Field, policy:&UploadPolicy) -> Result<StoredImage, AppError> {
+    let staged = policy.staged_path();
+    let processed = match stage_field(field, &staged, policy).await {
+        Ok(upload) => process_staged(&staged, upload.content_type).await,
+        Err(e) => Err(e),
+    };
+    remove_staged(&staged).await;
+    let image = processed?;
+
+    let digest = to_hex(digest::digest(&digest::SHA256, &image.original).as_ref());
+    let stored = StoredImage {
+        key: content_key(&digest, image.content_type),
+        digest,
+        size: image.original.len() as u64,
+        content_type: image.content_type,
+    };
+    store_once(files, blobs, &stored.key, put_image(files, &stored.key, image)).await?;
+    Ok(stored)
+}
+
+// decoding and encoding keep a thread busy, they don't run on the workers
+async fn process_staged(staged:&FsPath, content_type:&'static str) -> Result<ProcessedImage, AppError> {
+    let data = tokio::fs::read(staged).await.map_err(staging_error)?;
+    web::block(move || process_profile_image(&data, content_type))
+        .await
+        .map_err(|e| AppError::InternalError(e.to_string()))?
+}
+
+// the renditions go first, a stored original means the picture is complete
+async fn put_image(files:&dyn FileStorage, key:&str, image:ProcessedImage) -> Result<(), AppError> {
+    for (size, data) in image.variants {
+        if let Some(variant) = variant_key(key, size) {
+            files.put(&variant, data, image.content_type).await?;
+        }
+    }
+    files.put(key, image.original, image.content_type).await
+}
+
+// Drops a reference to a stored file and deletes it, renditions included, once nothing
+// refers to it. Failures are only logged, at worst a file is left behind.
+pub async fn release_file(files:&dyn FileStorage, blobs:&dyn BlobStore, key:&str) {
+    with_key_locked(key, release_locked(files, blobs, key)).await
+}
+
+// `release_file` for a caller holding the lock of `key`
+async fn release_locked(files:&dyn FileStorage, blobs:&dyn BlobStore, key:&str) {
+    match blobs.release_blob(key).await {
+        Ok(0) => {},
+        Ok(_) => return,
+        Err(e) => {
+            log::warn!("could not release upload {}: {}", key, e);
+            return;
+        },
+    }
+    let variants = ImageSize::RESIZED.iter().filter_map(|size| variant_key(key, *size));
+    for key in variants.chain(std::iter::once(key.to_string())) {
+        match files.delete(&key).await {
+            Ok(()) | Err(AppError::NotFoundError(_)) => {},
+            Err(e) => log::warn!("could not remove unreferenced upload {}: {}", key, e),
+        }
+    }
+}
+
+// Every file field of the form is stored, other fields are ignored. When a field is refused
+// the files stored before it are released again, the form is taken whole or not at all.
+pub async fn upload_files(files:Data<dyn FileStorage>, blobs:Data<dyn BlobStore>, config:Data<AppConfig>, mut payload:Multipart) -> Result<HttpResponse, AppError> {
+    let policy = UploadPolicy::files(&config.uploads);
+    let mut keys = Vec::new();
+
+    let stored = async {
+        while let Some(item) = payload.next().await {
+            let mut field = item.map_err(|e| AppError::ValidationError(e.to_string()))?;
+            if field.content_disposition().get_filename().is_none() {
+                continue;
+            }
+            keys.push(store_field(files.as_ref(), blobs.as_ref(), &mut field, &policy).await?);
+        }
+        Ok(())
+    }
+    .await;
+
+    if let Err(err) = stored {
+        for key in &keys {
+            release_file(files.as_ref(), blobs.as_ref(), key).await;
+        }
+        return Err(err);
+    }
+    if keys.is_empty() {
+        return Err(AppError::ValidationError("no file found in the form".to_string()));
+    }
+    let uploaded:Vec<UploadedFile> = keys.into_iter().map(|key| UploadedFile::set_data(key, &config.server.public_url)).collect();
+    let response = ResponseBuilder::BuildSuccessResponse("Files have been uploaded".to_string(), Some(uploaded));
+    Ok(HttpResponse::Ok().json(response))
+}
+
+// `H` from the request, `None` when it is missing or malformed
+fn typed_header<H:Header>(req:&HttpRequest) -> Option<H> {
+    req.headers().contains_key(H::name()).then(|| H::parse(req).ok()).flatten()
+}
+
+// HTTP dates only carry whole seconds
+fn unix_secs(time:SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs())
+}
+
+// Whether the client's copy is current. `If-None-Match` wins over `If-Modified-Since`.
+fn not_modified(req:&HttpRequest, etag:&EntityTag, info:&FileInfo) -> bool {
+    if let Some(condition) = typed_header::<header::IfNoneMatch>(req) {
+        return match condition {
+            header::IfNoneMatch::Any => true,
+            header::IfNoneMatch::Items(tags) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        };
+    }
+    match (typed_header::<header::IfModifiedSince>(req), info.modified) {
+        (Some(header::IfModifiedSince(since)), Some(modified)) => unix_secs(modified) <= unix_secs(since.into()),
+        _ => false,
+    }
+}
+
+// The single byte range asked for, if it should be honoured. Several ranges at once and
+// ranges guarded by an outdated `If-Range` get the whole file instead.
+fn requested_range(req:&HttpRequest, etag:&EntityTag, info:&FileInfo) -> Option<ByteRangeSpec> {
+    let current = match typed_header::<header::IfRange>(req) {
+        None => true,
+        Some(header::IfRange::EntityTag(tag)) => tag.strong_eq(etag),
+        Some(header::IfRange::Date(date)) => info.modified.is_some_and(|modified| unix_secs(modified) == unix_secs(date.into())),
+    };
+    match typed_header::<header::Range>(req) {
+        Some(header::Range::Bytes(mut ranges)) if current && ranges.len() == 1 => ranges.pop(),
+        _ => None,
+    }
+}
+
+// Serves a stored file with its validators. Conditional requests are answered with a 304
+// and a single `Range` with a 206, only the requested bytes are read from the storage.
+// `?size=` asks for a rendition of a picture, the original is served when it has none.
+pub async fn get_file(req:HttpRequest, files:Data<dyn FileStorage>, path:Path<String>, query:Query<FileQuery>) -> Result<HttpResponse, AppError> {
+    let mut key = path.into_inner();
+    let mut info = None;
+    if let Some(variant) = query.size.and_then(|size| variant_key(&key, size)) {
+        match files.stat(&variant).await {
+            Ok(found) => {
+                key = variant;
+                info = Some(found);
+            },
+            Err(AppError::NotFoundError(_)) => {},
+            Err(e) => return Err(e),
+        }
+    }
+    let info = match info {
+        Some(info) => info,
+        None => files.stat(&key).await?,
+    };
+
+    // what is stored under a key never changes, so the key identifies the content
+    let etag = EntityTag::new_strong(key.clone());
+    let fresh = not_modified(&req, &etag, &info);
+    let mut response = if fresh {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response
+        .insert_header(header::ETag(etag.clone()))
+        .insert_header((header::ACCEPT_RANGES, "bytes"))
+        .insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"));
+    if let Some(modified) = info.modified {
+        response.insert_header(header::LastModified(HttpDate::from(modified)));
+    }
+    if fresh {
+        return Ok(response.finish());
+    }
+    response.content_type(info.content_type.as_str());
+
+    let range = match requested_range(&req, &etag, &info) {
+        Some(spec) => match spec.to_satisfiable_range(info.size) {
+            Some((start, end)) => {
+                response.status(StatusCode::PARTIAL_CONTENT);
+                response.insert_header(header::ContentRange(ContentRangeSpec::Bytes {
+                    range: Some((start, end)),
+                    instance_length: Some(info.size),
+                }));
+                start..end + 1
+            }
+            None => {
+                return Ok(HttpResponse::RangeNotSatisfiable()
+                    .insert_header(header::ContentRange(ContentRangeSpec::Bytes { range: None, instance_length: Some(info.size) }))
+                    .finish());
+            }
+        },
+        None => 0..info.size,
+    };
+
+    // the length is announced without reading the file, the body of a HEAD is never sent
+    if req.method() == Method::HEAD {
+        return Ok(response
+            .no_chunking(range.end - range.start)
+            .streaming(stream::empty::<Result<Bytes, AppError>>()));
+    }
+    let length = range.end - range.start;
+    let data = files.read(&key, range).await?;
+    Ok(response.no_chunking(length).streaming(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, App};
+    use futures::future::join;
+    use serde_json::Value;
+
+    use super::super::testing::{multipart, png, Part, Uploads};
+    use super::*;
+
+    async fn put(files:&dyn FileStorage, key:&str) -> Result<(), AppError> {
+        files.put(key, b"the same content".to_vec(), "text/plain").await
+    }
+
+    // posts the form to `/upload` and returns the status and the body
+    async fn upload(uploads:&Uploads, parts:&[Part<'_>]) -> (StatusCode, Value) {
+        let app = test::init_service(
+            App::new()
+                .app_data(uploads.files.clone())
+                .app_data(uploads.blobs.clone())
+                .app_data(uploads.config.clone())
+                .route("/upload", web::post().to(upload_files)),
+        )
+        .await;
+        let (content_type, body) = multipart(parts);
+        let req = test::TestRequest::post()
+            .uri("/upload")
+            .insert_header((header::CONTENT_TYPE, content_type))
+            .set_payload(body)
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        (res.status(), test::read_body_json(res).await)
+    }
+
+    fn keys(body:&Value) -> Vec<String> {
+        body["data"].as_array().unwrap().iter().map(|file| file["key"].as_str().unwrap().to_string()).collect()
+    }
+
+    #[actix_web::test]
+    async fn identical_uploads_share_a_file_until_the_last_release() {
+        let uploads = Uploads::new();
+        let (files, blobs) = (uploads.files.as_ref(), uploads.blobs.as_ref());
+        let picture = png(8, 8, 1);
+
+        let (status, first) = upload(&uploads, &[("file", Some("a.png"), &picture), ("note", None, b"ignored")]).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, second) = upload(&uploads, &[("file", Some("b.PNG"), &picture), ("file", Some("c.txt"), b"some text")]).await;
+        let (first, second) = (keys(&first), keys(&second));
+        assert_eq!(first.len(), 1);
+        assert_eq!(second[0], first[0]);
+        assert!(first[0].ends_with(".png") && second[1].ends_with(".txt"));
+        assert_eq!(uploads.staged(), 0);
+
+        release_file(files, blobs, &first[0]).await;
+        assert!(uploads.has(&first[0]).await);
+        release_file(files, blobs, &first[0]).await;
+        assert!(!uploads.has(&first[0]).await);
+        assert!(uploads.has(&second[1]).await);
+    }
+
+    #[actix_web::test]
+    async fn refuses_forms_without_an_acceptable_file() {
+        let uploads = Uploads::new();
+        let picture = png(8, 8, 1);
+
+        let forms:[(&[Part], StatusCode); 3] = [
+            (&[("note", None, b"no file")], StatusCode::BAD_REQUEST),
+            (&[("file", Some("a.txt"), &picture)], StatusCode::UNSUPPORTED_MEDIA_TYPE),
+            (&[("file", Some("a.exe"), b"MZ\x90\x00")], StatusCode::UNSUPPORTED_MEDIA_TYPE),
+        ];
+        for (parts, expected) in forms {
+            assert_eq!(upload(&uploads, parts).await.0, expected);
+        }
+        assert_eq!(uploads.stored(), 0);
+    }
+
+    #[actix_web::test]
+    async fn releases_the_files_stored_before_a_refused_one() {
+        let uploads = Uploads::new();
+        let (files, blobs) = (uploads.files.as_ref(), uploads.blobs.as_ref());
+        let (kept, shared) = (png(8, 8, 1), png(8, 8, 2));
+
+        let (_, body) = upload(&uploads, &[("file", Some("kept.png"), &kept)]).await;
+        let kept_key = keys(&body).remove(0);
+
+        let parts:&[Part] = &[("file", Some("kept.png"), &kept), ("file", Some("new.png"), &shared), ("file", Some("a.txt"), &kept)];
+        assert_eq!(upload(&uploads, parts).await.0, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        // the first upload's reference is all that is left
+        assert!(uploads.has(&kept_key).await);
+        assert_eq!(uploads.stored(), 1);
+
+        release_file(files, blobs, &kept_key).await;
+        assert_eq!(uploads.stored(), 0);
+    }
+
+    // A release racing an upload of the same content must not delete what the upload refers to
+    #[actix_web::test]
+    async fn a_release_never_deletes_a_file_that_is_stored_again() {
+        let uploads = Uploads::new();
+        let (files, blobs) = (uploads.files.as_ref(), uploads.blobs.as_ref());
+        let key = content_key(&"ab".repeat(32), "text/plain");
+
+        for _ in 0..50 {
+            store_once(files, blobs, &key, put(files, &key)).await.unwrap();
+            let (_, stored) = join(release_file(files, blobs, &key), store_once(files, blobs, &key, put(files, &key))).await;
+            stored.unwrap();
+            assert!(uploads.has(&key).await);
+
+            release_file(files, blobs, &key).await;
+            assert!(!uploads.has(&key).await);
+        }
+        assert!(!KEY_LOCKS.lock().unwrap().contains_key(&key));
+    }
+}
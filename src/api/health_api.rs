@@ -0,0 +1,46 @@
+use std::time::{Duration, Instant};
+
+use actix_web::{http::header, rt::time::timeout, web::Data, HttpResponse};
+use futures::future::join_all;
+use serde_json::json;
+
+use crate::{model::health_model::{CheckReport, ReadinessReport}, repository::health::ReadinessChecks};
+
+use super::app_errors::AppError;
+
+// a dependency that has not answered by then counts as down
+const CHECK_TIMEOUT:Duration = Duration::from_secs(2);
+
+// Liveness, answering at all means the process is up. Like the readiness report it is plain
+// JSON without the response envelope, for the orchestrator probing it.
+pub async fn healthz() -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .json(json!({ "status": "ok" }))
+}
+
+// Readiness, every dependency is checked at once and a single one being down makes it a 503
+pub async fn readyz(checks:Data<ReadinessChecks>) -> HttpResponse {
+    let results = join_all(checks.probes().iter().map(|probe| async move {
+        let started = Instant::now();
+        let result = match timeout(CHECK_TIMEOUT, probe.check()).await {
+            Ok(result) => result,
+            Err(_) => Err(AppError::UnavailableError(format!("no answer within {} seconds", CHECK_TIMEOUT.as_secs()))),
+        };
+        let latency_ms = (started.elapsed().as_secs_f64() * 100_000.0).round() / 100.0;
+        let report = match result {
+            Ok(()) => CheckReport { status: "up", latency_ms, error: None },
+            Err(err) => CheckReport { status: "down", latency_ms, error: Some(err.to_string()) },
+        };
+        (probe.name(), report)
+    }))
+    .await;
+
+    let ready = results.iter().all(|(_, report)| report.status == "up");
+    let report = ReadinessReport {
+        status: if ready { "ready" } else { "unavailable" },
+        checks: results.into_iter().collect(),
+    };
+    let mut response = if ready { HttpResponse::Ok() } else { HttpResponse::ServiceUnavailable() };
+    response.insert_header((header::CACHE_CONTROL, "no-store")).json(report)
+}
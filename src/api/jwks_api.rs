@@ -0,0 +1,11 @@
+use actix_web::{http::header, web::Data, HttpResponse};
+
+use crate::middleware::jwt_config::TokenAuthentication;
+
+// Served as a plain JWK set rather than in the response envelope, that is what JWT
+// libraries fetching it expect
+pub async fn jwks(tokens:Data<TokenAuthentication>) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "public, max-age=300"))
+        .json(tokens.jwks())
+}
@@ -1,4 +1,11 @@
 pub mod user_api;
 pub mod book_api;
 pub mod author_api;
-pub mod app_errors;
\ No newline at end of file
+pub mod search_api;
+pub mod jwks_api;
+pub mod api_key_api;
+pub mod health_api;
+pub mod file_api;
+pub mod app_errors;
+#[cfg(test)]
+mod testing;
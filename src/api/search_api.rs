@@ -0,0 +1,20 @@
+use actix_web::{web::{Data, Query}, HttpResponse};
+
+use crate::model::search_model::SearchQuery;
+use crate::repository::search_index::{SearchHit, SearchIndex};
+use crate::utils::{pagination::{DEFAULT_PER_PAGE, MAX_PER_PAGE}, response::ResponseBuilder};
+
+use super::app_errors::AppError;
+
+pub async fn search(index:Data<SearchIndex>, query:Query<SearchQuery>) -> Result<HttpResponse, AppError> {
+    let text = query.q.to_owned().unwrap_or_default();
+    if text.trim().is_empty() {
+        return Err(AppError::ValidationError("search text q is required".to_string()));
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_PER_PAGE as usize).clamp(1, MAX_PER_PAGE as usize);
+    let hits = index.search(&text, query.kind, limit);
+
+    let response = ResponseBuilder::<Vec<SearchHit>>::BuildSuccessResponse(format!("{} results found", hits.len()), Some(hits));
+    Ok(HttpResponse::Ok().json(response))
+}
@@ -0,0 +1,94 @@
+// Helpers for the handler tests: uploads kept below a temporary directory, with references
+// counted by the memory backend, and multipart forms written by hand
+
+use std::{fs, io::Cursor, path::PathBuf, sync::Arc};
+
+use actix_web::web::Data;
+use image::{DynamicImage, ImageFormat, Rgb, RgbImage};
+
+use crate::{config::app_config::{AppConfig, UploadConfig}, utils::password::random_token};
+use crate::repository::{file_storage::LocalFileStorage, memory_repo::MemoryBlobRepo, store::{BlobStore, FileStorage}};
+
+const BOUNDARY:&str = "test-form-boundary";
+
+pub struct Uploads {
+    pub dir:PathBuf,
+    pub files:Data<dyn FileStorage>,
+    pub blobs:Data<dyn BlobStore>,
+    pub config:Data<AppConfig>,
+}
+
+impl Uploads {
+    pub fn new() -> Self {
+        let dir = std::env::temp_dir().join(format!("http_server-test-{}", &random_token()[..16]));
+        let config = AppConfig {
+            uploads: UploadConfig { dir: dir.display().to_string(), ..UploadConfig::default() },
+            ..AppConfig::default()
+        };
+        fs::create_dir_all(config.uploads.staging_dir()).unwrap();
+        let files = LocalFileStorage::init(&config.uploads.dir).unwrap();
+        Uploads {
+            dir,
+            files: Data::from(Arc::new(files) as Arc<dyn FileStorage>),
+            blobs: Data::from(Arc::new(MemoryBlobRepo::init()) as Arc<dyn BlobStore>),
+            config: Data::new(config),
+        }
+    }
+
+    // whether `key` is stored
+    pub async fn has(&self, key:&str) -> bool {
+        self.files.stat(key).await.is_ok()
+    }
+
+    // files left in the staging directory
+    pub fn staged(&self) -> usize {
+        fs::read_dir(self.config.uploads.staging_dir()).unwrap().count()
+    }
+
+    // every file below the directory, staged ones included
+    pub fn stored(&self) -> usize {
+        fn count(dir:&PathBuf) -> usize {
+            fs::read_dir(dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .map(|path| if path.is_dir() { count(&path) } else { 1 })
+                .sum()
+        }
+        count(&self.dir)
+    }
+}
+
+impl Drop for Uploads {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+// A field of a form: its name, the file name for files, and the content
+pub type Part<'a> = (&'a str, Option<&'a str>, &'a [u8]);
+
+// The content type and body of a multipart/form-data request
+pub fn multipart(parts:&[Part]) -> (String, Vec<u8>) {
+    let mut body = Vec::new();
+    for (name, file_name, data) in parts {
+        body.extend_from_slice(format!("--{}\r\n", BOUNDARY).as_bytes());
+        match file_name {
+            Some(file_name) => body.extend_from_slice(
+                format!("Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n\r\n", name, file_name).as_bytes(),
+            ),
+            None => body.extend_from_slice(format!("Content-Disposition: form-data; name=\"{}\"\r\n\r\n", name).as_bytes()),
+        }
+        body.extend_from_slice(data);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", BOUNDARY).as_bytes());
+    (format!("multipart/form-data; boundary={}", BOUNDARY), body)
+}
+
+// A PNG whose content depends on `seed`, so different seeds are stored under different keys
+pub fn png(width:u32, height:u32, seed:u8) -> Vec<u8> {
+    let image = DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| Rgb([x as u8, y as u8, seed])));
+    let mut data = Cursor::new(Vec::new());
+    image.write_to(&mut data, ImageFormat::Png).unwrap();
+    data.into_inner()
+}
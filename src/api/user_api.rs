@@ -1,221 +1,518 @@
-use crate::{middleware, model::{self, user_model::{LoginUser, User, UserData}}, repository::mongodb_repo::MongoRepo};
-use actix_web::{ delete, get, post, put, web::{Data, Json, Path}, HttpResponse};
-use crate::utils::response::ResponseBuilder;
+use crate::{middleware::{auth_middleware::ACCESS_TOKEN_COOKIE, auth_user::{AuthUser, Credential}, jwt_config::{TokenAuthentication, TokenKind}, role_guard::RequireRole}, model::{self, user_model::{ChangePassword, ChangeRole, ForgotPassword, LoginUser, RefreshToken, RegisterUser, ResetPassword, Role, User, UserData, UserPatch}}};
+use crate::repository::store::{LoginAttemptStore, PasswordResetStore, TokenRevocationStore, UserStore};
+use actix_web::{ cookie::{time::Duration, Cookie, SameSite}, delete, get, patch, post, put, web::{Data, Path}, HttpRequest, HttpResponse};
+use crate::utils::{mailer::Mailer, pagination::ListQuery, password::{hash_password, random_token, verify_password}, response::ResponseBuilder, validation::ValidJson};
+use chrono::Utc;
 use mongodb::bson::oid::ObjectId;
 
+use super::app_errors::AppError;
+
 #[post("/user")]
-pub async fn create_user(db: Data<MongoRepo>, new_user: Json<User>) ->HttpResponse {
+pub async fn create_user(db: Data<dyn UserStore>, new_user: ValidJson<RegisterUser>) -> Result<HttpResponse, AppError> {
     // check the user is exists or not
-    let req_name  = &new_user.name;
-    let old_user = db.check_user_exists(req_name).await;
-    match old_user {
-        // if ok means user already exists
-        Ok(_) => {
-            let response = ResponseBuilder::<()>::BuildFailedResponse(String::from("user already exists"));
-            HttpResponse::InternalServerError().body(serde_json::to_string(&response).unwrap())
-        },
-        Err(_) => {
-            
-            let data = User {
-                id:None,
-                name:new_user.name.to_owned(),
-                location: new_user.location.to_owned(),
-                title: new_user.title.to_owned(),
-            };
-        
-            let user_detail = db.create_user(data).await;
-            match user_detail {
-                Ok(user) => {
-                    let response = ResponseBuilder::BuildSuccessResponse(String::from("User account has been created"), Some(user));
-                    HttpResponse::Ok().json(response)
-                },
-                Err(err) => {
-                    let response = ResponseBuilder::<()>::BuildFailedResponse(err.to_string());
-                    HttpResponse::InternalServerError().json(response)
-                },
-            }
-        }
+    match db.check_user_exists(&new_user.name).await {
+        Ok(_) => return Err(AppError::ConflictError(String::from("user already exists"))),
+        Err(AppError::NotFoundError(_)) => {},
+        Err(err) => return Err(err),
     }
-    
 
+    let data = User {
+        id:None,
+        name:new_user.name.to_owned(),
+        location: new_user.location.to_owned(),
+        title: new_user.title.to_owned(),
+        password_hash: Some(hash_password(new_user.password.to_owned()).await?),
+        // admins hand out the other roles
+        role: Role::Reader,
+    };
+
+    let user = db.create_user(data).await?;
+    let response = ResponseBuilder::BuildSuccessResponse(String::from("User account has been created"), Some(user));
+    Ok(HttpResponse::Ok().json(response))
+}
+
+// Names identify users at login, so a rename may not take one already in use
+async fn check_name_free(db:&dyn UserStore, name:&str, id:&str) -> Result<(), AppError> {
+    match db.get_user_by_name(name).await {
+        Ok(user) if user.id.map(|user_id| user_id.to_hex()).as_deref() != Some(id) => {
+            Err(AppError::ConflictError(String::from("user name is already taken")))
+        },
+        Ok(_) | Err(AppError::NotFoundError(_)) => Ok(()),
+        Err(err) => Err(err),
+    }
 }
 
 #[get("/user/{id}")]
-pub async fn get_user(db:Data<MongoRepo>, path: Path<String>) -> HttpResponse {
+pub async fn get_user(db:Data<dyn UserStore>, path: Path<String>) -> Result<HttpResponse, AppError> {
     let id = path.into_inner();
-    if id.is_empty() {
-        return HttpResponse::BadRequest().body("invalid id");
-    }
-    let user_details = db.get_user(&id).await;
-    match user_details  {
-        Ok(user) => {
-            let  user_data = model::user_model::UserData{
-                id:user.id.unwrap().to_hex(),
-                name:user.name,
-                location:user.location,
-                title:user.title,
-                access_token:None,
-            };
 
-            let response = ResponseBuilder::<model::user_model::UserData>::BuildSuccessResponse(String::from("user fetch successfully"), Some(user_data));
-           return  HttpResponse::Ok().body(serde_json::to_string(&response).unwrap())
-        },
-        Err(err) => {
-            let response = ResponseBuilder::<()>::BuildFailedResponse(err.to_string());
+    let user = db.get_user(&id).await?;
+    let user_data = model::user_model::UserData{
+        id:user.id.unwrap_or_default().to_hex(),
+        name:user.name,
+        location:user.location,
+        title:user.title,
+        role:user.role,
+        access_token:None,
+        refresh_token:None,
+    };
 
-            if  err.to_string() == "Invalid id has been passed" {
-               return  HttpResponse::BadRequest().body(serde_json::to_string(&response).unwrap());
-            }
-            if err.to_string() == "user not found" {
-                return HttpResponse::NotFound().body(serde_json::to_string(&response).unwrap());
-            }
-            HttpResponse::InternalServerError().body(serde_json::to_string(&response).unwrap())
-        }
-    }   
-    
+    let response = ResponseBuilder::<model::user_model::UserData>::BuildSuccessResponse(String::from("user fetch successfully"), Some(user_data));
+    Ok(HttpResponse::Ok().json(response))
 }
 
-#[get("/users")]
-pub async fn get_users(db:Data<MongoRepo>) -> HttpResponse {
-    let users = db.get_all_users().await;
-    // println!("Users : {:?}", users);
-    let n = users.as_ref().unwrap().len() ;
-    if n as i32 == 0 {
-        let response = ResponseBuilder::<()>::BuildFailedResponse(String::from("Users not found"));
-        return HttpResponse::NotFound().body(serde_json::to_string(&response).unwrap());
-    }
-    let result = users.unwrap();
+#[get("/users", wrap = "RequireRole::new(Role::Admin)")]
+pub async fn get_users(db:Data<dyn UserStore>, req:HttpRequest) -> Result<HttpResponse, AppError> {
+    let query = ListQuery::parse(req.query_string(), User::LIST_FIELDS).map_err(AppError::ValidationError)?;
+
+    // an empty page is an answer too, e.g. past the last user matching the filters
+    let users = db.get_all_users(&query).await?;
+    let pagination = query.pagination(req.path(), &users, users.items.last().and_then(|user| user.id));
     let mut response_users:Vec<UserData> = Vec::new();
-    for user in result.iter() {
-        // println!("user name : {:?}", user.name);
+    for user in users.items.iter() {
        let user_data = UserData {
-            id:user.id.unwrap().to_hex(),
+            id:user.id.unwrap_or_default().to_hex(),
             name:user.name.to_string(),
             location:user.location.to_string(),
             title:user.title.to_string(),
+            role:user.role,
             access_token:None,
+            refresh_token:None,
        };
        response_users.push(user_data)
     }
-    let response = ResponseBuilder::<Vec<model::user_model::UserData>>::BuildSuccessResponse(String::from("users fetched!"), Some(response_users));
-    HttpResponse::Ok().json(response)
+    let response = ResponseBuilder::<Vec<model::user_model::UserData>>::BuildPageResponse(String::from("users fetched!"), Some(response_users), pagination);
+    Ok(HttpResponse::Ok().json(response))
 }
 
 #[put("/user/{id}")]
-pub async fn update_user(db:Data<MongoRepo>, path: Path<String>,new_user:Json<User>) -> HttpResponse {
+pub async fn update_user(db:Data<dyn UserStore>, auth:AuthUser, path: Path<String>,new_user:ValidJson<User>) -> Result<HttpResponse, AppError> {
     let id = path.into_inner();
-    if id.is_empty() {
-        return HttpResponse::BadRequest().body("invalid ID");
-    };
-
-    let obj_id:ObjectId;
-
-    match ObjectId::parse_str(&id) {
-        Ok(obj) => {
-            obj_id = obj
-        },
-        Err(_) => {
-            let response = ResponseBuilder::<()>::BuildFailedResponse(String::from("invalid id"));
-            return HttpResponse::BadRequest().json(response);
-        }
+    if !auth.can_manage_user(&id) {
+        return Err(AppError::ForbiddenError(String::from("users may only update their own account")));
     }
+    let obj_id = ObjectId::parse_str(&id).map_err(|_| AppError::InvalidIdError)?;
+    check_name_free(db.as_ref(), &new_user.name, &id).await?;
 
     let data = User {
         id:Some(obj_id),
         name: new_user.name.to_owned(),
         location:new_user.location.to_owned(),
         title:new_user.title.to_owned(),
+        password_hash:None,
+        role:Role::default(),
     };
 
-    let update_result = db.update_user(&id, data).await;
-
-    match update_result {
-        Ok(update) => {
-            if update.matched_count == 1 {
-                let updated_user_info = db.get_user(&id).await;
-                return match updated_user_info {
-                    Ok(user) => {
-                        let user_data = UserData {
-                            id:user.id.unwrap().to_hex(),
-                            name:user.name,
-                            location:user.location,
-                            title:user.title,
-                            access_token:None,
-                        };
-                        let response = ResponseBuilder::BuildSuccessResponse(String::from("user update successfully!"), Some(user_data));
-                        HttpResponse::Ok().json(response)
-                    },
-                    Err(err) => {
-                        let response = ResponseBuilder::<()>::BuildFailedResponse(err.to_string());
-                        HttpResponse::InternalServerError().json(response)
-                    },
-                };
-            }
-            let response = ResponseBuilder::<()>::BuildFailedResponse(String::from("No user found with specific id"));
-            return HttpResponse::NotFound().json(response);
-        }
-        Err(err) => {
-            let response = ResponseBuilder::<()>::BuildFailedResponse(String::from(err.to_string()));
-            HttpResponse::InternalServerError().json(response)
-        }
+    let update = db.update_user(&id, data).await?;
+    if update.matched_count != 1 {
+        return Err(AppError::NotFoundError(String::from("No user found with specific id")));
     }
+
+    let user = db.get_user(&id).await?;
+    let user_data = UserData {
+        id:user.id.unwrap_or_default().to_hex(),
+        name:user.name,
+        location:user.location,
+        title:user.title,
+        role:user.role,
+        access_token:None,
+        refresh_token:None,
+    };
+    let response = ResponseBuilder::BuildSuccessResponse(String::from("user update successfully!"), Some(user_data));
+    Ok(HttpResponse::Ok().json(response))
 }
 
-#[delete("/user/{id}")]
-pub async fn delete_user(db:Data<MongoRepo>, path: Path<String>) -> HttpResponse {
-    let obj_id:ObjectId;
+#[patch("/user/{id}")]
+pub async fn patch_user(db:Data<dyn UserStore>, auth:AuthUser, path: Path<String>, user:ValidJson<UserPatch>) -> Result<HttpResponse, AppError> {
     let id = path.into_inner();
+    if !auth.can_manage_user(&id) {
+        return Err(AppError::ForbiddenError(String::from("users may only update their own account")));
+    }
+    let patch = user.into_inner();
+    if patch.is_empty() {
+        return Err(AppError::ValidationError(String::from("no fields to update")));
+    }
+    if let Some(name) = &patch.name {
+        check_name_free(db.as_ref(), name, &id).await?;
+    }
 
-    if id.is_empty() {
-        let response = ResponseBuilder::<()>::BuildFailedResponse("invalid ID".to_string());
-        HttpResponse::BadRequest().json(response);
+    let update = db.patch_user(&id, patch).await?;
+    if update.matched_count != 1 {
+        return Err(AppError::NotFoundError(String::from("No user found with specific id")));
     }
 
-    match ObjectId::parse_str(&id) {
-        Ok(obj) => {
-            obj_id = obj
-        },
-        Err(_)=> {
-            let response = ResponseBuilder::<()>::BuildFailedResponse(String::from("invalid id"));
-            return HttpResponse::BadRequest().json(response);
-        }
+    let user = db.get_user(&id).await?;
+    let user_data = UserData {
+        id:user.id.unwrap_or_default().to_hex(),
+        name:user.name,
+        location:user.location,
+        title:user.title,
+        role:user.role,
+        access_token:None,
+        refresh_token:None,
+    };
+    let response = ResponseBuilder::BuildSuccessResponse(String::from("user update successfully!"), Some(user_data));
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[delete("/user/{id}", wrap = "RequireRole::new(Role::Admin)")]
+pub async fn delete_user(db:Data<dyn UserStore>, tokens:Data<TokenAuthentication>, revocations:Data<dyn TokenRevocationStore>, path: Path<String>) -> Result<HttpResponse, AppError> {
+    let obj_id = ObjectId::parse_str(path.into_inner()).map_err(|_| AppError::InvalidIdError)?;
+
+    let result = db.delete_user(&obj_id).await?;
+    if result.deleted_count != 1 {
+        return Err(AppError::NotFoundError(String::from("No user found with specific id")));
     }
+    // the sessions of the user go with it, its tokens may still carry the old role
+    revocations.revoke_user(&obj_id.to_hex(), Utc::now().timestamp() + tokens.refresh_token_ttl()).await?;
+    let response = ResponseBuilder::<()>::BuildSuccessResponse(String::from("User successfully deleted"), None);
+    Ok(HttpResponse::Ok().json(response))
+}
 
-    let user_details = db.delete_user(&obj_id).await;
-    return match user_details {
-        Ok(result) => {
-            if result.deleted_count == 1 {
-                let response = ResponseBuilder::<()>::BuildSuccessResponse(String::from("User successfully deleted"), None);
-                return HttpResponse::Ok().json(response)
-            }
-            let response = ResponseBuilder::<()>::BuildFailedResponse(String::from("failed to delete"));
-            HttpResponse::BadRequest().json(response)
-        },
-        Err(err) => {
-            let response = ResponseBuilder::<()>::BuildFailedResponse(String::from(err.to_string()));
-            HttpResponse::InternalServerError().json(response)
-        }
+#[put("/user/{id}/role", wrap = "RequireRole::new(Role::Admin)")]
+pub async fn set_user_role(db:Data<dyn UserStore>, path: Path<String>, body:ValidJson<ChangeRole>) -> Result<HttpResponse, AppError> {
+    let id = path.into_inner();
+
+    let update = db.set_role(&id, body.role).await?;
+    if update.matched_count != 1 {
+        return Err(AppError::NotFoundError(String::from("No user found with specific id")));
     }
+
+    // tokens already issued keep the old role until they are refreshed
+    let user = db.get_user(&id).await?;
+    let user_data = UserData {
+        id:user.id.unwrap_or_default().to_hex(),
+        name:user.name,
+        location:user.location,
+        title:user.title,
+        role:user.role,
+        access_token:None,
+        refresh_token:None,
+    };
+    let response = ResponseBuilder::BuildSuccessResponse(String::from("user role updated"), Some(user_data));
+    Ok(HttpResponse::Ok().json(response))
 }
 
 #[post("/login")]
-pub async fn login_user(db:Data<MongoRepo>, user_data:Json<LoginUser>) -> HttpResponse {
-    let name = user_data.name.to_owned();
+pub async fn login_user(db:Data<dyn UserStore>, tokens:Data<TokenAuthentication>, revocations:Data<dyn TokenRevocationStore>, attempts:Data<dyn LoginAttemptStore>, user_data:ValidJson<LoginUser>) -> Result<HttpResponse, AppError> {
+    let login = user_data.into_inner();
 
-    let result = db.get_user_by_name(&name).await;
-    
-    match result {
-        Ok(user) => {
-            // if user found then generate a token
-            let mut user_data = UserData { id: user.id.unwrap().to_string(), name, location: user.location, title: user.title, access_token:None };
-            let token_authentication = middleware::jwt_config::TokenAuthentication::init();
-            let token = token_authentication.generate_token(&user_data);
-            user_data.access_token = Some(token);
-            HttpResponse::Ok().json(user_data)
+    // a locked account is refused before the password is even looked at
+    if let Some(secs) = attempts.login_locked_for(&login.name).await? {
+        return Err(AppError::TooManyRequestsError(format!("too many failed logins, try again in {} seconds", secs), secs));
+    }
+
+    let user = match db.get_user_by_name(&login.name).await {
+        Ok(user) => Some(user),
+        Err(AppError::NotFoundError(_)) => None,
+        Err(err) => return Err(err),
+    };
+
+    // unknown names are verified against a dummy hash so both cases take as long
+    let password_hash = user.as_ref().and_then(|user| user.password_hash.to_owned());
+    let user = match (verify_password(login.password, password_hash).await?, user) {
+        (true, Some(user)) => user,
+        _ => {
+            attempts.record_login_failure(&login.name).await?;
+            return Err(AppError::UnauthorizedError(String::from("invalid user name or password")));
         },
-        Err(e) => {
-            HttpResponse::InternalServerError().json(e.to_string())
+    };
+    attempts.clear_login_failures(&login.name).await?;
+
+    // if user found then start a new session and hand out its tokens
+    let user_data = UserData { id: user.id.unwrap_or_default().to_string(), name: login.name, location: user.location, title: user.title, role: user.role, access_token:None, refresh_token:None };
+    let issued_at = revocations.user_token_time(&user_data.id).await?;
+    Ok(token_response(&tokens, issue_tokens(&tokens, user_data, &random_token(), issued_at)?))
+}
+
+// Access and refresh token for the given session, both carry the user without any tokens
+fn issue_tokens(tokens:&TokenAuthentication, mut user_data:UserData, session_id:&str, issued_at:i64) -> Result<UserData, AppError> {
+    let sign = |kind| tokens.generate_token(&user_data, kind, session_id, issued_at);
+
+    let (access, refresh) = (sign(TokenKind::Access)?, sign(TokenKind::Refresh)?);
+    user_data.access_token = Some(access);
+    user_data.refresh_token = Some(refresh);
+    Ok(user_data)
+}
+
+// The access token also goes into an http-only cookie, for browser clients
+fn token_response(tokens:&TokenAuthentication, user_data:UserData) -> HttpResponse {
+    let cookie = Cookie::build(ACCESS_TOKEN_COOKIE, user_data.access_token.clone().unwrap_or_default())
+        .path("/")
+        .http_only(true)
+        .secure(true)
+        .same_site(SameSite::Strict)
+        .max_age(Duration::seconds(tokens.access_token_ttl()))
+        .finish();
+    HttpResponse::Ok().cookie(cookie).json(user_data)
+}
+
+#[post("/token/refresh")]
+pub async fn refresh_token(db:Data<dyn UserStore>, tokens:Data<TokenAuthentication>, revocations:Data<dyn TokenRevocationStore>, body:ValidJson<RefreshToken>) -> Result<HttpResponse, AppError> {
+    let invalid = || AppError::UnauthorizedError(String::from("invalid refresh token"));
+
+    let claims = tokens.validate_token(&body.refresh_token).map_err(|_| invalid())?;
+    let user_id = claims.user.as_ref().map(|user| user.id.clone()).unwrap_or_default();
+    if claims.kind != TokenKind::Refresh
+        || revocations.is_token_revoked(&claims.sid).await?
+        || revocations.is_user_revoked(&user_id, claims.iat).await? {
+        return Err(invalid());
+    }
+    // refresh tokens are single use, seeing one again means it leaked so the whole session goes
+    if revocations.is_token_revoked(&claims.jti).await? {
+        revocations.revoke_token(&claims.sid, Utc::now().timestamp() + tokens.refresh_token_ttl()).await?;
+        return Err(AppError::UnauthorizedError(String::from("refresh token has already been used")));
+    }
+    revocations.revoke_token(&claims.jti, claims.exp).await?;
+
+    // the user may have changed or been deleted since the session started
+    let user = match db.get_user(&user_id).await {
+        Ok(user) => user,
+        Err(AppError::NotFoundError(_)) => return Err(invalid()),
+        Err(err) => return Err(err),
+    };
+    let user_data = UserData { id: user.id.unwrap_or_default().to_string(), name: user.name, location: user.location, title: user.title, role: user.role, access_token:None, refresh_token:None };
+    let issued_at = revocations.user_token_time(&user_data.id).await?;
+    Ok(token_response(&tokens, issue_tokens(&tokens, user_data, &claims.sid, issued_at)?))
+}
+
+// Revokes the session of the presented access token, which takes its refresh token along
+#[post("/logout")]
+pub async fn logout_user(tokens:Data<TokenAuthentication>, revocations:Data<dyn TokenRevocationStore>, auth:AuthUser) -> Result<HttpResponse, AppError> {
+    let session_id = match auth.credential {
+        Credential::Session(session_id) => session_id,
+        Credential::ApiKey(key_id) => return Err(AppError::ValidationError(format!("API key {} can not log out, an admin has to revoke it", key_id))),
+    };
+    // no token of the session can outlive a refresh token issued right now
+    revocations.revoke_token(&session_id, Utc::now().timestamp() + tokens.refresh_token_ttl()).await?;
+    let mut cookie = Cookie::build(ACCESS_TOKEN_COOKIE, "").path("/").finish();
+    cookie.make_removal();
+    let response = ResponseBuilder::<()>::BuildSuccessResponse(String::from("logged out"), None);
+    Ok(HttpResponse::Ok().cookie(cookie).json(response))
+}
+
+#[put("/user/{id}/password")]
+pub async fn change_password(db:Data<dyn UserStore>, tokens:Data<TokenAuthentication>, revocations:Data<dyn TokenRevocationStore>, auth:AuthUser, path: Path<String>, body:ValidJson<ChangePassword>) -> Result<HttpResponse, AppError> {
+    let id = path.into_inner();
+    if !auth.can_manage_user(&id) {
+        return Err(AppError::ForbiddenError(String::from("users may only change their own password")));
+    }
+    let body = body.into_inner();
+
+    let user = db.get_user(&id).await?;
+    if !verify_password(body.current_password, user.password_hash).await? {
+        return Err(AppError::UnauthorizedError(String::from("current password is incorrect")));
+    }
+
+    db.set_password_hash(&id, hash_password(body.new_password).await?).await?;
+    // a session opened with the old password may be someone else's, every one of them ends
+    revocations.revoke_user(&id, Utc::now().timestamp() + tokens.refresh_token_ttl()).await?;
+    let response = ResponseBuilder::<()>::BuildSuccessResponse(String::from("password has been changed, please log in again"), None);
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[post("/password/forgot")]
+pub async fn forgot_password(db:Data<dyn UserStore>, resets:Data<dyn PasswordResetStore>, mailer:Data<dyn Mailer>, body:ValidJson<ForgotPassword>) -> Result<HttpResponse, AppError> {
+    match db.get_user_by_name(&body.name).await {
+        Ok(user) => {
+            let token = resets.issue_reset(&user.id.unwrap_or_default().to_hex()).await?;
+            mailer.send_password_reset(&user, &token).await?;
         },
+        Err(AppError::NotFoundError(_)) => {},
+        Err(err) => return Err(err),
     }
 
-}
\ No newline at end of file
+    // same answer whether or not the user exists
+    let response = ResponseBuilder::<()>::BuildSuccessResponse(String::from("if the user exists a reset token has been sent"), None);
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[post("/password/reset")]
+pub async fn reset_password(db:Data<dyn UserStore>, tokens:Data<TokenAuthentication>, resets:Data<dyn PasswordResetStore>, revocations:Data<dyn TokenRevocationStore>, body:ValidJson<ResetPassword>) -> Result<HttpResponse, AppError> {
+    let body = body.into_inner();
+    let user_id = resets
+        .redeem_reset(&body.token)
+        .await?
+        .ok_or(AppError::ValidationError(String::from("invalid or expired reset token")))?;
+
+    let update = db.set_password_hash(&user_id, hash_password(body.new_password).await?).await?;
+    if update.matched_count != 1 {
+        return Err(AppError::NotFoundError(String::from("No user found with specific id")));
+    }
+    // whoever needed the reset may not be the only one holding a session
+    revocations.revoke_user(&user_id, Utc::now().timestamp() + tokens.refresh_token_ttl()).await?;
+    let response = ResponseBuilder::<()>::BuildSuccessResponse(String::from("password has been reset"), None);
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{http::{header, Method, StatusCode}, test, App};
+    use serde_json::{json, Value};
+
+    use crate::{config::app_config::{AuthConfig, JwtKeyConfig}, middleware::auth_middleware::Authentication, routers::user_router::user_router};
+    use crate::repository::{memory_repo::{MemoryLoginAttemptRepo, MemoryTokenRevocationRepo, MemoryUserRepo}, store::MAX_FAILED_LOGINS};
+    use super::*;
+
+    struct Backend {
+        users:Data<dyn UserStore>,
+        attempts:Data<dyn LoginAttemptStore>,
+        revocations:Data<dyn TokenRevocationStore>,
+        tokens:Data<TokenAuthentication>,
+    }
+
+    impl Backend {
+        fn new() -> Self {
+            let config = AuthConfig {
+                jwt_keys: vec![JwtKeyConfig { kid: "test".to_string(), algorithm: "HS256".to_string(), source: "a test secret that is long enough for HS256".to_string() }],
+                ..AuthConfig::default()
+            };
+            Backend {
+                users: Data::from(Arc::new(MemoryUserRepo::init()) as Arc<dyn UserStore>),
+                attempts: Data::from(Arc::new(MemoryLoginAttemptRepo::init()) as Arc<dyn LoginAttemptStore>),
+                revocations: Data::from(Arc::new(MemoryTokenRevocationRepo::init()) as Arc<dyn TokenRevocationStore>),
+                tokens: Data::new(TokenAuthentication::from_config(&config).unwrap()),
+            }
+        }
+
+        // sends `req` with the bearer `token`, if any, and returns the status and the body
+        async fn send(&self, req:test::TestRequest, token:Option<&Value>) -> (StatusCode, Value) {
+            let authentication = Authentication::new()
+                .public(Method::POST, "/user/user")
+                .public(Method::POST, "/user/login")
+                .public(Method::POST, "/user/token/refresh");
+            let app = test::init_service(
+                App::new()
+                    .wrap(authentication)
+                    .app_data(self.users.clone())
+                    .app_data(self.attempts.clone())
+                    .app_data(self.revocations.clone())
+                    .app_data(self.tokens.clone())
+                    .service(user_router()),
+            )
+            .await;
+            let req = match token.and_then(Value::as_str) {
+                Some(token) => req.insert_header((header::AUTHORIZATION, format!("Bearer {}", token))),
+                None => req,
+            };
+            // the middleware fails the request instead of answering it
+            let res = match test::try_call_service(&app, req.to_request()).await {
+                Ok(res) => res.map_into_boxed_body().into_parts().1,
+                Err(err) => err.error_response(),
+            };
+            let status = res.status();
+            let body = actix_web::body::to_bytes(res.into_body()).await.unwrap();
+            (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+        }
+
+        // posts `body` to `path` below the user scope
+        async fn post(&self, path:&str, body:Value) -> (StatusCode, Value) {
+            self.send(test::TestRequest::post().uri(&format!("/user{}", path)).set_json(body), None).await
+        }
+
+        async fn register(&self, name:&str, password:&str) -> StatusCode {
+            self.post("/user", json!({ "name": name, "location": "here", "title": "reader", "password": password })).await.0
+        }
+
+        async fn login(&self, name:&str, password:&str) -> (StatusCode, Value) {
+            self.post("/login", json!({ "name": name, "password": password })).await
+        }
+
+        async fn refresh(&self, token:&Value) -> (StatusCode, Value) {
+            self.post("/token/refresh", json!({ "refresh_token": token })).await
+        }
+    }
+
+    #[actix_web::test]
+    async fn registers_and_logs_in_with_the_right_password_only() {
+        let backend = Backend::new();
+        assert_eq!(backend.register("ann", "correct horse").await, StatusCode::OK);
+        assert_eq!(backend.register("ann", "another one").await, StatusCode::CONFLICT);
+        assert_eq!(backend.register("bob", "short").await, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let (status, body) = backend.login("ann", "correct horse").await;
+        assert_eq!(status, StatusCode::OK);
+        let claims = backend.tokens.validate_token(body["access_token"].as_str().unwrap()).unwrap();
+        assert_eq!(claims.kind, TokenKind::Access);
+        assert_eq!(claims.role, Role::Reader);
+
+        assert_eq!(backend.login("ann", "wrong horse").await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(backend.login("nobody", "correct horse").await.0, StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn locks_the_login_after_repeated_failures() {
+        let backend = Backend::new();
+        assert_eq!(backend.register("ann", "correct horse").await, StatusCode::OK);
+
+        for _ in 0..MAX_FAILED_LOGINS {
+            assert_eq!(backend.login("ann", "wrong horse").await.0, StatusCode::UNAUTHORIZED);
+        }
+        // even the right password is refused until the lock runs out
+        assert_eq!(backend.login("ann", "correct horse").await.0, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[actix_web::test]
+    async fn a_refresh_token_used_twice_ends_the_session() {
+        let backend = Backend::new();
+        assert_eq!(backend.register("ann", "correct horse").await, StatusCode::OK);
+        let (_, login) = backend.login("ann", "correct horse").await;
+
+        let (status, refreshed) = backend.refresh(&login["refresh_token"]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_ne!(refreshed["refresh_token"], login["refresh_token"]);
+        // an access token is no refresh token
+        assert_eq!(backend.refresh(&refreshed["access_token"]).await.0, StatusCode::UNAUTHORIZED);
+
+        let (status, body) = backend.refresh(&login["refresh_token"]).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["msg"], "refresh token has already been used");
+        // the token handed out before the reuse went with the session
+        assert_eq!(backend.refresh(&refreshed["refresh_token"]).await.0, StatusCode::UNAUTHORIZED);
+
+        // another login starts a new session
+        let (_, login) = backend.login("ann", "correct horse").await;
+        assert_eq!(backend.refresh(&login["refresh_token"]).await.0, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn logs_in_right_after_a_password_change() {
+        let backend = Backend::new();
+        assert_eq!(backend.register("ann", "correct horse").await, StatusCode::OK);
+        let (_, old) = backend.login("ann", "correct horse").await;
+        let path = format!("/user/user/{}", old["id"].as_str().unwrap());
+
+        let change = test::TestRequest::put()
+            .uri(&format!("{}/password", path))
+            .set_json(json!({ "current_password": "correct horse", "new_password": "battery staple" }));
+        assert_eq!(backend.send(change, Some(&old["access_token"])).await.0, StatusCode::OK);
+        let (status, new) = backend.login("ann", "battery staple").await;
+        assert_eq!(status, StatusCode::OK);
+
+        // within the second of the change only the tokens issued before it are refused
+        assert_eq!(backend.send(test::TestRequest::get().uri(&path), Some(&old["access_token"])).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(backend.send(test::TestRequest::get().uri(&path), Some(&new["access_token"])).await.0, StatusCode::OK);
+        assert_eq!(backend.refresh(&old["refresh_token"]).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(backend.refresh(&new["refresh_token"]).await.0, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn a_deleted_user_is_logged_out() {
+        let backend = Backend::new();
+        for name in ["ann", "admin"] {
+            assert_eq!(backend.register(name, "correct horse").await, StatusCode::OK);
+        }
+        let admin = backend.users.get_user_by_name("admin").await.unwrap().id.unwrap().to_hex();
+        backend.users.set_role(&admin, Role::Admin).await.unwrap();
+        let (_, ann) = backend.login("ann", "correct horse").await;
+        let (_, admin) = backend.login("admin", "correct horse").await;
+        let path = format!("/user/user/{}", ann["id"].as_str().unwrap());
+
+        assert_eq!(backend.send(test::TestRequest::delete().uri(&path), Some(&admin["access_token"])).await.0, StatusCode::OK);
+        assert_eq!(backend.send(test::TestRequest::get().uri("/user/users"), Some(&ann["access_token"])).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(backend.refresh(&ann["refresh_token"]).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(backend.send(test::TestRequest::get().uri("/user/users"), Some(&admin["access_token"])).await.0, StatusCode::OK);
+    }
+}
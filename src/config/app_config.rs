@@ -0,0 +1,581 @@
+use std::{env, fs, io, path::PathBuf};
+
+use dotenv::dotenv;
+use serde::Deserialize;
+
+use crate::{config::db_config::{AuthorDeletePolicy, StorageBackend}, middleware::jwt_keys::JwtAlgorithm};
+
+// read when APP_CONFIG does not name a file, the server also starts without it
+const DEFAULT_CONFIG_FILE:&str = "config.json";
+
+// Every setting of the server. Loaded once at startup from a JSON file (APP_CONFIG, or
+// config.json when present), then environment variables override single values, see
+// `apply_env` for their names. Shared with the handlers through app data.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppConfig {
+    pub server:ServerConfig,
+    pub storage:StorageConfig,
+    pub auth:AuthConfig,
+    pub uploads:UploadConfig,
+    pub rate_limits:RateLimitConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    // host:port the server listens on
+    pub bind_address:String,
+    // how clients reach the server, used for the links in responses
+    pub public_url:String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub backend:StorageBackend,
+    // required with the mongo backend
+    pub mongo_uri:Option<String>,
+    pub mongo_db_name:String,
+    // sqlite://<file>?mode=rwc or postgres://..., for the sql backend
+    pub database_url:String,
+    pub author_delete_policy:AuthorDeletePolicy,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub jwt_keys:Vec<JwtKeyConfig>,
+    // kid new tokens are signed with, defaults to the first key
+    pub jwt_signing_key:Option<String>,
+    // token lifetimes, in seconds
+    pub access_token_ttl:i64,
+    pub refresh_token_ttl:i64,
+    // existing user given the admin role on startup
+    pub admin_user:Option<String>,
+    // writes password reset tokens to the debug log, there is no mailer yet. Development only.
+    pub log_reset_tokens:bool,
+}
+
+// `source` is the secret for HS256 and the path of a PEM private key otherwise
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JwtKeyConfig {
+    pub kid:String,
+    pub algorithm:String,
+    pub source:String,
+}
+
+// the source may be a secret, so it never shows up in logs
+impl std::fmt::Debug for JwtKeyConfig {
+    fn fmt(&self, f:&mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JwtKeyConfig").field("kid", &self.kid).field("algorithm", &self.algorithm).finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UploadConfig {
+    pub backend:FileBackend,
+    // root directory of the local backend, uploads are staged below it with every backend
+    pub dir:String,
+    // largest file taken by `POST /upload`, in bytes
+    pub max_file_size:u64,
+    // largest picture taken by `POST /author/author/profile`, in bytes
+    pub max_profile_image_size:u64,
+    pub s3:S3Config,
+}
+
+// Requests a client can make, see `RateLimit`. The credential endpoints are counted per
+// address, everything else per address and then once more per user or API key.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    // POST /user/login
+    pub login_per_minute:u32,
+    // POST /user/user
+    pub register_per_hour:u32,
+    // POST /user/password/forgot and /user/password/reset, each
+    pub password_reset_per_hour:u32,
+    // POST /user/token/refresh
+    pub refresh_per_minute:u32,
+    // every request, before authentication
+    pub per_ip_per_minute:u32,
+    // every request, after authentication
+    pub per_user_per_minute:u32,
+}
+
+impl UploadConfig {
+    // where uploads are written while they arrive, on the same file system as the local backend
+    pub fn staging_dir(&self) -> PathBuf {
+        PathBuf::from(&self.dir).join(".staging")
+    }
+}
+
+// Where uploaded files are kept, `uploads.backend` of the config (defaults to local)
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileBackend {
+    Local,
+    S3,
+}
+
+impl FileBackend {
+    pub fn parse(name:&str) -> Option<Self> {
+        match name {
+            v if v.eq_ignore_ascii_case("local") => Some(FileBackend::Local),
+            v if v.eq_ignore_ascii_case("s3") => Some(FileBackend::S3),
+            _ => None,
+        }
+    }
+}
+
+// Any S3 compatible service, objects are addressed path style as endpoint/bucket/key
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct S3Config {
+    pub endpoint:String,
+    pub bucket:String,
+    pub region:String,
+    pub access_key:String,
+    pub secret_key:String,
+}
+
+impl std::fmt::Debug for S3Config {
+    fn fmt(&self, f:&mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("S3Config")
+            .field("endpoint", &self.endpoint)
+            .field("bucket", &self.bucket)
+            .field("region", &self.region)
+            .field("access_key", &self.access_key)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig { bind_address: "127.0.0.1:8000".to_string(), public_url: "http://localhost:8000".to_string() }
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            backend: StorageBackend::Mongo,
+            mongo_uri: None,
+            mongo_db_name: "rustDB".to_string(),
+            database_url: "sqlite://rustDB.sqlite?mode=rwc".to_string(),
+            author_delete_policy: AuthorDeletePolicy::Reject,
+        }
+    }
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            jwt_keys: Vec::new(),
+            jwt_signing_key: None,
+            access_token_ttl: 3600,
+            refresh_token_ttl: 7 * 24 * 3600,
+            admin_user: None,
+            log_reset_tokens: false,
+        }
+    }
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        UploadConfig {
+            backend: FileBackend::Local,
+            dir: "./tmp".to_string(),
+            max_file_size: 20 * 1024 * 1024,
+            max_profile_image_size: 5 * 1024 * 1024,
+            s3: S3Config::default(),
+        }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            login_per_minute: 10,
+            register_per_hour: 20,
+            password_reset_per_hour: 10,
+            refresh_per_minute: 30,
+            per_ip_per_minute: 600,
+            per_user_per_minute: 300,
+        }
+    }
+}
+
+impl Default for S3Config {
+    fn default() -> Self {
+        S3Config {
+            endpoint: String::new(),
+            bucket: String::new(),
+            region: "us-east-1".to_string(),
+            access_key: String::new(),
+            secret_key: String::new(),
+        }
+    }
+}
+
+fn invalid(msg:String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+impl AppConfig {
+    // Every problem is reported at once, one per line, so a broken deployment can be
+    // fixed in a single pass
+    pub fn load() -> io::Result<Self> {
+        dotenv().ok();
+        AppConfig::load_with(&|name| env::var(name).ok(), DEFAULT_CONFIG_FILE)
+    }
+
+    // `load` reading variables through `var` and falling back to `default_file`, so the
+    // tests don't have to change the environment of the process
+    fn load_with(var:&dyn Fn(&str) -> Option<String>, default_file:&str) -> io::Result<Self> {
+        let (path, required) = match var("APP_CONFIG") {
+            Some(path) => (path, true),
+            None => (default_file.to_string(), false),
+        };
+        let mut config = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text).map_err(|e| invalid(format!("{}: {}", path, e)))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => AppConfig::default(),
+            Err(e) => return Err(io::Error::new(e.kind(), format!("{}: {}", path, e))),
+        };
+
+        let mut errors = Vec::new();
+        config.apply_env(var, &mut errors);
+        config.validate(&mut errors);
+        if !errors.is_empty() {
+            return Err(invalid(format!("invalid configuration:\n  {}", errors.join("\n  "))));
+        }
+        Ok(config)
+    }
+
+    fn apply_env(&mut self, env:&dyn Fn(&str) -> Option<String>, errors:&mut Vec<String>) {
+        let var = |name:&str| env(name).filter(|value| !value.trim().is_empty());
+        let mut seconds = |name:&str, target:&mut i64| {
+            if let Some(value) = var(name) {
+                match value.trim().parse() {
+                    Ok(secs) => *target = secs,
+                    Err(_) => errors.push(format!("{} must be a number of seconds, got {:?}", name, value)),
+                }
+            }
+        };
+        seconds("ACCESS_TOKEN_TTL", &mut self.auth.access_token_ttl);
+        seconds("REFRESH_TOKEN_TTL", &mut self.auth.refresh_token_ttl);
+
+        if let Some(value) = var("BIND_ADDRESS") {
+            self.server.bind_address = value;
+        }
+        if let Some(value) = var("PUBLIC_URL") {
+            self.server.public_url = value;
+        }
+        if let Some(value) = var("STORAGE_BACKEND") {
+            match StorageBackend::parse(&value) {
+                Some(backend) => self.storage.backend = backend,
+                None => errors.push(format!("STORAGE_BACKEND must be mongo, memory or sql, got {:?}", value)),
+            }
+        }
+        if let Some(value) = var("MONGOURI") {
+            self.storage.mongo_uri = Some(value);
+        }
+        if let Some(value) = var("MONGO_DB_NAME") {
+            self.storage.mongo_db_name = value;
+        }
+        if let Some(value) = var("DATABASE_URL") {
+            self.storage.database_url = value;
+        }
+        if let Some(value) = var("AUTHOR_DELETE_POLICY") {
+            match AuthorDeletePolicy::parse(&value) {
+                Some(policy) => self.storage.author_delete_policy = policy,
+                None => errors.push(format!("AUTHOR_DELETE_POLICY must be reject, cascade or nullify, got {:?}", value)),
+            }
+        }
+        // a comma separated list of `kid:ALG:source` entries, replacing the keys of the file
+        if let Some(value) = var("JWT_KEYS") {
+            self.auth.jwt_keys.clear();
+            for (position, entry) in value.split(',').map(str::trim).filter(|entry| !entry.is_empty()).enumerate() {
+                // the entry may hold a secret, so errors only point at its position
+                match entry.splitn(3, ':').collect::<Vec<&str>>()[..] {
+                    [kid, algorithm, source] => self.auth.jwt_keys.push(JwtKeyConfig {
+                        kid: kid.to_string(),
+                        algorithm: algorithm.to_string(),
+                        source: source.to_string(),
+                    }),
+                    _ => errors.push(format!("JWT_KEYS entry {} is not of the form kid:ALG:source", position + 1)),
+                }
+            }
+        }
+        if let Some(value) = var("JWT_SIGNING_KEY") {
+            self.auth.jwt_signing_key = Some(value);
+        }
+        if let Some(value) = var("ADMIN_USER") {
+            self.auth.admin_user = Some(value);
+        }
+        if let Some(value) = var("LOG_RESET_TOKENS") {
+            match value.trim().parse() {
+                Ok(enabled) => self.auth.log_reset_tokens = enabled,
+                Err(_) => errors.push(format!("LOG_RESET_TOKENS must be true or false, got {:?}", value)),
+            }
+        }
+        if let Some(value) = var("UPLOAD_BACKEND") {
+            match FileBackend::parse(&value) {
+                Some(backend) => self.uploads.backend = backend,
+                None => errors.push(format!("UPLOAD_BACKEND must be local or s3, got {:?}", value)),
+            }
+        }
+        if let Some(value) = var("UPLOAD_DIR") {
+            self.uploads.dir = value;
+        }
+        for (name, target) in [
+            ("UPLOAD_MAX_FILE_SIZE", &mut self.uploads.max_file_size),
+            ("UPLOAD_MAX_PROFILE_IMAGE_SIZE", &mut self.uploads.max_profile_image_size),
+        ] {
+            if let Some(value) = var(name) {
+                match value.trim().parse() {
+                    Ok(bytes) => *target = bytes,
+                    Err(_) => errors.push(format!("{} must be a number of bytes, got {:?}", name, value)),
+                }
+            }
+        }
+        let limits = &mut self.rate_limits;
+        for (name, target) in [
+            ("RATE_LIMIT_LOGIN_PER_MINUTE", &mut limits.login_per_minute),
+            ("RATE_LIMIT_REGISTER_PER_HOUR", &mut limits.register_per_hour),
+            ("RATE_LIMIT_PASSWORD_RESET_PER_HOUR", &mut limits.password_reset_per_hour),
+            ("RATE_LIMIT_REFRESH_PER_MINUTE", &mut limits.refresh_per_minute),
+            ("RATE_LIMIT_PER_IP_PER_MINUTE", &mut limits.per_ip_per_minute),
+            ("RATE_LIMIT_PER_USER_PER_MINUTE", &mut limits.per_user_per_minute),
+        ] {
+            if let Some(value) = var(name) {
+                match value.trim().parse() {
+                    Ok(requests) => *target = requests,
+                    Err(_) => errors.push(format!("{} must be a number of requests, got {:?}", name, value)),
+                }
+            }
+        }
+        let s3 = &mut self.uploads.s3;
+        for (name, target) in [
+            ("S3_ENDPOINT", &mut s3.endpoint),
+            ("S3_BUCKET", &mut s3.bucket),
+            ("S3_REGION", &mut s3.region),
+            ("S3_ACCESS_KEY", &mut s3.access_key),
+            ("S3_SECRET_KEY", &mut s3.secret_key),
+        ] {
+            if let Some(value) = var(name) {
+                *target = value;
+            }
+        }
+    }
+
+    fn validate(&mut self, errors:&mut Vec<String>) {
+        let has_port = self
+            .server
+            .bind_address
+            .rsplit_once(':')
+            .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok());
+        if !has_port {
+            errors.push(format!("server.bind_address must be host:port, got {:?}", self.server.bind_address));
+        }
+        self.server.public_url = self.server.public_url.trim_end_matches('/').to_string();
+        if !(self.server.public_url.starts_with("http://") || self.server.public_url.starts_with("https://")) {
+            errors.push(format!("server.public_url must be an http or https URL, got {:?}", self.server.public_url));
+        }
+
+        if self.storage.backend == StorageBackend::Mongo && self.storage.mongo_uri.is_none() {
+            errors.push("storage.mongo_uri (MONGOURI) is required with the mongo backend".to_string());
+        }
+        if self.storage.mongo_db_name.trim().is_empty() {
+            errors.push("storage.mongo_db_name must not be empty".to_string());
+        }
+
+        if self.auth.jwt_keys.is_empty() {
+            errors.push("auth.jwt_keys (JWT_KEYS) must contain at least one key".to_string());
+        }
+        for (position, key) in self.auth.jwt_keys.iter().enumerate() {
+            if key.kid.is_empty() {
+                errors.push(format!("auth.jwt_keys entry {} has an empty kid", position + 1));
+            }
+            if JwtAlgorithm::parse(&key.algorithm).is_none() {
+                errors.push(format!("auth.jwt_keys {} has algorithm {:?}, expected HS256, RS256 or EdDSA", key.kid, key.algorithm));
+            }
+            if self.auth.jwt_keys[..position].iter().any(|other| other.kid == key.kid) {
+                errors.push(format!("auth.jwt_keys has more than one key with kid {}", key.kid));
+            }
+        }
+        if let Some(kid) = &self.auth.jwt_signing_key {
+            if !self.auth.jwt_keys.iter().any(|key| &key.kid == kid) {
+                errors.push(format!("auth.jwt_signing_key {} is not one of auth.jwt_keys", kid));
+            }
+        }
+        if self.auth.access_token_ttl <= 0 || self.auth.refresh_token_ttl <= 0 {
+            errors.push("auth.access_token_ttl and auth.refresh_token_ttl must be positive".to_string());
+        } else if self.auth.access_token_ttl > self.auth.refresh_token_ttl {
+            errors.push("auth.access_token_ttl must not be longer than auth.refresh_token_ttl".to_string());
+        }
+
+        if self.uploads.dir.trim().is_empty() {
+            errors.push("uploads.dir must not be empty".to_string());
+        }
+        if self.uploads.max_file_size == 0 || self.uploads.max_profile_image_size == 0 {
+            errors.push("uploads.max_file_size and uploads.max_profile_image_size must be positive".to_string());
+        }
+        let limits = &self.rate_limits;
+        for (name, value) in [
+            ("login_per_minute", limits.login_per_minute),
+            ("register_per_hour", limits.register_per_hour),
+            ("password_reset_per_hour", limits.password_reset_per_hour),
+            ("refresh_per_minute", limits.refresh_per_minute),
+            ("per_ip_per_minute", limits.per_ip_per_minute),
+            ("per_user_per_minute", limits.per_user_per_minute),
+        ] {
+            if value == 0 {
+                errors.push(format!("rate_limits.{} must be positive", name));
+            }
+        }
+        match self.uploads.backend {
+            FileBackend::Local => {},
+            FileBackend::S3 => {
+                let s3 = &mut self.uploads.s3;
+                s3.endpoint = s3.endpoint.trim_end_matches('/').to_string();
+                if !(s3.endpoint.starts_with("http://") || s3.endpoint.starts_with("https://")) {
+                    errors.push(format!("uploads.s3.endpoint (S3_ENDPOINT) must be an http or https URL, got {:?}", s3.endpoint));
+                }
+                for (name, value) in [("bucket", &s3.bucket), ("region", &s3.region), ("access_key", &s3.access_key), ("secret_key", &s3.secret_key)] {
+                    if value.trim().is_empty() {
+                        errors.push(format!("uploads.s3.{} (S3_{}) is required with the s3 backend", name, name.to_uppercase()));
+                    }
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::utils::password::random_token;
+    use super::*;
+
+    // a file below the temporary directory, removed again when dropped
+    struct ConfigFile(PathBuf);
+
+    impl ConfigFile {
+        fn new(text:&str) -> Self {
+            let path = env::temp_dir().join(format!("http_server-config-{}.json", &random_token()[..16]));
+            fs::write(&path, text).unwrap();
+            ConfigFile(path)
+        }
+
+        fn path(&self) -> String {
+            self.0.display().to_string()
+        }
+    }
+
+    impl Drop for ConfigFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    const NO_FILE:&str = "/nonexistent/config.json";
+
+    fn load(vars:&[(&str, &str)], default_file:&str) -> io::Result<AppConfig> {
+        let vars:HashMap<String, String> = vars.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        AppConfig::load_with(&|name| vars.get(name).cloned(), default_file)
+    }
+
+    #[test]
+    fn starts_from_the_defaults_without_a_file() {
+        let config = load(&[("STORAGE_BACKEND", "memory"), ("JWT_KEYS", "dev:HS256:a secret")], NO_FILE).unwrap();
+
+        assert_eq!(config.server.bind_address, "127.0.0.1:8000");
+        assert_eq!(config.server.public_url, "http://localhost:8000");
+        assert_eq!(config.storage.backend, StorageBackend::Memory);
+        assert_eq!(config.storage.author_delete_policy, AuthorDeletePolicy::Reject);
+        assert_eq!((config.auth.access_token_ttl, config.auth.refresh_token_ttl), (3600, 7 * 24 * 3600));
+        assert_eq!(config.uploads.dir, "./tmp");
+        assert_eq!(config.uploads.backend, FileBackend::Local);
+        assert_eq!(config.rate_limits.login_per_minute, 10);
+        assert_eq!(config.rate_limits.per_ip_per_minute, 600);
+    }
+
+    #[test]
+    fn the_environment_overrides_the_file() {
+        let file = ConfigFile::new(
+            r#"{
+                "server": { "bind_address": "0.0.0.0:9000", "public_url": "https://books.example.com/" },
+                "storage": { "backend": "memory" },
+                "auth": { "jwt_keys": [{ "kid": "file", "algorithm": "HS256", "source": "a secret" }] },
+                "rate_limits": { "login_per_minute": 5, "refresh_per_minute": 50 }
+            }"#,
+        );
+        let vars = [
+            ("APP_CONFIG", file.path()),
+            ("BIND_ADDRESS", "10.0.0.1:80".to_string()),
+            ("RATE_LIMIT_LOGIN_PER_MINUTE", "7".to_string()),
+            ("JWT_KEYS", "env:HS256:another secret".to_string()),
+            // blank values count as unset
+            ("PUBLIC_URL", " ".to_string()),
+        ];
+        let vars:Vec<(&str, &str)> = vars.iter().map(|(name, value)| (*name, value.as_str())).collect();
+        let config = load(&vars, NO_FILE).unwrap();
+
+        assert_eq!(config.server.bind_address, "10.0.0.1:80");
+        assert_eq!(config.server.public_url, "https://books.example.com");
+        assert_eq!(config.rate_limits.login_per_minute, 7);
+        assert_eq!(config.rate_limits.refresh_per_minute, 50);
+        assert_eq!(config.rate_limits.register_per_hour, 20);
+        let kids:Vec<&str> = config.auth.jwt_keys.iter().map(|key| key.kid.as_str()).collect();
+        assert_eq!(kids, ["env"]);
+
+        // the default file is read the same way when APP_CONFIG is unset
+        let config = load(&[], &file.path()).unwrap();
+        assert_eq!(config.server.bind_address, "0.0.0.0:9000");
+        assert_eq!(config.rate_limits.login_per_minute, 5);
+    }
+
+    #[test]
+    fn reports_every_invalid_value_together() {
+        let vars = [
+            ("ACCESS_TOKEN_TTL", "soon"),
+            ("STORAGE_BACKEND", "oracle"),
+            ("BIND_ADDRESS", "nohost"),
+            ("RATE_LIMIT_PER_IP_PER_MINUTE", "0"),
+            ("RATE_LIMIT_LOGIN_PER_MINUTE", "many"),
+            ("JWT_KEYS", "dev:HS512:a secret,broken"),
+            ("UPLOAD_BACKEND", "s3"),
+        ];
+        let message = load(&vars, NO_FILE).unwrap_err().to_string();
+
+        for expected in [
+            "ACCESS_TOKEN_TTL must be a number of seconds",
+            "STORAGE_BACKEND must be mongo, memory or sql",
+            "server.bind_address must be host:port",
+            "rate_limits.per_ip_per_minute must be positive",
+            "RATE_LIMIT_LOGIN_PER_MINUTE must be a number of requests",
+            "JWT_KEYS entry 2 is not of the form kid:ALG:source",
+            "auth.jwt_keys dev has algorithm \"HS512\"",
+            "storage.mongo_uri (MONGOURI) is required",
+            "uploads.s3.endpoint (S3_ENDPOINT) must be an http or https URL",
+            "uploads.s3.bucket (S3_BUCKET) is required",
+        ] {
+            assert!(message.contains(expected), "{:?} is missing from {}", expected, message);
+        }
+        // the secret of a key never ends up in the message
+        assert!(!message.contains("a secret"));
+    }
+
+    #[test]
+    fn a_named_file_has_to_exist_and_be_valid() {
+        assert_eq!(load(&[("APP_CONFIG", NO_FILE)], NO_FILE).unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        let file = ConfigFile::new(r#"{ "server": { "bind_adress": "0.0.0.0:9000" } }"#);
+        let message = load(&[("APP_CONFIG", &file.path())], NO_FILE).unwrap_err().to_string();
+        assert!(message.contains("unknown field `bind_adress`"), "{}", message);
+    }
+}
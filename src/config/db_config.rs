@@ -1,45 +1,86 @@
 use mongodb::{Client, Database};
-use std::env;
-extern crate dotenv;
-use dotenv::dotenv;
+use serde::Deserialize;
+use sqlx::{any::{install_default_drivers, AnyPoolOptions}, AnyPool};
+use std::io;
 
+use crate::config::app_config::StorageConfig;
 
 
-pub struct DBConfig {
-    db:Database
+// Storage used by the repositories, `storage.backend` of the config (defaults to mongo)
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    Mongo,
+    Memory,
+    Sql
 }
 
-impl DBConfig {
-    pub async fn init() -> Database {
-        dotenv().ok();
+impl StorageBackend {
+    pub fn parse(name:&str) -> Option<Self> {
+        match name {
+            v if v.eq_ignore_ascii_case("mongo") => Some(StorageBackend::Mongo),
+            v if v.eq_ignore_ascii_case("memory") => Some(StorageBackend::Memory),
+            v if v.eq_ignore_ascii_case("sql") => Some(StorageBackend::Sql),
+            _ => None,
+        }
+    }
+}
 
-        let uri = match env::var("MONGOURI") {
-          Ok(v) => v.to_string(),
-          Err(_) => format!("Error loading env variable"),  
-        };
+// What happens to an author's books when the author is deleted, `storage.author_delete_policy`
+// of the config (defaults to reject)
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthorDeletePolicy {
+    Reject,
+    Cascade,
+    Nullify
+}
 
-        let client = Client::with_uri_str(uri).await.unwrap();
-        let db = client.database("rustDB");
-        db
-        // let col:Collection<User> = db.collection("User");
-        // DBConfig {col}
+impl AuthorDeletePolicy {
+    pub fn parse(name:&str) -> Option<Self> {
+        match name {
+            v if v.eq_ignore_ascii_case("reject") => Some(AuthorDeletePolicy::Reject),
+            v if v.eq_ignore_ascii_case("cascade") => Some(AuthorDeletePolicy::Cascade),
+            v if v.eq_ignore_ascii_case("nullify") => Some(AuthorDeletePolicy::Nullify),
+            _ => None,
+        }
     }
+}
 
-    pub async fn connection(&mut self) {
-        dotenv().ok();
+// Mongo storage, `storage.mongo_uri` and `storage.mongo_db_name` of the config
+pub struct DBConfig;
 
-        let uri = match env::var("MONGOURI") {
-          Ok(v) => v.to_string(),
-          Err(_) => format!("Error loading env variable"),  
+impl DBConfig {
+    pub async fn init(config:&StorageConfig) -> io::Result<Database> {
+        let uri = match &config.mongo_uri {
+          Some(v) => v.to_string(),
+          None => return Err(io::Error::new(io::ErrorKind::NotFound, "storage.mongo_uri is not set")),
         };
 
-        let client = Client::with_uri_str(uri).await.unwrap();
-        let db = client.database("rustDB");
-        self.db = db
+        let client = Client::with_uri_str(uri).await.map_err(|e| io::Error::other(e.to_string()))?;
+        Ok(client.database(&config.mongo_db_name))
     }
+}
+
+// Relational storage, `storage.database_url` is either sqlite://<file>?mode=rwc or postgres://...
+pub struct SqlDBConfig;
+
+impl SqlDBConfig {
+    pub async fn init(config:&StorageConfig) -> io::Result<AnyPool> {
+        install_default_drivers();
+
+        let pool = AnyPoolOptions::new()
+            .max_connections(5)
+            .connect(&config.database_url)
+            .await
+            .map_err(|e| io::Error::other(e.to_string()))?;
 
-    // pub async fn get_collection(collection_name:String) -> Collation<T> {
-    //     let col = Self::db.collection(collection_name);
-    //     col
-    // }
-}
\ No newline at end of file
+        // apply pending schema migrations before serving any request
+        sqlx::migrate!("./migrations")
+            .run(&pool)
+            .await
+            .map_err(|e| io::Error::other(e.to_string()))?;
+
+        Ok(pool)
+    }
+}
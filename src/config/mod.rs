@@ -1 +1,2 @@
-pub mod db_config;
\ No newline at end of file
+pub mod db_config;
+pub mod app_config;
\ No newline at end of file
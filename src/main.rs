@@ -1,10 +1,5 @@
 
-use actix_web::{HttpResponse, Responder};
-use actix_web::{middleware::Logger, web::Data, App, HttpServer, web,web::Path
-};
-use std::io::Write;
-use actix_multipart::Multipart;
-use futures::StreamExt;
+use actix_web::{http::Method, middleware::Logger, web::Data, App, HttpServer, web};
 mod api;
 mod model;
 mod repository;
@@ -15,64 +10,55 @@ pub mod config;
 use repository::mongodb_repo::MongoRepo;
 use repository::book_repo::BookRepo;
 use  repository::*;
-use routers::{user_router::user_router, book_router::book_router, author_router::author_router};
-use config::db_config::DBConfig;
-use std::fs::File;
-use std::io::Read;
+use repository::memory_repo::{MemoryApiKeyRepo, MemoryAuthorRepo, MemoryBlobRepo, MemoryBookRepo, MemoryLoginAttemptRepo, MemoryPasswordResetRepo, MemoryTokenRevocationRepo, MemoryUserRepo};
+use repository::sql_repo::{SqlApiKeyRepo, SqlAuthorRepo, SqlBlobRepo, SqlBookRepo, SqlLoginAttemptRepo, SqlPasswordResetRepo, SqlTokenRevocationRepo, SqlUserRepo};
+use repository::store::{ApiKeyStore, AuthorStore, BlobStore, BookStore, FileStorage, LoginAttemptStore, PasswordResetStore, TokenRevocationStore, UserStore};
+use repository::search_index::SearchIndex;
+use utils::mailer::{DevMailer, Mailer};
+use repository::health::{FileStorageProbe, HealthProbe, MongoProbe, ReadinessChecks, SqlProbe};
+use repository::{file_storage::LocalFileStorage, s3_storage::S3FileStorage};
+use middleware::{auth_middleware::Authentication, jwt_config::TokenAuthentication, rate_limit::{Quota, RateLimit}, role_guard::RequireRole};
+use repository::indexed_repo::{rebuild_index, IndexedAuthorStore, IndexedBookStore};
+use routers::{user_router::user_router, book_router::book_router, author_router::author_router, search_router::search_router, jwks_router::jwks_router, api_key_router::api_key_router, health_router::health_router};
+use api::{app_errors::AppError, file_api::{get_file, upload_files}};
+use model::{api_key_model::ApiScope, user_model::Role};
+use config::app_config::{AppConfig, FileBackend};
+use config::db_config::{DBConfig, SqlDBConfig, StorageBackend};
+use std::{fs, sync::Arc};
 
-async fn handle_multipart(mut payload: Multipart) -> impl Responder {
-    // Iterate over multipart fields
-    println!("handle multipart called..");
-    if let Some(item) = payload.next().await {
-        let mut field = item.expect("Failed to get next field from multipart request");
-        
-        let content_disposition = field.content_disposition();
-        
-        let file_name = content_disposition.get_filename();
-        println!("TEST")
-        if let Some(file_val) = file_name {
-            let filepath = format!("./tmp/{}", file_val);
+// Registrations always start out as readers, so the first admin comes from `auth.admin_user`
+// (ADMIN_USER): that existing account is given the admin role on startup
+async fn bootstrap_admin(users:&dyn UserStore, admin_user:Option<&str>) -> Result<(), AppError> {
+    let name = match admin_user {
+        Some(name) => name,
+        None => return Ok(()),
+    };
 
-            match  std::fs::File::create(filepath) {
-                Ok(mut file) => {
-                    while let Some(chunk) = field.next().await {
-                        let data = chunk.expect("failed to read filed data chunk");
-                        let err = file.write_all(&data);
-                        println!("File Write Error : {:?}", err);
-                        println!("File Name : {:#?}", file)
-                    }
-                },
-                Err(e) => {
-                    println!("{:?}",e.to_string())
-                },
-            }
-        }
+    match users.get_user_by_name(name).await {
+        Ok(user) => {
+            users.set_role(&user.id.unwrap_or_default().to_hex(), Role::Admin).await?;
+            log::info!("user {} has the admin role", name);
+        },
+        Err(AppError::NotFoundError(_)) => log::warn!("ADMIN_USER {} does not exist, no admin was set up", name),
+        Err(err) => return Err(err),
     }
-    HttpResponse::Ok().finish()
+    Ok(())
 }
 
 
-async fn index(path:Path<(String,String)>) -> HttpResponse {
-
-    let (dirctory,file_name) = path.into_inner();
-    let file_path = format!("./{}/{}",dirctory, file_name);
-    let mut file = match File::open(file_path) {
-        Ok(file) => file,
-        Err(e) => return HttpResponse::NotFound().body(e.to_string()),
-    };
-    
-    let mut buffer = Vec::new();
-    if let Err(_) = file.read_to_end(&mut buffer) {
-        return HttpResponse::InternalServerError().finish();
-    }
-
-    // Return the image in the response body
-    HttpResponse::Ok()
-        .content_type("image/jpeg")
-        .body(buffer)
+// the stores of the configured backend, and the probe telling whether it is reachable
+struct Stores {
+    books:Arc<dyn BookStore>,
+    users:Arc<dyn UserStore>,
+    authors:Arc<dyn AuthorStore>,
+    api_keys:Arc<dyn ApiKeyStore>,
+    blobs:Arc<dyn BlobStore>,
+    revocations:Arc<dyn TokenRevocationStore>,
+    password_resets:Arc<dyn PasswordResetStore>,
+    login_attempts:Arc<dyn LoginAttemptStore>,
+    probe:Option<Box<dyn HealthProbe>>,
 }
 
-
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     if std::env::var_os("RUST_LOG").is_none() {
@@ -80,32 +66,175 @@ async fn main() -> std::io::Result<()> {
     }   
     env_logger::init();
 
+    // printed as is, the Debug output of the error would squash the list into one line
+    let config = match AppConfig::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        },
+    };
 
-    let db = DBConfig::init().await;
-    let book_db = BookRepo::init(db.clone()).await;
-    let user_db = MongoRepo::init(db.clone()).await;
-    let author_db = author_repo::AuthorRepo::init(db).await;
-    let db_book_db = Data::new(book_db);
-    let db_user_db = Data::new(user_db);
-    let db_author = Data::new(author_db);
+    let stores = match config.storage.backend {
+        StorageBackend::Mongo => {
+            let db = DBConfig::init(&config.storage).await?;
+            let startup_error = |e:AppError| std::io::Error::other(e.to_string());
+            Stores {
+                books: Arc::new(BookRepo::init(db.clone()).await),
+                users: Arc::new(MongoRepo::init(db.clone()).await.map_err(startup_error)?),
+                authors: Arc::new(author_repo::AuthorRepo::init(db.clone()).await),
+                api_keys: Arc::new(api_key_repo::ApiKeyRepo::init(db.clone()).await),
+                blobs: Arc::new(blob_repo::BlobRepo::init(db.clone()).await),
+                revocations: Arc::new(token_revocation_repo::TokenRevocationRepo::init(db.clone()).await.map_err(startup_error)?),
+                password_resets: Arc::new(password_reset_repo::PasswordResetRepo::init(db.clone()).await.map_err(startup_error)?),
+                login_attempts: Arc::new(login_attempt_repo::LoginAttemptRepo::init(db.clone()).await.map_err(startup_error)?),
+                probe: Some(Box::new(MongoProbe::init(db))),
+            }
+        },
+        StorageBackend::Memory => {
+            println!("Using in-memory storage, data will be lost on shutdown");
+            Stores {
+                books: Arc::new(MemoryBookRepo::init()),
+                users: Arc::new(MemoryUserRepo::init()),
+                authors: Arc::new(MemoryAuthorRepo::init()),
+                api_keys: Arc::new(MemoryApiKeyRepo::init()),
+                blobs: Arc::new(MemoryBlobRepo::init()),
+                revocations: Arc::new(MemoryTokenRevocationRepo::init()),
+                password_resets: Arc::new(MemoryPasswordResetRepo::init()),
+                login_attempts: Arc::new(MemoryLoginAttemptRepo::init()),
+                probe: None,
+            }
+        },
+        StorageBackend::Sql => {
+            let pool = SqlDBConfig::init(&config.storage).await?;
+            Stores {
+                books: Arc::new(SqlBookRepo::init(pool.clone())),
+                users: Arc::new(SqlUserRepo::init(pool.clone())),
+                authors: Arc::new(SqlAuthorRepo::init(pool.clone())),
+                api_keys: Arc::new(SqlApiKeyRepo::init(pool.clone())),
+                blobs: Arc::new(SqlBlobRepo::init(pool.clone())),
+                revocations: Arc::new(SqlTokenRevocationRepo::init(pool.clone())),
+                password_resets: Arc::new(SqlPasswordResetRepo::init(pool.clone())),
+                login_attempts: Arc::new(SqlLoginAttemptRepo::init(pool.clone())),
+                probe: Some(Box::new(SqlProbe::init(pool))),
+            }
+        },
+    };
+    let Stores { books: book_db, users: user_db, authors: author_db, api_keys: api_key_db, blobs: blob_db, revocations, password_resets, login_attempts, probe: storage_probe } = stores;
+
+    bootstrap_admin(user_db.as_ref(), config.auth.admin_user.as_deref())
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    // search index is filled from the stored data, then kept up to date by the indexed stores
+    let search_index = Arc::new(SearchIndex::init());
+    rebuild_index(&search_index, book_db.as_ref(), author_db.as_ref())
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    let book_db: Arc<dyn BookStore> = Arc::new(IndexedBookStore::init(book_db, search_index.clone()));
+    let author_db: Arc<dyn AuthorStore> = Arc::new(IndexedAuthorStore::init(author_db, search_index.clone()));
+
+    let db_book_db: Data<dyn BookStore> = Data::from(book_db);
+    let db_user_db: Data<dyn UserStore> = Data::from(user_db);
+    let db_author: Data<dyn AuthorStore> = Data::from(author_db);
+    let db_api_key: Data<dyn ApiKeyStore> = Data::from(api_key_db);
+    let db_blobs: Data<dyn BlobStore> = Data::from(blob_db);
+    let db_search = Data::from(search_index);
+    let author_delete_policy = Data::new(config.storage.author_delete_policy);
+    let password_resets: Data<dyn PasswordResetStore> = Data::from(password_resets);
+    let token_revocations: Data<dyn TokenRevocationStore> = Data::from(revocations);
+    let login_attempts: Data<dyn LoginAttemptStore> = Data::from(login_attempts);
+    let mailer: Data<dyn Mailer> = Data::from(Arc::new(DevMailer::init(config.auth.log_reset_tokens)) as Arc<dyn Mailer>);
+    let token_authentication = Data::new(TokenAuthentication::from_config(&config.auth)?);
+    // uploads arrive there with either backend
+    fs::create_dir_all(config.uploads.staging_dir())?;
+    let file_storage:Arc<dyn FileStorage> = match config.uploads.backend {
+        FileBackend::Local => Arc::new(LocalFileStorage::init(&config.uploads.dir)?),
+        FileBackend::S3 => Arc::new(S3FileStorage::init(&config.uploads.s3)?),
+    };
+    // in-memory storage can't be unreachable, so it has no probe
+    let mut probes:Vec<Box<dyn HealthProbe>> = storage_probe.into_iter().collect();
+    probes.push(Box::new(FileStorageProbe::init(file_storage.clone())));
+    let db_files: Data<dyn FileStorage> = Data::from(file_storage);
+    let readiness_checks = Data::new(ReadinessChecks::init(probes));
+    let bind_address = config.server.bind_address.clone();
+    let app_config = Data::new(config);
     
     
+    // everything else needs a token
+    let authentication = Authentication::new()
+        .public(Method::POST, "/user/user")
+        .public(Method::POST, "/user/login")
+        .public(Method::POST, "/user/token/refresh")
+        .public(Method::POST, "/user/password/forgot")
+        .public(Method::POST, "/user/password/reset")
+        .public(Method::GET, "/.well-known/jwks.json")
+        .public(Method::GET, "/healthz")
+        .public(Method::GET, "/readyz")
+        .optional(Method::GET, "/get_file/{key}")
+        .optional(Method::GET, "/author/get_file/{key}")
+        .optional(Method::HEAD, "/get_file/{key}")
+        .optional(Method::HEAD, "/author/get_file/{key}")
+        // machine clients with an `X-API-Key` only reach books and authors
+        .api_key_scopes("/book/{tail}*", ApiScope::BooksRead, ApiScope::BooksWrite)
+        .api_key_scopes("/author/{tail}*", ApiScope::AuthorsRead, ApiScope::AuthorsWrite);
+
+    // the endpoints taking credentials get tight limits per address, and every address a
+    // generous one, counted before authentication so guessing tokens or keys is limited too
+    let limits = &app_config.rate_limits;
+    let ip_rate_limit = RateLimit::new()
+        .per_ip(Some(Method::POST), "/user/login", Quota::per_minute(limits.login_per_minute))
+        .per_ip(Some(Method::POST), "/user/user", Quota::per_hour(limits.register_per_hour))
+        .per_ip(Some(Method::POST), "/user/password/forgot", Quota::per_hour(limits.password_reset_per_hour))
+        .per_ip(Some(Method::POST), "/user/password/reset", Quota::per_hour(limits.password_reset_per_hour))
+        .per_ip(Some(Method::POST), "/user/token/refresh", Quota::per_minute(limits.refresh_per_minute))
+        .per_ip(None, "/{tail}*", Quota::per_minute(limits.per_ip_per_minute));
+    // once the caller is known, each of them gets its own budget
+    let user_rate_limit = RateLimit::new()
+        .per_user(None, "/{tail}*", Quota::per_minute(limits.per_user_per_minute));
+
     println!("🚀 Server started successfully");
 
     HttpServer::new(move || {
         App::new()
-            .wrap(middleware::auth_middleware::Authentication)
+            // the last wrap runs first: addresses are limited, then authenticated, then
+            // limited per caller
+            .wrap(user_rate_limit.clone())
+            .wrap(authentication.clone())
+            .wrap(ip_rate_limit.clone())
             .app_data(db_book_db.clone())
             .app_data(db_user_db.clone())
             .app_data(db_author.clone())
+            .app_data(db_api_key.clone())
+            .app_data(db_files.clone())
+            .app_data(db_blobs.clone())
+            .app_data(db_search.clone())
+            .app_data(author_delete_policy.clone())
+            .app_data(password_resets.clone())
+            .app_data(token_revocations.clone())
+            .app_data(login_attempts.clone())
+            .app_data(mailer.clone())
+            .app_data(app_config.clone())
+            .app_data(readiness_checks.clone())
+            .app_data(token_authentication.clone())
+            // malformed bodies, paths and query strings get the same error body as the handlers
+            .app_data(web::JsonConfig::default().error_handler(|err, _| AppError::ValidationError(err.to_string()).into()))
+            .app_data(web::PathConfig::default().error_handler(|err, _| AppError::ValidationError(err.to_string()).into()))
+            .app_data(web::QueryConfig::default().error_handler(|err, _| AppError::ValidationError(err.to_string()).into()))
             .service(user_router())
-            .service(book_router())
-            .service(author_router())
-            .route("/upload", web::post().to(handle_multipart))
-            .route("/get_file/{dirc}/{file_name}", web::get().to(index))
+            // anyone signed in can read books and authors, changing them needs an editor
+            .service(book_router().wrap(RequireRole::for_writes(Role::Editor)))
+            .service(author_router().wrap(RequireRole::for_writes(Role::Editor)))
+            .service(search_router())
+            .service(jwks_router())
+            .service(api_key_router())
+            .configure(health_router)
+            .route("/upload", web::post().to(upload_files))
+            .route("/get_file/{key}", web::get().to(get_file))
+            .route("/get_file/{key}", web::head().to(get_file))
             .wrap(Logger::default())
     })
-    .bind(("127.0.0.1", 8000))?
+    .bind(bind_address)?
     .run()
     .await
 }
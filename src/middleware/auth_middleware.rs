@@ -1,15 +1,169 @@
-use std::{future::{ready, Future, Ready}, pin::Pin};
+use std::{future::{ready, Ready}, rc::Rc};
 
 use actix_web::{
-    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform}, error::ErrorUnauthorized, Error
+    dev::{forward_ready, ResourceDef, Service, ServiceRequest, ServiceResponse, Transform}, http::{header, Method}, web::Data, Error, HttpMessage, HttpRequest
 };
-use crate::middleware::jwt_config;
+use futures::future::LocalBoxFuture;
+use mongodb::bson;
+use crate::{api::app_errors::AppError, model::{api_key_model::{ApiKey, ApiScope}, user_model::Role}};
+use crate::repository::store::{ApiKeyStore, TokenRevocationStore};
+use crate::middleware::{auth_user::{AuthUser, Credential}, jwt_config::{TokenAuthentication, TokenKind}};
 
-pub struct Authentication;
+// cookie the access token is read from when there is no `Authorization` header
+pub const ACCESS_TOKEN_COOKIE:&str = "access_token";
+
+pub const API_KEY_HEADER:&str = "X-API-Key";
+
+// `last_used_at` of an API key is only written when it is older than this, in milliseconds
+const API_KEY_LAST_USED_PRECISION:i64 = 60 * 1000;
+
+// How a route listed with `Authentication` may be called, routes not listed need a token
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    // no token needed, one that is sent anyway is ignored
+    Public,
+    // works without a token, a valid one still identifies the caller
+    Optional,
+}
+
+#[derive(Clone)]
+struct AccessRule {
+    method:Method,
+    path:ResourceDef,
+    access:Access,
+}
+
+// Scopes an API key needs on matching routes, `read` for GET and HEAD, `write` otherwise
+#[derive(Clone)]
+struct ApiKeyRule {
+    path:ResourceDef,
+    read:ApiScope,
+    write:ApiScope,
+}
+
+// The token in the request: `Authorization: Bearer <token>` (RFC 6750), a bare token in the
+// header as older clients send it, or the cookie set at login for browser clients
+fn request_token(req:&HttpRequest) -> Result<Option<String>, AppError> {
+    if let Some(value) = req.headers().get(header::AUTHORIZATION) {
+        let value = value
+            .to_str()
+            .map_err(|_| AppError::UnauthorizedError("UnAuthorised Request".to_string()))?
+            .trim();
+        let token = match value.split_once(' ') {
+            Some((scheme, token)) if scheme.eq_ignore_ascii_case("bearer") => token.trim(),
+            Some(_) => return Err(AppError::UnauthorizedError("unsupported authorization scheme".to_string())),
+            None => value,
+        };
+        return Ok(Some(token.to_string()));
+    }
+    Ok(req.cookie(ACCESS_TOKEN_COOKIE).map(|cookie| cookie.value().to_string()))
+}
+
+// Caller identified by an access token. Refresh tokens are not accepted here, and neither
+// are tokens whose id, session or user has been revoked.
+async fn authenticate(req:&HttpRequest, token:&str) -> Result<AuthUser, AppError> {
+    let claims = req
+        .app_data::<Data<TokenAuthentication>>()
+        .ok_or(AppError::InternalError("token authentication is not configured".to_string()))?
+        .validate_token(token)?;
+    if claims.kind != TokenKind::Access {
+        return Err(AppError::UnauthorizedError("access token required".to_string()));
+    }
+
+    let revocations = req
+        .app_data::<Data<dyn TokenRevocationStore>>()
+        .ok_or(AppError::InternalError("token revocation is not configured".to_string()))?;
+    let user_id = claims.user.as_ref().map(|user| user.id.as_str()).unwrap_or_default();
+    let revoked = revocations.is_token_revoked(&claims.jti).await?
+        || revocations.is_token_revoked(&claims.sid).await?
+        || revocations.is_user_revoked(user_id, claims.iat).await?;
+    if revoked {
+        return Err(AppError::UnauthorizedError("token has been revoked".to_string()));
+    }
+    AuthUser::from_claims(claims).ok_or(AppError::UnauthorizedError("invalid token".to_string()))
+}
+
+// Caller identified by an API key, which needs the scope the route asks of keys. Routes
+// without an API key rule do not take keys at all.
+async fn authenticate_api_key(req:&HttpRequest, key:&str, required:Option<ApiScope>) -> Result<AuthUser, AppError> {
+    let required = required.ok_or(AppError::ForbiddenError("API keys can not be used on this route".to_string()))?;
+    let store = req
+        .app_data::<Data<dyn ApiKeyStore>>()
+        .ok_or(AppError::InternalError("API keys are not configured".to_string()))?;
+
+    let api_key = match store.get_api_key_by_hash(&ApiKey::hash(key)).await {
+        Ok(api_key) if !api_key.revoked => api_key,
+        Ok(_) | Err(AppError::NotFoundError(_)) => return Err(AppError::UnauthorizedError("invalid API key".to_string())),
+        Err(err) => return Err(err),
+    };
+    if !api_key.scopes.contains(&required) {
+        return Err(AppError::ForbiddenError(format!("API key lacks the {} scope", required.as_str())));
+    }
+
+    let id = api_key.id.unwrap_or_default();
+    let now = bson::DateTime::now();
+    let stale = match api_key.last_used_at {
+        Some(used_at) => now.timestamp_millis() - used_at.timestamp_millis() > API_KEY_LAST_USED_PRECISION,
+        None => true,
+    };
+    if stale {
+        store.set_api_key_last_used(id, now).await?;
+    }
+    Ok(AuthUser { id: api_key.created_by, role: Role::Reader, credential: Credential::ApiKey(id.to_hex()) })
+}
+
+async fn identify(req:&HttpRequest, access:Option<Access>, api_scope:Option<ApiScope>) -> Result<Option<AuthUser>, AppError> {
+    if let Some(key) = req.headers().get(API_KEY_HEADER) {
+        let key = key
+            .to_str()
+            .map_err(|_| AppError::UnauthorizedError("invalid API key".to_string()))?;
+        return authenticate_api_key(req, key, api_scope).await.map(Some);
+    }
+
+    match request_token(req)? {
+        Some(token) => authenticate(req, &token).await.map(Some),
+        None if access == Some(Access::Optional) => Ok(None),
+        None => Err(AppError::UnauthorizedError("Token not found".to_string())),
+    }
+}
+
+// Checks the token of every request except the routes allowed through with `public` or
+// `optional`. Paths use the route pattern syntax, e.g. `/user/user/{id}`, and a whole scope
+// can be listed with a tail segment such as `/files/{tail}*`.
+#[derive(Clone, Default)]
+pub struct Authentication {
+    rules:Vec<AccessRule>,
+    api_key_rules:Vec<ApiKeyRule>,
+}
+
+impl Authentication {
+    pub fn new() -> Self {
+        Authentication::default()
+    }
+
+    pub fn public(self, method:Method, path:&str) -> Self {
+        self.allow(method, path, Access::Public)
+    }
+
+    pub fn optional(self, method:Method, path:&str) -> Self {
+        self.allow(method, path, Access::Optional)
+    }
+
+    // Routes under `path` also take an `X-API-Key`, with `read` or `write` among its scopes
+    pub fn api_key_scopes(mut self, path:&str, read:ApiScope, write:ApiScope) -> Self {
+        self.api_key_rules.push(ApiKeyRule { path: ResourceDef::new(path), read, write });
+        self
+    }
+
+    fn allow(mut self, method:Method, path:&str, access:Access) -> Self {
+        self.rules.push(AccessRule { method, path: ResourceDef::new(path), access });
+        self
+    }
+}
 
 impl<S,B> Transform<S, ServiceRequest> for Authentication 
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
@@ -20,73 +174,122 @@ where
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(AuthenticationMiddleware { service }))
+        ready(Ok(AuthenticationMiddleware {
+            service: Rc::new(service),
+            rules: self.rules.clone(),
+            api_key_rules: self.api_key_rules.clone(),
+        }))
     }
 }
 
 pub struct AuthenticationMiddleware<S>{
-    service:S
+    service:Rc<S>,
+    rules:Vec<AccessRule>,
+    api_key_rules:Vec<ApiKeyRule>,
 }
 
-type LocalBoxFuture<T> = Pin<Box<dyn Future<Output = T> + 'static>>;
+impl<S> AuthenticationMiddleware<S> {
+    fn access(&self, req:&ServiceRequest) -> Option<Access> {
+        self.rules
+            .iter()
+            .find(|rule| rule.method == req.method() && rule.path.is_match(req.path()))
+            .map(|rule| rule.access)
+    }
+
+    fn api_scope(&self, req:&ServiceRequest) -> Option<ApiScope> {
+        let is_read = matches!(*req.method(), Method::GET | Method::HEAD);
+        self.api_key_rules
+            .iter()
+            .find(|rule| rule.path.is_match(req.path()))
+            .map(|rule| if is_read { rule.read } else { rule.write })
+    }
+}
 
 impl<S,B> Service<ServiceRequest> for AuthenticationMiddleware<S> 
 where
-    S:Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S:Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
     type Response = ServiceResponse<B>;
     type Error = Error;
-    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
 
     forward_ready!(service);
 
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
         println!("req path : {}", req.path());
-        if req.path() != "/user/login"  {
-            let verify = req.headers().get("Authorization");
+        let access = self.access(&req);
+        let api_scope = self.api_scope(&req);
+        let service = self.service.clone();
 
-            if verify.is_none() {
-                return Box::pin(async move{
-                    Err(ErrorUnauthorized("Token not found"))
-                });
-            }
-    
-            if let Some(auth_data) = verify {
-                let data = auth_data.to_str();
-                match data {
-                    Ok(auth_val) => {
-                        let header_val = format!("{}", auth_val);
-                        let token_author = jwt_config::TokenAuthentication::init();
-    
-                        match token_author.validate_token(&header_val) {
-                            Ok(_) => {},
-                            Err(e) => {
-                                return Box::pin(async move{
-                                    Err(ErrorUnauthorized(e.to_string()))
-                                });
-                            },
-                        }
-                    },
-                    Err(_) => {
-                        return Box::pin(async move {
-                            Err(ErrorUnauthorized("UnAuthorised Request"))
-                        });
-                    }
+        Box::pin(async move {
+            if access != Some(Access::Public) {
+                // picked up by the role guards and the `AuthUser` extractor
+                if let Some(user) = identify(req.request(), access, api_scope).await? {
+                    req.extensions_mut().insert(user);
                 }
             }
-    
-        }
+            service.call(req).await
+        })
+    }
+}
 
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
 
-        let fut = self.service.call(req);
+    use actix_web::test::TestRequest;
+    use chrono::Utc;
 
-        Box::pin(async move{
-            let res = fut.await?;
-            Ok(res)
-        })
+    use crate::config::app_config::{AuthConfig, JwtKeyConfig};
+    use crate::model::user_model::UserData;
+    use crate::repository::memory_repo::MemoryTokenRevocationRepo;
+    use super::*;
+
+    fn request(tokens:TokenAuthentication) -> HttpRequest {
+        let revocations:Data<dyn TokenRevocationStore> = Data::from(Arc::new(MemoryTokenRevocationRepo::init()) as Arc<dyn TokenRevocationStore>);
+        TestRequest::default().app_data(Data::new(tokens)).app_data(revocations).to_http_request()
+    }
+
+    fn tokens() -> TokenAuthentication {
+        let config = AuthConfig {
+            jwt_keys: vec![JwtKeyConfig { kid: "dev".to_string(), algorithm: "HS256".to_string(), source: "a test secret that is long enough for HS256".to_string() }],
+            ..AuthConfig::default()
+        };
+        TokenAuthentication::from_config(&config).unwrap()
+    }
 
+    fn user() -> UserData {
+        UserData { id: "user".to_string(), name: "ann".to_string(), location: "here".to_string(), title: "dev".to_string(), role: Role::Reader, access_token: None, refresh_token: None }
     }
-}
\ No newline at end of file
+
+    #[actix_web::test]
+    async fn refresh_tokens_are_no_access_tokens() {
+        let tokens = tokens();
+        let access = tokens.generate_token(&user(), TokenKind::Access, "session", Utc::now().timestamp()).unwrap();
+        let refresh = tokens.generate_token(&user(), TokenKind::Refresh, "session", Utc::now().timestamp()).unwrap();
+        let req = request(tokens);
+
+        assert_eq!(authenticate(&req, &access).await.unwrap().id, "user");
+        assert!(matches!(authenticate(&req, &refresh).await, Err(AppError::UnauthorizedError(msg)) if msg.contains("access token")));
+    }
+
+    #[actix_web::test]
+    async fn revoked_sessions_and_users_are_refused() {
+        let tokens = tokens();
+        let access = tokens.generate_token(&user(), TokenKind::Access, "session", Utc::now().timestamp()).unwrap();
+        let req = request(tokens);
+        let revocations = req.app_data::<Data<dyn TokenRevocationStore>>().unwrap();
+
+        revocations.revoke_token("session", Utc::now().timestamp() + 60).await.unwrap();
+        assert!(authenticate(&req, &access).await.is_err());
+
+        // a fresh store, so only the user is revoked this time
+        let req = request(self::tokens());
+        let revocations = req.app_data::<Data<dyn TokenRevocationStore>>().unwrap();
+        revocations.revoke_user("user", Utc::now().timestamp() + 60).await.unwrap();
+        assert!(authenticate(&req, &access).await.is_err());
+    }
+}
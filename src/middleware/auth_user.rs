@@ -0,0 +1,50 @@
+use std::future::{ready, Ready};
+
+use actix_web::{dev::Payload, FromRequest, HttpMessage, HttpRequest};
+
+use crate::{api::app_errors::AppError, middleware::jwt_config::TokenCliams, model::user_model::{Role, UserData}};
+
+// The caller of an authenticated request. `Authentication` builds it from the access token
+// and leaves it in the request extensions, handlers take it as an argument.
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    // for an API key, the admin who created it
+    pub id:String,
+    // not checked for API keys, their scopes are
+    pub role:Role,
+    pub credential:Credential,
+}
+
+#[derive(Debug, Clone)]
+pub enum Credential {
+    // access token, with the session it belongs to
+    Session(String),
+    // `X-API-Key`, with the id of the key
+    ApiKey(String),
+}
+
+impl AuthUser {
+    pub fn from_claims(claims:TokenCliams<UserData>) -> Option<Self> {
+        let user = claims.user?;
+        Some(AuthUser { id: user.id, role: claims.role, credential: Credential::Session(claims.sid) })
+    }
+
+    // admins may act on any account, everybody else only on their own
+    pub fn can_manage_user(&self, user_id:&str) -> bool {
+        self.role == Role::Admin || self.id == user_id
+    }
+}
+
+impl FromRequest for AuthUser {
+    type Error = AppError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req:&HttpRequest, _:&mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<AuthUser>()
+                .cloned()
+                .ok_or(AppError::UnauthorizedError("Token not found".to_string())),
+        )
+    }
+}
@@ -1,41 +1,278 @@
-use chrono::Utc;
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, TokenData, Validation, errors::Error};
+use std::{collections::BTreeMap, io};
 
-use crate::model;
+use jsonwebtoken::{decode, decode_header, encode, errors::ErrorKind, Header, Validation};
+use serde_json::{json, Value};
 
+use crate::{api::app_errors::AppError, config::app_config::AuthConfig, middleware::jwt_keys::{JwtAlgorithm, JwtKey}, model::user_model::{Role, UserData}};
 
+// Every configured key verifies tokens, only `signing_key` signs new ones. Built once at
+// startup and shared with the middleware and handlers through app data.
 pub struct TokenAuthentication{
-    secret_key:String,
-    issued_at:i64,
-    expired_at:i64
+    keys:BTreeMap<String, JwtKey>,
+    // kid of the key new tokens are signed with
+    signing_key:String,
+    // token lifetimes, in seconds
+    access_token_ttl:i64,
+    refresh_token_ttl:i64,
 }
-#[derive(serde::Serialize, serde::Deserialize, Default, Debug)]
-struct TokenCliams<T> {
-    user:Option<T>,
-    iat: i64, 
-    exp: i64, 
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenKind {
+    Access,
+    Refresh,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct TokenCliams<T> {
+    pub user:Option<T>,
+    pub iat: i64,
+    pub exp: i64,
+    // unique per token, revoking it only invalidates this token
+    pub jti: String,
+    // shared by every token issued from the same login, revoking it ends the session
+    pub sid: String,
+    pub kind: TokenKind,
+    // role of the user when the token was issued, a refresh picks up changes
+    pub role: Role,
 }
 
 impl TokenAuthentication {
-    
-    pub fn init() -> Self {
-        let now = Utc::now().timestamp();
-        let expiration = now + 3600;
-        TokenAuthentication { secret_key: "h3evGTWiSjG9rYDstJC4q0eVTTen8aa5Y3UT+Q8p".to_string(), issued_at: now, expired_at: expiration }
+
+    // `AppConfig` has already checked the kids and algorithms, loading can still fail on
+    // the key material itself. The signing key defaults to the first key, so a key can be
+    // rotated out while its tokens still verify.
+    pub fn from_config(config:&AuthConfig) -> io::Result<Self> {
+        let invalid = |msg:String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+
+        let mut keys:BTreeMap<String, JwtKey> = BTreeMap::new();
+        for key in &config.jwt_keys {
+            let algorithm = JwtAlgorithm::parse(&key.algorithm)
+                .ok_or_else(|| invalid(format!("JWT key {}: unsupported algorithm {}", key.kid, key.algorithm)))?;
+            keys.insert(key.kid.clone(), JwtKey::load(&key.kid, algorithm, &key.source)?);
+        }
+
+        let signing_key = match (&config.jwt_signing_key, config.jwt_keys.first()) {
+            (Some(kid), _) if keys.contains_key(kid) => kid.clone(),
+            (Some(kid), _) => return Err(invalid(format!("JWT signing key {} is not configured", kid))),
+            (None, Some(key)) => key.kid.clone(),
+            (None, None) => return Err(invalid("no JWT key is configured".to_string())),
+        };
+        Ok(TokenAuthentication {
+            keys,
+            signing_key,
+            access_token_ttl: config.access_token_ttl,
+            refresh_token_ttl: config.refresh_token_ttl,
+        })
+    }
+
+    pub fn access_token_ttl(&self) -> i64 {
+        self.access_token_ttl
+    }
+
+    pub fn refresh_token_ttl(&self) -> i64 {
+        self.refresh_token_ttl
+    }
+
+    // A token dated `issued_at`, its lifetime counts from then
+    pub fn generate_token(&self, user_data:&UserData, kind:TokenKind, session_id:&str, issued_at:i64) -> Result<String, AppError> {
+        let key = &self.keys[&self.signing_key];
+        let ttl = match kind {
+            TokenKind::Access => self.access_token_ttl,
+            TokenKind::Refresh => self.refresh_token_ttl,
+        };
+        let token_claim = TokenCliams {
+            user: Some(user_data),
+            iat: issued_at,
+            exp: issued_at + ttl,
+            jti: crate::utils::password::random_token(),
+            sid: session_id.to_string(),
+            kind,
+            role: user_data.role,
+        };
+
+        let mut header = Header::new(key.algorithm);
+        header.kid = Some(key.kid.clone());
+        encode(&header, &token_claim, &key.encoding).map_err(|e| AppError::InternalError(e.to_string()))
+    }
+
+    // The key is picked by `kid` and only checks tokens of its own algorithm, so a token can't
+    // ask for a different algorithm than the key was configured with
+    pub fn validate_token(&self, token:&str) -> Result<TokenCliams<UserData>, AppError> {
+        let invalid = || AppError::UnauthorizedError("invalid token".to_string());
+
+        let header = decode_header(token).map_err(|_| invalid())?;
+        let key = header
+            .kid
+            .and_then(|kid| self.keys.get(&kid))
+            .ok_or(AppError::UnauthorizedError("token signed with an unknown key".to_string()))?;
+
+        let mut validation = Validation::new(key.algorithm);
+        validation.leeway = 0;
+        match decode::<TokenCliams<UserData>>(token, &key.decoding, &validation) {
+            Ok(data) => Ok(data.claims),
+            Err(e) if *e.kind() == ErrorKind::ExpiredSignature => Err(AppError::UnauthorizedError("token has expired".to_string())),
+            Err(_) => Err(invalid()),
+        }
+    }
+
+    // JWK set with the public half of every asymmetric key, for services verifying our tokens
+    pub fn jwks(&self) -> Value {
+        let keys:Vec<&Value> = self.keys.values().filter_map(|key| key.jwk.as_ref()).collect();
+        json!({ "keys": keys })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use jsonwebtoken::Algorithm;
+
+    use crate::config::app_config::JwtKeyConfig;
+    use super::*;
+
+    const SECRET:&str = "a test secret that is long enough for HS256";
+
+    fn authentication(kid:&str) -> TokenAuthentication {
+        let config = AuthConfig {
+            jwt_keys: vec![JwtKeyConfig { kid: kid.to_string(), algorithm: "HS256".to_string(), source: SECRET.to_string() }],
+            ..AuthConfig::default()
+        };
+        TokenAuthentication::from_config(&config).unwrap()
+    }
+
+    fn fixture(name:&str) -> String {
+        format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    // an RSA and an Ed25519 key next to a shared secret, signing with `signing_key`
+    fn asymmetric(signing_key:&str) -> TokenAuthentication {
+        let key = |kid:&str, algorithm:&str, source:String| JwtKeyConfig { kid: kid.to_string(), algorithm: algorithm.to_string(), source };
+        let config = AuthConfig {
+            jwt_keys: vec![
+                key("rsa", "RS256", fixture("rsa_pkcs8.pem")),
+                key("ed", "EdDSA", fixture("ed25519.pem")),
+                key("dev", "HS256", SECRET.to_string()),
+            ],
+            jwt_signing_key: Some(signing_key.to_string()),
+            ..AuthConfig::default()
+        };
+        TokenAuthentication::from_config(&config).unwrap()
+    }
+
+    fn user() -> UserData {
+        UserData { id: "user".to_string(), name: "ann".to_string(), location: "here".to_string(), title: "dev".to_string(), role: Role::Reader, access_token: None, refresh_token: None }
+    }
+
+    fn claims(exp:i64) -> TokenCliams<UserData> {
+        TokenCliams { user: Some(user()), iat: Utc::now().timestamp(), exp, jti: "jti".to_string(), sid: "sid".to_string(), kind: TokenKind::Access, role: Role::Reader }
+    }
+
+    fn header(algorithm:Algorithm, kid:&str) -> Header {
+        let mut header = Header::new(algorithm);
+        header.kid = Some(kid.to_string());
+        header
+    }
+
+    #[test]
+    fn accepts_its_own_tokens() {
+        let auth = authentication("dev");
+        let token = auth.generate_token(&user(), TokenKind::Refresh, "session", Utc::now().timestamp()).unwrap();
+
+        let claims = auth.validate_token(&token).unwrap();
+        assert_eq!((claims.kind, claims.sid.as_str()), (TokenKind::Refresh, "session"));
+        assert_eq!(claims.user.unwrap().id, "user");
+    }
+
+    #[test]
+    fn rejects_another_algorithm_than_the_key_has() {
+        let auth = authentication("dev");
+        let exp = Utc::now().timestamp() + 60;
+        let hs512 = encode(&header(Algorithm::HS512, "dev"), &claims(exp), &jsonwebtoken::EncodingKey::from_secret(SECRET.as_bytes())).unwrap();
+        assert!(auth.validate_token(&hs512).is_err());
+
+        let unsigned = format!(
+            "{}.{}.",
+            base64::encode_config(r#"{"alg":"none","kid":"dev"}"#, base64::URL_SAFE_NO_PAD),
+            base64::encode_config(serde_json::to_vec(&claims(exp)).unwrap(), base64::URL_SAFE_NO_PAD),
+        );
+        assert!(auth.validate_token(&unsigned).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_kids() {
+        let token = authentication("old").generate_token(&user(), TokenKind::Access, "session", Utc::now().timestamp()).unwrap();
+        assert!(matches!(authentication("dev").validate_token(&token), Err(AppError::UnauthorizedError(msg)) if msg.contains("unknown key")));
     }
 
-    pub fn generate_token(&self, user_data:&model::user_model::UserData) -> String {
-        let token_claim = TokenCliams { user: Some(user_data), iat: self.issued_at, exp: self.expired_at };
+    #[test]
+    fn rejects_expired_tokens() {
+        let auth = authentication("dev");
+        let token = encode(&header(Algorithm::HS256, "dev"), &claims(Utc::now().timestamp() - 1), &auth.keys["dev"].encoding).unwrap();
+        assert!(matches!(auth.validate_token(&token), Err(AppError::UnauthorizedError(msg)) if msg.contains("expired")));
+    }
+
+    #[test]
+    fn rejects_tampered_tokens() {
+        let auth = authentication("dev");
+        let token = auth.generate_token(&user(), TokenKind::Access, "session", Utc::now().timestamp()).unwrap();
+        let (signing_input, signature) = token.rsplit_once('.').unwrap();
+
+        let mut forged = signature.as_bytes().to_vec();
+        forged[0] = if forged[0] == b'A' { b'B' } else { b'A' };
+        assert!(auth.validate_token(&format!("{}.{}", signing_input, String::from_utf8(forged).unwrap())).is_err());
+
+        // an admin role put into the claims of a valid token
+        let (header, _) = signing_input.split_once('.').unwrap();
+        let mut admin = claims(Utc::now().timestamp() + 60);
+        admin.role = Role::Admin;
+        let payload = base64::encode_config(serde_json::to_vec(&admin).unwrap(), base64::URL_SAFE_NO_PAD);
+        assert!(auth.validate_token(&format!("{}.{}.{}", header, payload, signature)).is_err());
+    }
 
-        let header = Header::new(jsonwebtoken::Algorithm::HS256);
+    #[test]
+    fn signs_and_verifies_with_asymmetric_keys() {
+        for (kid, algorithm) in [("rsa", Algorithm::RS256), ("ed", Algorithm::EdDSA)] {
+            let auth = asymmetric(kid);
+            let token = auth.generate_token(&user(), TokenKind::Access, "session", Utc::now().timestamp()).unwrap();
+            let header = decode_header(&token).unwrap();
+            assert_eq!((header.alg, header.kid.as_deref()), (algorithm, Some(kid)));
+            assert_eq!(auth.validate_token(&token).unwrap().sid, "session");
 
-        match encode(&header, &token_claim, &EncodingKey::from_secret(self.secret_key.as_ref())) {
-            Ok(token) => token,
-            Err(e) => e.to_string(),
+            // verified with the other keys configured as well, after a rotation
+            assert!(asymmetric("dev").validate_token(&token).is_ok());
         }
     }
 
-    pub fn validate_token(&self, token:&str) -> Result<TokenData<serde_json::Value>, Error> {
-        decode::<serde_json::Value>(&token, &DecodingKey::from_secret(&self.secret_key.as_ref()), &Validation::default())
+    #[test]
+    fn publishes_only_the_asymmetric_keys() {
+        let jwks = asymmetric("rsa").jwks();
+        let keys = jwks["keys"].as_array().unwrap();
+        let kids:Vec<&str> = keys.iter().map(|key| key["kid"].as_str().unwrap()).collect();
+        assert_eq!(kids, ["ed", "rsa"]);
+        assert!(keys.iter().all(|key| key.get("d").is_none() && key.get("k").is_none()));
+        assert_eq!(keys[0]["x"].as_str().unwrap().len(), 43);
+        assert_eq!(keys[1]["e"], "AQAB");
     }
-}
\ No newline at end of file
+
+    // A token of another algorithm than its key's, e.g. HS256 keyed with the public RSA key
+    // everyone can fetch, is refused before its signature is looked at
+    #[test]
+    fn rejects_tokens_that_switch_the_algorithm_of_a_key() {
+        let auth = asymmetric("rsa");
+        let exp = Utc::now().timestamp() + 60;
+        let public_pem = std::fs::read(fixture("rsa_public.pem")).unwrap();
+
+        let confused = encode(&header(Algorithm::HS256, "rsa"), &claims(exp), &jsonwebtoken::EncodingKey::from_secret(&public_pem)).unwrap();
+        assert!(auth.validate_token(&confused).is_err());
+
+        let rsa = jsonwebtoken::EncodingKey::from_rsa_pem(&std::fs::read(fixture("rsa_pkcs8.pem")).unwrap()).unwrap();
+        for (algorithm, kid) in [(Algorithm::RS256, "ed"), (Algorithm::PS256, "rsa"), (Algorithm::RS512, "rsa")] {
+            let token = encode(&header(algorithm, kid), &claims(exp), &rsa).unwrap();
+            assert!(auth.validate_token(&token).is_err(), "{:?} {}", algorithm, kid);
+        }
+        // the same claims signed the way the key is configured pass
+        let token = encode(&header(Algorithm::RS256, "rsa"), &claims(exp), &rsa).unwrap();
+        assert!(auth.validate_token(&token).is_ok());
+    }
+}
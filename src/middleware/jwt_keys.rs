@@ -0,0 +1,144 @@
+use std::{fs, io};
+
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use ring::signature::{Ed25519KeyPair, KeyPair, RsaKeyPair};
+use serde_json::{json, Value};
+
+// Signing algorithms a key can be configured with, named as in the JWS `alg` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JwtAlgorithm {
+    HS256,
+    RS256,
+    EdDSA,
+}
+
+impl JwtAlgorithm {
+    pub fn parse(name:&str) -> Option<Self> {
+        match name {
+            "HS256" => Some(JwtAlgorithm::HS256),
+            "RS256" => Some(JwtAlgorithm::RS256),
+            "EdDSA" => Some(JwtAlgorithm::EdDSA),
+            _ => None,
+        }
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        match self {
+            JwtAlgorithm::HS256 => Algorithm::HS256,
+            JwtAlgorithm::RS256 => Algorithm::RS256,
+            JwtAlgorithm::EdDSA => Algorithm::EdDSA,
+        }
+    }
+}
+
+// A key identified by the `kid` put in the header of the tokens it signs. Signing and
+// verifying is left to jsonwebtoken, the private key is only parsed here for its public half.
+pub struct JwtKey {
+    pub kid:String,
+    pub algorithm:Algorithm,
+    pub encoding:EncodingKey,
+    pub decoding:DecodingKey,
+    // public half as a JWK, shared secrets are never published
+    pub jwk:Option<Value>,
+}
+
+impl JwtKey {
+    // `source` is the secret itself for HS256 and the path of a PEM encoded private key
+    // (PKCS#8, or PKCS#1 for RSA) for the asymmetric algorithms
+    pub fn load(kid:&str, algorithm:JwtAlgorithm, source:&str) -> io::Result<Self> {
+        let invalid = |msg:String| io::Error::new(io::ErrorKind::InvalidData, format!("JWT key {}: {}", kid, msg));
+        let encode = |bytes:&[u8]| base64::encode_config(bytes, base64::URL_SAFE_NO_PAD);
+
+        let (encoding, decoding, jwk) = match algorithm {
+            JwtAlgorithm::HS256 => {
+                if source.len() < 32 {
+                    return Err(invalid("HS256 secrets must be at least 32 bytes".to_string()));
+                }
+                (EncodingKey::from_secret(source.as_bytes()), DecodingKey::from_secret(source.as_bytes()), None)
+            },
+            JwtAlgorithm::RS256 => {
+                let bytes = fs::read(source)?;
+                let pem = pem::parse(&bytes).map_err(|e| invalid(e.to_string()))?;
+                let key = match pem.tag.as_str() {
+                    "RSA PRIVATE KEY" => RsaKeyPair::from_der(&pem.contents),
+                    "PRIVATE KEY" => RsaKeyPair::from_pkcs8(&pem.contents),
+                    tag => return Err(invalid(format!("unsupported PEM block {}", tag))),
+                }
+                .map_err(|e| invalid(e.to_string()))?;
+                let (n, e) = (key.public_key().modulus().big_endian_without_leading_zero(), key.public_key().exponent().big_endian_without_leading_zero());
+                let jwk = json!({ "kty": "RSA", "use": "sig", "alg": "RS256", "kid": kid, "n": encode(n), "e": encode(e) });
+                let encoding = EncodingKey::from_rsa_pem(&bytes).map_err(|e| invalid(e.to_string()))?;
+                (encoding, DecodingKey::from_rsa_raw_components(n, e), Some(jwk))
+            },
+            JwtAlgorithm::EdDSA => {
+                let bytes = fs::read(source)?;
+                let pem = pem::parse(&bytes).map_err(|e| invalid(e.to_string()))?;
+                if pem.tag != "PRIVATE KEY" {
+                    return Err(invalid(format!("unsupported PEM block {}", pem.tag)));
+                }
+                let key = Ed25519KeyPair::from_pkcs8_maybe_unchecked(&pem.contents).map_err(|e| invalid(e.to_string()))?;
+                let x = key.public_key().as_ref();
+                let jwk = json!({ "kty": "OKP", "use": "sig", "alg": "EdDSA", "crv": "Ed25519", "kid": kid, "x": encode(x) });
+                let encoding = EncodingKey::from_ed_pem(&bytes).map_err(|e| invalid(e.to_string()))?;
+                (encoding, DecodingKey::from_ed_der(x), Some(jwk))
+            },
+        };
+        Ok(JwtKey { kid: kid.to_string(), algorithm: algorithm.algorithm(), encoding, decoding, jwk })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the public halves of the fixture keys, as printed by openssl
+    const RSA_N:&str = "n39_vjl6DNSiv4MaxGbpx4B-JKx-_1jotzw2QWA7f07HtZ3KG7ZVjubdM2F18Yka25CBNGmw8lN9QocEJhvJURAFWQ8vlaLQ6OZkB1M_d3s5PQIKZ0kSnVf1qJVo_BX-N1ptWNdUmjlY4CC-PsbmuphBYFxoVxiNg9yF5DMYSU-EpULHG2Yzhra9wRIHde5itWrrKJCwNJ0fj-9E3P0zYO5zyLsiOMnPpUNdi2jcS7QU6x3eIL0BKck_DWHn7a-2XOYgcYgvvvEmlNtqhztXQyoG3Fb00u1oQZQMYCrz7MW2teZxE45T5nWLRGdUQ6cVEbcwcf6TkcA7TJ3-3kn-UQ";
+    const ED25519_X:&str = "vnxmCN6p-X9Fp_LkuAltAB1S_D96ZU2cRg7a5pJP7r0";
+
+    fn fixture(name:&str) -> String {
+        format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    #[test]
+    fn publishes_the_public_half_of_rsa_keys() {
+        for file in ["rsa_pkcs1.pem", "rsa_pkcs8.pem"] {
+            let key = JwtKey::load("rsa", JwtAlgorithm::RS256, &fixture(file)).unwrap();
+            assert_eq!(key.algorithm, Algorithm::RS256);
+            assert_eq!(
+                key.jwk.unwrap(),
+                json!({ "kty": "RSA", "use": "sig", "alg": "RS256", "kid": "rsa", "n": RSA_N, "e": "AQAB" }),
+                "{}",
+                file
+            );
+        }
+    }
+
+    #[test]
+    fn publishes_the_public_half_of_ed25519_keys() {
+        let key = JwtKey::load("ed", JwtAlgorithm::EdDSA, &fixture("ed25519.pem")).unwrap();
+        assert_eq!(key.algorithm, Algorithm::EdDSA);
+        assert_eq!(key.jwk.unwrap(), json!({ "kty": "OKP", "use": "sig", "alg": "EdDSA", "crv": "Ed25519", "kid": "ed", "x": ED25519_X }));
+    }
+
+    #[test]
+    fn never_publishes_shared_secrets() {
+        let key = JwtKey::load("dev", JwtAlgorithm::HS256, "a test secret that is long enough for HS256").unwrap();
+        assert!(key.jwk.is_none());
+    }
+
+    #[test]
+    fn refuses_keys_that_do_not_fit_the_algorithm() {
+        let refused = [
+            (JwtAlgorithm::HS256, "too short".to_string()),
+            (JwtAlgorithm::RS256, fixture("ed25519.pem")),
+            (JwtAlgorithm::RS256, fixture("rsa_public.pem")),
+            (JwtAlgorithm::EdDSA, fixture("rsa_pkcs8.pem")),
+            (JwtAlgorithm::EdDSA, fixture("rsa_pkcs1.pem")),
+            (JwtAlgorithm::EdDSA, fixture("ec_p256.pem")),
+            (JwtAlgorithm::EdDSA, fixture("missing.pem")),
+        ];
+        for (algorithm, source) in refused {
+            assert!(JwtKey::load("key", algorithm, &source).is_err(), "{:?} {}", algorithm, source);
+        }
+    }
+}
@@ -1,2 +1,6 @@
 pub mod auth_middleware;
-pub mod jwt_config;
\ No newline at end of file
+pub mod auth_user;
+pub mod jwt_config;
+pub mod jwt_keys;
+pub mod role_guard;
+pub mod rate_limit;
\ No newline at end of file
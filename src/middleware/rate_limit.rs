@@ -0,0 +1,267 @@
+use std::{collections::HashMap, future::{ready, Ready}, sync::{Arc, Mutex, PoisonError}, time::{Duration, Instant}};
+
+use actix_web::{
+    body::EitherBody, dev::{forward_ready, ResourceDef, Service, ServiceRequest, ServiceResponse, Transform}, http::{header::{HeaderMap, HeaderName, HeaderValue}, Method}, Error, HttpMessage, ResponseError
+};
+use futures::future::LocalBoxFuture;
+use crate::{api::app_errors::AppError, middleware::auth_user::{AuthUser, Credential}};
+
+// once there are more buckets than this, the ones that have filled up again are dropped
+const MAX_BUCKETS:usize = 10_000;
+
+// `burst` requests at once, refilled evenly over `period`
+#[derive(Debug, Clone, Copy)]
+pub struct Quota {
+    burst:u32,
+    period:Duration,
+}
+
+impl Quota {
+    pub fn per_minute(burst:u32) -> Self {
+        Quota { burst, period: Duration::from_secs(60) }
+    }
+
+    pub fn per_hour(burst:u32) -> Self {
+        Quota { burst, period: Duration::from_secs(3600) }
+    }
+
+    // tokens per second
+    fn rate(&self) -> f64 {
+        f64::from(self.burst) / self.period.as_secs_f64()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LimitKey {
+    Ip,
+    // the authenticated caller, or the address for anonymous requests
+    User,
+}
+
+#[derive(Clone)]
+struct LimitRule {
+    // None matches every method
+    method:Option<Method>,
+    path:ResourceDef,
+    key:LimitKey,
+    quota:Quota,
+}
+
+struct Bucket {
+    tokens:f64,
+    updated:Instant,
+    // from then on the bucket is the same as a new one
+    full_at:Instant,
+}
+
+impl Bucket {
+    fn new(quota:&Quota, now:Instant) -> Self {
+        Bucket { tokens: f64::from(quota.burst), updated: now, full_at: now }
+    }
+
+    fn refill(&mut self, quota:&Quota, now:Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * quota.rate()).min(f64::from(quota.burst));
+        self.updated = now;
+    }
+
+    fn take(&mut self, quota:&Quota) {
+        self.tokens -= 1.0;
+        self.full_at = self.updated + Duration::from_secs_f64((f64::from(quota.burst) - self.tokens) / quota.rate());
+    }
+}
+
+// What the most constrained bucket of a request looks like, sent back as `RateLimit-*`
+struct Decision {
+    limit:u32,
+    remaining:u32,
+    // seconds until the bucket is full again
+    reset:u64,
+    // set when the request is refused
+    retry_after:Option<u64>,
+}
+
+// Buckets keyed by the position of their rule and the client, shared by every worker
+#[derive(Default)]
+struct Buckets {
+    buckets:Mutex<HashMap<(usize, String), Bucket>>,
+}
+
+impl Buckets {
+    // Takes a token from every bucket of the request, or from none of them when one is empty
+    fn take(&self, limits:&[(usize, Quota, String)]) -> Decision {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        if buckets.len() > MAX_BUCKETS {
+            buckets.retain(|_, bucket| bucket.full_at > now);
+        }
+
+        for (rule, quota, client) in limits {
+            buckets
+                .entry((*rule, client.clone()))
+                .or_insert_with(|| Bucket::new(quota, now))
+                .refill(quota, now);
+        }
+
+        let retry_after = limits
+            .iter()
+            .filter_map(|(rule, quota, client)| {
+                let tokens = buckets[&(*rule, client.clone())].tokens;
+                (tokens < 1.0).then(|| ((1.0 - tokens) / quota.rate()).ceil() as u64)
+            })
+            .max();
+        if retry_after.is_none() {
+            for (rule, quota, client) in limits {
+                if let Some(bucket) = buckets.get_mut(&(*rule, client.clone())) {
+                    bucket.take(quota);
+                }
+            }
+        }
+
+        let (quota, tokens) = limits
+            .iter()
+            .map(|(rule, quota, client)| (*quota, buckets[&(*rule, client.clone())].tokens))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap_or((Quota::per_minute(0), 0.0));
+        Decision {
+            limit: quota.burst,
+            remaining: tokens.max(0.0).floor() as u32,
+            reset: ((f64::from(quota.burst) - tokens) / quota.rate()).ceil() as u64,
+            retry_after,
+        }
+    }
+}
+
+// Token buckets per client address or per caller. Every rule matching a request takes a
+// token from its own bucket, a request is refused with a 429 once one of them is empty.
+// Per user rules only see the caller when wrapped inside `Authentication`, per address
+// rules belong outside of it so requests failing authentication are counted as well.
+#[derive(Clone, Default)]
+pub struct RateLimit {
+    rules:Vec<LimitRule>,
+    buckets:Arc<Buckets>,
+}
+
+impl RateLimit {
+    pub fn new() -> Self {
+        RateLimit::default()
+    }
+
+    // One bucket per client address. Behind a proxy every client shares the proxy's address.
+    pub fn per_ip(self, method:Option<Method>, path:&str, quota:Quota) -> Self {
+        self.limit(method, path, LimitKey::Ip, quota)
+    }
+
+    // One bucket per user or API key, anonymous requests are counted per address
+    pub fn per_user(self, method:Option<Method>, path:&str, quota:Quota) -> Self {
+        self.limit(method, path, LimitKey::User, quota)
+    }
+
+    fn limit(mut self, method:Option<Method>, path:&str, key:LimitKey, quota:Quota) -> Self {
+        self.rules.push(LimitRule { method, path: ResourceDef::new(path), key, quota });
+        self
+    }
+}
+
+impl<S,B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware { service, rules: self.rules.clone(), buckets: self.buckets.clone() }))
+    }
+}
+
+pub struct RateLimitMiddleware<S>{
+    service:S,
+    rules:Vec<LimitRule>,
+    buckets:Arc<Buckets>,
+}
+
+impl<S> RateLimitMiddleware<S> {
+    // the buckets the request takes a token from
+    fn limits(&self, req:&ServiceRequest) -> Vec<(usize, Quota, String)> {
+        let ip = req.peer_addr().map(|addr| addr.ip().to_string()).unwrap_or_default();
+        let caller = req.extensions().get::<AuthUser>().map(|user| match &user.credential {
+            Credential::Session(_) => format!("user:{}", user.id),
+            Credential::ApiKey(key_id) => format!("key:{}", key_id),
+        });
+
+        self.rules
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| rule.method.as_ref().is_none_or(|method| method == req.method()) && rule.path.is_match(req.path()))
+            .map(|(position, rule)| {
+                let client = match (rule.key, &caller) {
+                    (LimitKey::User, Some(caller)) => caller.clone(),
+                    _ => format!("ip:{}", ip),
+                };
+                (position, rule.quota, client)
+            })
+            .collect()
+    }
+}
+
+// draft-ietf-httpapi-ratelimit-headers. With more than one limiter on the way, the headers
+// show whichever bucket has the fewest requests left.
+fn set_headers(headers:&mut HeaderMap, decision:&Decision) {
+    let remaining = headers
+        .get("ratelimit-remaining")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u32>().ok());
+    if remaining.is_some_and(|remaining| remaining <= decision.remaining) {
+        return;
+    }
+    let values = [
+        ("ratelimit-limit", decision.limit),
+        ("ratelimit-remaining", decision.remaining),
+        ("ratelimit-reset", decision.reset as u32),
+    ];
+    for (name, value) in values {
+        headers.insert(HeaderName::from_static(name), HeaderValue::from(value));
+    }
+}
+
+impl<S,B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S:Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let limits = self.limits(&req);
+        if limits.is_empty() {
+            let fut = self.service.call(req);
+            return Box::pin(async move { fut.await.map(ServiceResponse::map_into_left_body) });
+        }
+
+        let decision = self.buckets.take(&limits);
+        if let Some(secs) = decision.retry_after {
+            let err = AppError::TooManyRequestsError(format!("rate limit exceeded, retry in {} seconds", secs), secs);
+            let mut res = req.into_response(err.error_response()).map_into_right_body();
+            set_headers(res.headers_mut(), &decision);
+            return Box::pin(async move { Ok(res) });
+        }
+
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let mut res = fut.await?.map_into_left_body();
+            set_headers(res.headers_mut(), &decision);
+            Ok(res)
+        })
+    }
+}
@@ -0,0 +1,81 @@
+use std::{future::{ready, Ready}, rc::Rc};
+
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform}, http::Method, Error, HttpMessage
+};
+use futures::future::LocalBoxFuture;
+use crate::{api::app_errors::AppError, middleware::auth_user::{AuthUser, Credential}, model::user_model::Role};
+
+// Rejects requests whose token carries a lower role than `role`. Wrapped around a scope or
+// a single route, behind `Authentication` which leaves the caller in the extensions.
+pub struct RequireRole {
+    role:Role,
+    // reads (GET, HEAD, OPTIONS) go through for any authenticated user
+    writes_only:bool,
+}
+
+impl RequireRole {
+    pub fn new(role:Role) -> Self {
+        RequireRole { role, writes_only: false }
+    }
+
+    pub fn for_writes(role:Role) -> Self {
+        RequireRole { role, writes_only: true }
+    }
+}
+
+impl<S,B> Transform<S, ServiceRequest> for RequireRole
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequireRoleMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequireRoleMiddleware { service: Rc::new(service), role: self.role, writes_only: self.writes_only }))
+    }
+}
+
+pub struct RequireRoleMiddleware<S>{
+    service:Rc<S>,
+    role:Role,
+    writes_only:bool,
+}
+
+impl<S,B> Service<ServiceRequest> for RequireRoleMiddleware<S>
+where
+    S:Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let is_read = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+        if !(self.writes_only && is_read) {
+            let caller = req.extensions().get::<AuthUser>().map(|user| (user.role, user.credential.clone()));
+            let denied = match caller {
+                None => Some(AppError::UnauthorizedError("Token not found".to_string())),
+                // API keys were already checked against the scopes of the route
+                Some((_, Credential::ApiKey(_))) => None,
+                Some((role, _)) if role < self.role => Some(AppError::ForbiddenError(format!("requires the {} role", self.role.as_str()))),
+                Some(_) => None,
+            };
+            if let Some(err) = denied {
+                return Box::pin(async move { Err(err.into()) });
+            }
+        }
+
+        let service = self.service.clone();
+        Box::pin(async move { service.call(req).await })
+    }
+}
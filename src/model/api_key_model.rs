@@ -0,0 +1,95 @@
+use mongodb::bson::{self, oid::ObjectId};
+use serde::{Serialize, Deserialize};
+use validator::Validate;
+
+use crate::utils::{password::hash_token, validation::not_blank};
+
+// What an API key may do, checked by `Authentication` against the route being called
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum ApiScope {
+    #[serde(rename = "books:read")]
+    BooksRead,
+    #[serde(rename = "books:write")]
+    BooksWrite,
+    #[serde(rename = "authors:read")]
+    AuthorsRead,
+    #[serde(rename = "authors:write")]
+    AuthorsWrite,
+}
+
+impl ApiScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::BooksRead => "books:read",
+            ApiScope::BooksWrite => "books:write",
+            ApiScope::AuthorsRead => "authors:read",
+            ApiScope::AuthorsWrite => "authors:write",
+        }
+    }
+}
+
+// Only the SHA-256 of the key is stored, the key itself is shown once when it is created
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id:Option<ObjectId>,
+    pub name:String,
+    // start of the key, enough to tell keys apart in listings
+    pub prefix:String,
+    pub key_hash:String,
+    pub scopes:Vec<ApiScope>,
+    // admin who created the key, requests made with it act on their behalf
+    pub created_by:String,
+    pub created_at:bson::DateTime,
+    #[serde(default)]
+    pub last_used_at:Option<bson::DateTime>,
+    #[serde(default)]
+    pub revoked:bool,
+}
+
+impl ApiKey {
+    pub fn hash(key:&str) -> String {
+        hash_token(key)
+    }
+}
+
+// Body of `POST /apikey/keys`
+#[derive(Serialize, Deserialize, Debug, Default, Validate)]
+pub struct NewApiKey {
+    #[validate(length(max = 100, message = "must be at most 100 characters"), custom = "not_blank")]
+    pub name:String,
+    #[validate(length(min = 1, message = "must contain at least one scope"))]
+    pub scopes:Vec<ApiScope>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ApiKeyData {
+    pub id:String,
+    pub name:String,
+    pub prefix:String,
+    pub scopes:Vec<ApiScope>,
+    pub created_by:String,
+    pub created_at:String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_used_at:Option<String>,
+    pub revoked:bool,
+    // the plain key, only present in the response creating it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key:Option<String>,
+}
+
+impl ApiKeyData {
+    pub fn set_data(api_key:ApiKey) -> Self {
+        ApiKeyData {
+            id:api_key.id.map(|id| id.to_hex()).unwrap_or_default(),
+            name:api_key.name,
+            prefix:api_key.prefix,
+            scopes:api_key.scopes,
+            created_by:api_key.created_by,
+            created_at:api_key.created_at.to_string(),
+            last_used_at:api_key.last_used_at.map(|time| time.to_string()),
+            revoked:api_key.revoked,
+            key:None,
+        }
+    }
+}
@@ -1,39 +1,88 @@
+use lazy_static::lazy_static;
+use regex::Regex;
 use serde::{Serialize,Deserialize};
 use mongodb::bson::oid::ObjectId;
 use mongodb::{bson, bson::Document};
-#[derive(Default,Debug, Serialize, Deserialize)]
+use validator::Validate;
+
+use crate::utils::{pagination::FieldKind, validation::not_blank};
+use crate::model::file_model::{file_url, ImageSize};
+
+lazy_static! {
+    // digits with an optional leading `+`, spaces, dashes and brackets allowed in between
+    static ref CONTACT_NUMBER: Regex = Regex::new(r"^\+?[0-9][0-9 ()-]{5,19}$").unwrap();
+}
+
+#[derive(Default,Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct Author {
     #[serde(rename="_id", skip_serializing_if="Option::is_none")]
     pub id:Option<ObjectId>,
+    #[validate(length(max = 100, message = "must be at most 100 characters"), custom = "not_blank")]
     pub author_name:String,
+    #[validate(regex(path = "CONTACT_NUMBER", message = "must be a phone number"))]
     pub contact:String,
+    #[validate(email(message = "must be a valid email address"))]
     pub author_email:String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub created_at:Option<bson::DateTime>
+    pub created_at:Option<bson::DateTime>,
+    // id of the user who added the author, set by the server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_by:Option<String>
+}
+// Body of `PATCH /author/author/{id}`, only the supplied fields are changed
+#[derive(Default, Debug, Clone, Serialize, Deserialize, Validate)]
+#[serde(deny_unknown_fields)]
+pub struct AuthorPatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[validate(length(max = 100, message = "must be at most 100 characters"), custom = "not_blank")]
+    pub author_name:Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[validate(regex(path = "CONTACT_NUMBER", message = "must be a phone number"))]
+    pub contact:Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[validate(email(message = "must be a valid email address"))]
+    pub author_email:Option<String>
+}
+
+impl AuthorPatch {
+    pub fn is_empty(&self) -> bool {
+        self.author_name.is_none() && self.contact.is_none() && self.author_email.is_none()
+    }
 }
+
 #[derive(Default,Serialize, Deserialize)]
 pub struct AuthorData {
     pub id:String,
     pub author_name:String,
     pub contact:String,
     pub author_email:String,
-    pub created_at:String
+    pub created_at:String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_by:Option<String>
 }
 
 impl AuthorData {
     
     pub fn set_data(author:Author) -> Self {
         AuthorData {
-            id:author.id.unwrap().to_string(),
+            id:author.id.map(|id| id.to_string()).unwrap_or_default(),
             author_name:author.author_name,
             author_email:author.author_email,
             contact:author.contact,
-            created_at:author.created_at.unwrap().to_string(),
+            created_at:author.created_at.map(|time| time.to_string()).unwrap_or_default(),
+            created_by:author.created_by,
         }
     }
 }
 
 impl Author {
+    // fields clients may filter and sort on in `GET /author/authors`
+    pub const LIST_FIELDS:&'static [(&'static str, FieldKind)] = &[
+        ("author_name", FieldKind::Text),
+        ("contact", FieldKind::Text),
+        ("author_email", FieldKind::Text),
+    ];
+
     // Function to convert Author struct to BSON Document
     pub fn to_document(&self) -> Result<Document, mongodb::bson::ser::Error> {
         bson::to_document(self)
@@ -44,12 +93,19 @@ impl Author {
     //     bson::from_document(doc)
     // }
 }
-#[derive(Default, Debug, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct AuthorProfile {
     #[serde(rename="_id", skip_serializing_if="Option::is_none")]
     pub id:Option<ObjectId>,
     pub author_id:ObjectId,
     pub profile_imgae:String,
+    // hex SHA-256 of the stored picture, which its key is made from
+    #[serde(default)]
+    pub digest:String,
+    #[serde(default)]
+    pub size:u64,
+    #[serde(default)]
+    pub content_type:String,
     #[serde(skip_serializing_if="Option::is_none")]
     pub created_at:Option<bson::DateTime>
 }
@@ -65,12 +121,36 @@ pub struct AuthorProfileData {
     pub id:String,
     pub author_id:String,
     pub profile_image:String,
+    pub images:ProfileImageUrls,
+    pub digest:String,
+    pub size:u64,
+    pub content_type:String,
     pub created_at:String
 }
 
+// where each size of the picture is downloaded from
+#[derive(Default, Serialize, Deserialize)]
+pub struct ProfileImageUrls {
+    pub thumbnail:String,
+    pub medium:String,
+    pub original:String,
+}
+
 impl AuthorProfileData {
-    pub fn set_profile_data(author:AuthorProfile) -> Self {
-        let profile_img = format!("http://locathost:8000{}", author.profile_imgae);
-        AuthorProfileData { id: author.id.unwrap().to_string(), author_id: author.author_id.to_string(), profile_image: profile_img.to_string(), created_at: author.created_at.unwrap().to_string() }
+    // `public_url` is `server.public_url` of the config, the image is stored under its key
+    pub fn set_profile_data(author:AuthorProfile, public_url:&str) -> Self {
+        let profile_img = file_url(public_url, &author.profile_imgae);
+        let sized = |size:ImageSize| format!("{}?size={}", profile_img, size.as_str());
+        let images = ProfileImageUrls { thumbnail: sized(ImageSize::Thumbnail), medium: sized(ImageSize::Medium), original: profile_img.clone() };
+        AuthorProfileData {
+            id: author.id.map(|id| id.to_string()).unwrap_or_default(),
+            author_id: author.author_id.to_string(),
+            profile_image: profile_img.to_string(),
+            images,
+            digest: author.digest,
+            size: author.size,
+            content_type: author.content_type,
+            created_at: author.created_at.map(|time| time.to_string()).unwrap_or_default(),
+        }
     }
 }
\ No newline at end of file
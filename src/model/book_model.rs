@@ -1,11 +1,50 @@
 use mongodb::bson::oid::ObjectId;
 use serde::{Serialize, Deserialize};
+use validator::Validate;
 
-#[derive(Default, Serialize, Deserialize)]
+use crate::utils::{pagination::FieldKind, validation::not_blank};
+
+#[derive(Default, Clone, Serialize, Deserialize, Validate)]
 pub struct Book {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub id:Option<ObjectId>,
+    #[validate(length(max = 200, message = "must be at most 200 characters"), custom = "not_blank")]
     pub book_name:String,
+    #[validate(custom = "not_blank")]
     pub book_author:String,
-    pub total_page:i32
-}
\ No newline at end of file
+    #[validate(range(min = 1, max = 100000, message = "must be between 1 and 100000"))]
+    pub total_page:i32,
+    // id of the user who added the book, set by the server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_by:Option<String>
+}
+
+impl Book {
+    // fields clients may filter and sort on in `GET /book/books`
+    pub const LIST_FIELDS:&'static [(&'static str, FieldKind)] = &[
+        ("book_name", FieldKind::Text),
+        ("book_author", FieldKind::Text),
+        ("total_page", FieldKind::Int),
+    ];
+}
+
+// Body of `PATCH /book/book/{id}`, only the supplied fields are changed
+#[derive(Default, Clone, Serialize, Deserialize, Validate)]
+#[serde(deny_unknown_fields)]
+pub struct BookPatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[validate(length(max = 200, message = "must be at most 200 characters"), custom = "not_blank")]
+    pub book_name:Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[validate(custom = "not_blank")]
+    pub book_author:Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[validate(range(min = 1, max = 100000, message = "must be between 1 and 100000"))]
+    pub total_page:Option<i32>
+}
+
+impl BookPatch {
+    pub fn is_empty(&self) -> bool {
+        self.book_name.is_none() && self.book_author.is_none() && self.total_page.is_none()
+    }
+}
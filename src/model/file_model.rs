@@ -0,0 +1,55 @@
+use serde::{Serialize, Deserialize};
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct UploadedFile {
+    pub key:String,
+    pub url:String,
+}
+
+impl UploadedFile {
+    // `public_url` is `server.public_url` of the config
+    pub fn set_data(key:String, public_url:&str) -> Self {
+        UploadedFile { url: file_url(public_url, &key), key }
+    }
+}
+
+// where a stored file is downloaded from
+pub fn file_url(public_url:&str, key:&str) -> String {
+    format!("{}/get_file/{}", public_url, key)
+}
+
+// The renditions of an uploaded picture, `?size=` of a download
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageSize {
+    Thumbnail,
+    Medium,
+    Original,
+}
+
+impl ImageSize {
+    // the sizes rendered from the original at upload time
+    pub const RESIZED:[ImageSize; 2] = [ImageSize::Thumbnail, ImageSize::Medium];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ImageSize::Thumbnail => "thumbnail",
+            ImageSize::Medium => "medium",
+            ImageSize::Original => "original",
+        }
+    }
+
+    // the longest side in pixels, the original is never scaled
+    pub fn max_side(self) -> usize {
+        match self {
+            ImageSize::Thumbnail => 128,
+            ImageSize::Medium => 512,
+            ImageSize::Original => usize::MAX,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct FileQuery {
+    pub size:Option<ImageSize>,
+}
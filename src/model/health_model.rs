@@ -0,0 +1,20 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+#[derive(Serialize, Debug)]
+pub struct CheckReport {
+    // up or down
+    pub status:&'static str,
+    pub latency_ms:f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error:Option<String>,
+}
+
+// Body of `/readyz`, one entry per dependency
+#[derive(Serialize, Debug)]
+pub struct ReadinessReport {
+    // ready or unavailable
+    pub status:&'static str,
+    pub checks:BTreeMap<&'static str, CheckReport>,
+}
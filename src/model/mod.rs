@@ -1,3 +1,7 @@
 pub mod user_model;
 pub mod book_model;
-pub mod author_model;
\ No newline at end of file
+pub mod author_model;
+pub mod search_model;
+pub mod api_key_model;
+pub mod health_model;
+pub mod file_model;
\ No newline at end of file
@@ -0,0 +1,11 @@
+use serde::{Serialize, Deserialize};
+
+use crate::repository::search_index::DocKind;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SearchQuery {
+    pub q:Option<String>,
+    #[serde(rename = "type")]
+    pub kind:Option<DocKind>,
+    pub limit:Option<usize>
+}
@@ -1,25 +1,152 @@
 use mongodb::bson::oid::ObjectId;
 use serde::{Serialize, Deserialize};
+use validator::Validate;
 
-#[derive(Debug,Default, Serialize, Deserialize)]
+use crate::utils::{pagination::FieldKind, validation::not_blank};
+
+// What a user may do, each role includes everything the roles before it allow:
+// readers can read, editors can also change books and authors, admins manage users
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    Reader,
+    Editor,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Reader => "reader",
+            Role::Editor => "editor",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn parse(value:&str) -> Option<Self> {
+        match value {
+            "reader" => Some(Role::Reader),
+            "editor" => Some(Role::Editor),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug,Default, Clone, Serialize, Deserialize, Validate)]
 pub struct User {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub id: Option<ObjectId>,
+    #[validate(length(max = 50, message = "must be at most 50 characters"), custom = "not_blank")]
     pub name: String,
+    #[validate(length(max = 100, message = "must be at most 100 characters"))]
     pub location: String,
+    #[validate(length(max = 100, message = "must be at most 100 characters"))]
     pub title: String,
+    // Argon2 PHC string, never part of a request or of `UserData`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_hash: Option<String>,
+    // only changed through `PUT /user/user/{id}/role`, ignored by the other updates
+    #[serde(default)]
+    pub role: Role,
+}
+
+impl User {
+    // fields clients may filter and sort on in `GET /user/users`
+    pub const LIST_FIELDS:&'static [(&'static str, FieldKind)] = &[
+        ("name", FieldKind::Text),
+        ("location", FieldKind::Text),
+        ("title", FieldKind::Text),
+    ];
 }
-#[derive(Serialize, Deserialize, Debug, Default)]
+
+// Body of `PATCH /user/user/{id}`, only the supplied fields are changed
+#[derive(Debug, Default, Clone, Serialize, Deserialize, Validate)]
+#[serde(deny_unknown_fields)]
+pub struct UserPatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[validate(length(max = 50, message = "must be at most 50 characters"), custom = "not_blank")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[validate(length(max = 100, message = "must be at most 100 characters"))]
+    pub location: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[validate(length(max = 100, message = "must be at most 100 characters"))]
+    pub title: Option<String>,
+}
+
+impl UserPatch {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.location.is_none() && self.title.is_none()
+    }
+}
+
+// Body of the registration request `POST /user/user`
+#[derive(Serialize, Deserialize, Debug, Default, Validate)]
+pub struct RegisterUser {
+    #[validate(length(max = 50, message = "must be at most 50 characters"), custom = "not_blank")]
+    pub name: String,
+    #[validate(length(max = 100, message = "must be at most 100 characters"))]
+    pub location: String,
+    #[validate(length(max = 100, message = "must be at most 100 characters"))]
+    pub title: String,
+    #[validate(length(min = 8, max = 128, message = "must be between 8 and 128 characters"))]
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Validate)]
 pub struct LoginUser {
+    #[validate(custom = "not_blank")]
+    pub name:String,
+    #[validate(length(min = 1, message = "is required"))]
+    pub password:String
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Validate)]
+pub struct ChangePassword {
+    #[validate(length(min = 1, message = "is required"))]
+    pub current_password:String,
+    #[validate(length(min = 8, max = 128, message = "must be between 8 and 128 characters"))]
+    pub new_password:String
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Validate)]
+pub struct ForgotPassword {
+    #[validate(custom = "not_blank")]
     pub name:String
 }
 
+#[derive(Serialize, Deserialize, Debug, Default, Validate)]
+pub struct ResetPassword {
+    #[validate(length(min = 1, message = "is required"))]
+    pub token:String,
+    #[validate(length(min = 8, max = 128, message = "must be between 8 and 128 characters"))]
+    pub new_password:String
+}
+
+// Body of `PUT /user/user/{id}/role`
+#[derive(Serialize, Deserialize, Debug, Default, Validate)]
+pub struct ChangeRole {
+    pub role:Role
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Validate)]
+pub struct RefreshToken {
+    #[validate(length(min = 1, message = "is required"))]
+    pub refresh_token:String
+}
+
 #[derive(Debug, Deserialize, Serialize, Default)]
 pub struct UserData {
     pub id:String,
     pub name:String,
     pub location:String,
     pub title:String,
+    #[serde(default)]
+    pub role:Role,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_token:Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub access_token:Option<String>
+    pub refresh_token:Option<String>
 }
\ No newline at end of file
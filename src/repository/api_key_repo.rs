@@ -0,0 +1,77 @@
+use async_trait::async_trait;
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{self, doc, oid::ObjectId},
+    Collection, Database
+};
+
+use crate::{api::app_errors::AppError, model::api_key_model::ApiKey};
+use super::store::{ApiKeyStore, InsertResult, UpdateResult};
+
+pub struct ApiKeyRepo {
+    col:Collection<ApiKey>,
+}
+
+impl ApiKeyRepo {
+    pub async fn init(db:Database) -> Self {
+        let col:Collection<ApiKey> = db.collection("ApiKey");
+        ApiKeyRepo {col}
+    }
+}
+
+#[async_trait]
+impl ApiKeyStore for ApiKeyRepo {
+    async fn create_api_key(&self, api_key:ApiKey) -> Result<InsertResult, AppError> {
+        let result = self
+            .col
+            .insert_one(api_key, None)
+            .await?;
+        Ok(result.into())
+    }
+
+    async fn get_api_keys(&self) -> Result<Vec<ApiKey>, AppError> {
+        let mut cursor = self.col.find(None, None).await?;
+
+        let mut api_keys:Vec<ApiKey> = Vec::new();
+        while let Some(api_key) = cursor.try_next().await? {
+            api_keys.push(api_key);
+        }
+        Ok(api_keys)
+    }
+
+    async fn get_api_key_by_hash(&self, key_hash:&str) -> Result<ApiKey, AppError> {
+        let filter = doc! {"key_hash":key_hash};
+        match self.col.find_one(filter, None).await? {
+            Some(api_key) => Ok(api_key),
+            None => Err(AppError::NotFoundError("API key not found".to_string())),
+        }
+    }
+
+    async fn revoke_api_key(&self, id:ObjectId) -> Result<UpdateResult, AppError> {
+        let filter = doc! {"_id":id};
+        let update = doc! {
+            "$set":{
+                "revoked":true
+            },
+        };
+        let result = self
+            .col
+            .update_one(filter, update, None)
+            .await?;
+        Ok(result.into())
+    }
+
+    async fn set_api_key_last_used(&self, id:ObjectId, used_at:bson::DateTime) -> Result<UpdateResult, AppError> {
+        let filter = doc! {"_id":id};
+        let update = doc! {
+            "$set":{
+                "last_used_at":used_at
+            },
+        };
+        let result = self
+            .col
+            .update_one(filter, update, None)
+            .await?;
+        Ok(result.into())
+    }
+}
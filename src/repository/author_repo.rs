@@ -1,12 +1,15 @@
 use actix_web::Result;
+use async_trait::async_trait;
 use mongodb:: {
-    bson::{ self, doc, extjson::de::Error,oid::ObjectId, Document},
-    results::{DeleteResult, InsertOneResult, UpdateResult},
+    bson::{ self, doc, oid::ObjectId, Document},
+    options::FindOneOptions,
     Collection, Database
 };
 // use mongodb::error::Error;
-use crate::{api::app_errors::AppError, model::author_model::{Author, AuthorProfile}};
+use crate::{api::app_errors::AppError, model::author_model::{Author, AuthorPatch, AuthorProfile}, utils::pagination::{ListQuery, Page}};
 use futures::stream::TryStreamExt; //add this
+use super::mongodb_repo::{list_filter, list_options};
+use super::store::{AuthorStore, DeleteResult, InsertResult, UpdateResult};
 
 pub struct AuthorRepo {
     col :Collection<Document>,
@@ -21,55 +24,48 @@ impl AuthorRepo {
         let profile_col:Collection<Document> = db.collection("Author_Profile");
         AuthorRepo{ col, profile_col }
     }
+}
 
-    pub async fn create_author(&self, mut author:Author) -> Result<InsertOneResult, Error> {
+#[async_trait]
+impl AuthorStore for AuthorRepo {
+    async fn create_author(&self, mut author:Author) -> Result<InsertResult, AppError> {
         let created_at_bson = bson::DateTime::now();
 
         if author.created_at.is_none() {
             author.created_at = Some(created_at_bson);
         }
         
-        let bson_author = match author.to_document() {
-            Ok(bson_doc) => bson_doc,
-            Err(e) => {
-                return Err(Error::DeserializationError { message: e.to_string() });
-            }
-        };
+        let bson_author = author.to_document()?;
         
-        let result = self.col.insert_one(bson_author, None).await;
-        Ok(result.unwrap())
+        let result = self.col.insert_one(bson_author, None).await?;
+        Ok(result.into())
     }
 
-    pub async fn get_authors(&self) -> Result<Vec<Author>, Error> {
-        let mut cursor = self.col.find(None, None).await.ok().expect("Error fetch authors");
+    async fn get_authors(&self, query:&ListQuery) -> Result<Page<Author>, AppError> {
+        let total = self.col.count_documents(list_filter(query, false), None).await?;
+        let mut cursor = self.col.find(list_filter(query, true), list_options(query)).await?;
 
         let mut authors:Vec<Author> = Vec::new();
         while let Some(author) = cursor 
             .try_next()
-            .await
-            .ok()
-            .expect("Error mapping through cursor")
+            .await?
 
         {
-            authors.push(bson::from_document(author).unwrap());    
+            authors.push(bson::from_document(author)?);    
         }
-        Ok(authors)
+        Ok(Page { items: authors, total })
     }
 
-    pub async fn get_author(&self, id:ObjectId) -> Result<Author, Error> {
+    async fn get_author(&self, id:ObjectId) -> Result<Author, AppError> {
         let filter = doc! {"_id": id};
 
-        let author = match self.col.find_one(filter, None).await {
-            Ok(author) => author,
-            Err(e) => return Err(Error::DeserializationError{message:format!("Error fetching author by ID: {}", e)}),
-        };
-        if author.is_none() {
-            return Err(Error::DeserializationError { message: AppError::DataNotFoundError.to_string() });
+        match self.col.find_one(filter, None).await? {
+            Some(author) => Ok(bson::from_document(author)?),
+            None => Err(AppError::NotFoundError("Data not found".to_string())),
         }
-        Ok(bson::from_document(author.unwrap_or_default()).unwrap())
     }
 
-    pub async fn update_author(&self, id:ObjectId, author:Author) -> Result<UpdateResult, Error> {
+    async fn update_author(&self, id:ObjectId, author:Author) -> Result<UpdateResult, AppError> {
         let filter = doc! {"_id":id};
         let created_at_bson = bson::DateTime::now();
        
@@ -85,58 +81,92 @@ impl AuthorRepo {
         let result = self
             .col
             .update_one(filter, update, None)
-            .await
-            .ok()
-            .expect("Error updating Author");
+            .await?;
 
-        Ok(result)
+        Ok(result.into())
 
     }
 
-    pub async fn delete_author(&self, id:ObjectId) -> Result<DeleteResult,Error> {
+    async fn patch_author(&self, id:ObjectId, patch:AuthorPatch) -> Result<UpdateResult, AppError> {
+        let filter = doc! {"_id":id};
+        let mut fields = bson::to_document(&patch)?;
+        fields.insert("updated_at", bson::DateTime::now());
+
+        let update = doc! {
+            "$set": fields
+        };
+
+        let result = self
+            .col
+            .update_one(filter, update, None)
+            .await?;
+
+        Ok(result.into())
+    }
+
+    async fn delete_author(&self, id:ObjectId) -> Result<DeleteResult, AppError> {
         let filter = doc! {"_id":id};
 
         let delete = self
             .col
             .delete_one(filter, None)
-            .await
-            .ok()
-            .expect("Error delete author");
+            .await?;
 
-        Ok(delete)
+        Ok(delete.into())
     }
 
-    pub async fn upload_profile_pic(&self, mut author:AuthorProfile) -> Result<InsertOneResult, Error> {
+    // ObjectIds grow with time, the profiles stored before this one are the ones with smaller ids
+    async fn upload_profile_pic(&self, mut author:AuthorProfile) -> Result<Vec<AuthorProfile>, AppError> {
+        let id = ObjectId::new();
+        author.id = Some(id);
         let created_at_bson = bson::DateTime::now();
 
         if author.created_at.is_none() {
             author.created_at = Some(created_at_bson);
         }
 
-        let bson_author = match author.to_document() {
-            Ok(bson_doc) => bson_doc,
-            Err(e) => {
-                return Err(Error::DeserializationError { message: e.to_string() });
-            }
-        };
+        let bson_author = author.to_document()?;
+
+        self.profile_col.insert_one(bson_author, None).await?;
+        let filter = doc! {"author_id":author.author_id, "_id":{"$lt":id}};
+        let mut cursor = self.profile_col.find(filter, None).await?;
 
-        let result = self.profile_col.insert_one(bson_author, None).await;
-        Ok(result.unwrap())
+        // one by one, so a profile removed concurrently is returned by only one caller
+        let mut replaced = Vec::new();
+        while let Some(document) = cursor.try_next().await? {
+            let profile:AuthorProfile = bson::from_document(document)?;
+            let result = self.profile_col.delete_one(doc! {"_id":profile.id}, None).await?;
+            if result.deleted_count > 0 {
+                replaced.push(profile);
+            }
+        }
+        Ok(replaced)
     }
 
-    pub async fn get_profile_pic(&self, id:ObjectId) ->Result<AuthorProfile, Error> {
+    async fn get_profile_pic(&self, id:ObjectId) ->Result<AuthorProfile, AppError> {
         let filter = doc! {"author_id":id};
+        let options = FindOneOptions::builder().sort(doc! {"_id":-1}).build();
 
-        let author_profile = match self.profile_col.find_one(filter, None).await {
-            Ok(author_p)=> author_p,
-            Err(e) => {return Err(Error::DeserializationError { message: e.to_string() });}
-            
-        };
+        match self.profile_col.find_one(filter, options).await? {
+            Some(author_profile) => Ok(bson::from_document(author_profile)?),
+            None => Err(AppError::NotFoundError("Author profile not found".to_string())),
+        }
+    }
 
-        if author_profile.is_none() {
-            return Err(Error::DeserializationError { message: AppError::UserNotFoundError.to_string() });
+    async fn delete_profile_pics(&self, author_id:ObjectId) -> Result<Vec<AuthorProfile>, AppError> {
+        let filter = doc! {"author_id":author_id};
+        let mut cursor = self.profile_col.find(filter, None).await?;
+
+        // one by one, so a profile removed concurrently is returned by only one caller
+        let mut deleted = Vec::new();
+        while let Some(document) = cursor.try_next().await? {
+            let profile:AuthorProfile = bson::from_document(document)?;
+            let result = self.profile_col.delete_one(doc! {"_id":profile.id}, None).await?;
+            if result.deleted_count > 0 {
+                deleted.push(profile);
+            }
         }
-        Ok(bson::from_document(author_profile.unwrap_or_default()).unwrap())
+        Ok(deleted)
     }
 
 }
\ No newline at end of file
@@ -0,0 +1,56 @@
+use async_trait::async_trait;
+use mongodb::{
+    bson::{doc, Document},
+    options::{FindOneAndUpdateOptions, ReturnDocument},
+    Collection, Database
+};
+
+use crate::api::app_errors::AppError;
+use super::store::BlobStore;
+
+// one document per stored file, `{_id: key, refs: count}`
+pub struct BlobRepo {
+    col:Collection<Document>,
+}
+
+impl BlobRepo {
+    pub async fn init(db:Database) -> Self {
+        let col:Collection<Document> = db.collection("Blob");
+        BlobRepo {col}
+    }
+}
+
+fn refs_of(document:Option<Document>) -> u64 {
+    document.and_then(|document| document.get_i64("refs").ok()).map_or(0, |refs| refs.max(0) as u64)
+}
+
+#[async_trait]
+impl BlobStore for BlobRepo {
+    async fn acquire_blob(&self, key:&str) -> Result<u64, AppError> {
+        let options = FindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .build();
+        let updated = self
+            .col
+            .find_one_and_update(doc! {"_id":key}, doc! {"$inc":{"refs":1_i64}}, options)
+            .await?;
+        Ok(refs_of(updated))
+    }
+
+    async fn release_blob(&self, key:&str) -> Result<u64, AppError> {
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        let updated = self
+            .col
+            .find_one_and_update(doc! {"_id":key, "refs":{"$gt":0_i64}}, doc! {"$inc":{"refs":-1_i64}}, options)
+            .await?;
+        // files stored before counting started have no document, nothing else refers to them
+        let refs = refs_of(updated);
+        if refs == 0 {
+            self.col.delete_one(doc! {"_id":key, "refs":{"$lte":0_i64}}, None).await?;
+        }
+        Ok(refs)
+    }
+}
@@ -5,12 +5,15 @@ use futures::stream::TryStreamExt; //add this
 
 
 
+use async_trait::async_trait;
 use mongodb::{
-    bson::{doc, extjson::de::Error, oid::ObjectId},
-    results::{DeleteResult, InsertOneResult, UpdateResult}, Collection, Database
+    bson::{self, doc, oid::ObjectId},
+    Collection, Database
 };
 
-use crate::model::book_model::Book;
+use crate::{api::app_errors::AppError, model::book_model::{Book, BookPatch}, utils::pagination::{ListQuery, Page}};
+use super::mongodb_repo::{list_filter, list_options};
+use super::store::{BookStore, DeleteResult, InsertResult, UpdateResult};
 
 
 
@@ -23,62 +26,63 @@ impl BookRepo {
         let col:Collection<Book> = db.collection("Book");
         BookRepo {col}
     }
+}
 
-    pub async fn create_book(&self, new_book:Book) -> Result<InsertOneResult, Error> {
+#[async_trait]
+impl BookStore for BookRepo {
+    async fn create_book(&self, new_book:Book) -> Result<InsertResult, AppError> {
         let new_doc = Book {
             id:None,
             book_name:new_book.book_name,
             book_author:new_book.book_author,
-            total_page:new_book.total_page
+            total_page:new_book.total_page,
+            created_by:new_book.created_by,
         };
 
         let book = self
             .col
             .insert_one(new_doc, None)
-            .await
-            .ok()
-            .expect("Error creating book");
-        Ok(book)
+            .await?;
+        Ok(book.into())
     }
 
-    pub async fn get_book(&self, id:ObjectId) -> Result<Book, Error> {
+    async fn get_book(&self, id:ObjectId) -> Result<Book, AppError> {
         
         let filter = doc! {"_id":id};
         let book = self
             .col
             .find_one(filter, None)
-            .await
-            .ok()
-            .expect("failed to fetched book");
+            .await?;
 
         if book.is_none() {
-            return Err(Error::DeserializationError { message: format!("book not found for this id") });
+            return Err(AppError::NotFoundError("book not found for this id".to_string()));
         }
         Ok(book.unwrap_or_default())
     }
 
-    pub async fn get_books(&self) -> Result<Vec<Book>, Error> {
+    async fn get_books(&self, query:&ListQuery) -> Result<Page<Book>, AppError> {
+        let total = self
+            .col
+            .count_documents(list_filter(query, false), None)
+            .await?;
+
         let mut cursor = self
             .col
-            .find(None, None)
-            .await
-            .ok()
-            .expect("Error fecth books");
+            .find(list_filter(query, true), list_options(query))
+            .await?;
         
         let mut books:Vec<Book> = Vec::new();
         while let Some(book) = cursor
             .try_next()
-            .await
-            .ok()
-            .expect("Error mapping through cursor")
+            .await?
         {
             books.push(book)    
         }
-        Ok(books)
+        Ok(Page { items: books, total })
 
     }
 
-    pub async fn update_book(&self, id:ObjectId, data:Book) -> Result<UpdateResult, Error> {
+    async fn update_book(&self, id:ObjectId, data:Book) -> Result<UpdateResult, AppError> {
         let filter = doc! {"_id": id};
         let new_doc = doc! {
             "$set":{
@@ -91,42 +95,48 @@ impl BookRepo {
         let update_doc = self
             .col
             .update_one(filter, new_doc, None)
-            .await
-            .ok()
-            .expect("Error updating books");
-        Ok(update_doc)
+            .await?;
+        Ok(update_doc.into())
+    }
+
+    async fn patch_book(&self, id:ObjectId, patch:BookPatch) -> Result<UpdateResult, AppError> {
+        let filter = doc! {"_id": id};
+        // unset fields are skipped when serializing, so only the supplied ones are set
+        let new_doc = doc! {
+            "$set": bson::to_document(&patch)?
+        };
+
+        let update_doc = self
+            .col
+            .update_one(filter, new_doc, None)
+            .await?;
+        Ok(update_doc.into())
     }
 
-    pub async fn delete_book(&self, id:ObjectId) -> Result<DeleteResult, Error> {
+    async fn delete_book(&self, id:ObjectId) -> Result<DeleteResult, AppError> {
         let filter = doc! {"_id":id};
 
         let delete_book = self
             .col
             .delete_one(filter, None)
-            .await
-            .ok()
-            .expect("Error delete book");
+            .await?;
 
-        Ok(delete_book)
+        Ok(delete_book.into())
     }
 
-    pub async fn get_book_by_author(&self, id:String) -> Result<Vec<Book>, Error> {
+    async fn get_book_by_author(&self, id:String) -> Result<Vec<Book>, AppError> {
         let filter = doc! {"book_author":id};
 
         let mut cursor = self
             .col
             .find(filter, None)
-            .await
-            .ok()
-            .expect("Error fetch books by author");
+            .await?;
 
         let mut books:Vec<Book> = Vec::new();
 
         while let Some(book) = cursor
             .try_next()
-            .await
-            .ok()
-            .expect("Error mapping cursor")
+            .await?
 
         {
             books.push(book)
@@ -135,17 +145,31 @@ impl BookRepo {
         Ok(books)
     }
 
-    pub async fn delete_books_by_author(&self, author_id:String) -> Result<DeleteResult, Error> {
+    async fn delete_books_by_author(&self, author_id:String) -> Result<DeleteResult, AppError> {
         let filter = doc! {"book_author":author_id};
 
         let result = self
             .col
             .delete_many(filter, None)
-            .await
-            .ok()
-            .expect("Error book delete");
+            .await?;
+
+        Ok(result.into())
+    }
+
+    async fn unset_book_author(&self, author_id:String) -> Result<UpdateResult, AppError> {
+        let filter = doc! {"book_author":author_id};
+        let new_doc = doc! {
+            "$set":{
+                "book_author":""
+            },
+        };
+
+        let result = self
+            .col
+            .update_many(filter, new_doc, None)
+            .await?;
 
-        Ok(result)
+        Ok(result.into())
     }
 
 }
\ No newline at end of file
@@ -0,0 +1,243 @@
+use std::{fs::{self, File}, io::{self, Read, SeekFrom}, ops::Range, path::{Path, PathBuf}};
+
+use actix_web::web;
+use async_trait::async_trait;
+use futures::{StreamExt, TryStreamExt};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+use crate::{api::app_errors::AppError, model::file_model::ImageSize, utils::password::random_token};
+use super::store::{FileInfo, FileStorage, FileStream};
+
+// extensions kept on object keys, anything else is stored without one
+const KNOWN_TYPES:[(&str, &str); 8] = [
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("png", "image/png"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("pdf", "application/pdf"),
+    ("txt", "text/plain"),
+    ("json", "application/json"),
+];
+
+const DEFAULT_CONTENT_TYPE:&str = "application/octet-stream";
+
+// bytes read from a local file at a time while it is sent
+const READ_CHUNK:usize = 64 * 1024;
+
+// leading bytes of the formats recognised by their content, `None` matches any byte
+const SIGNATURES:[(&[Option<u8>], &str); 6] = [
+    (&[Some(0xFF), Some(0xD8), Some(0xFF)], "image/jpeg"),
+    (&[Some(0x89), Some(b'P'), Some(b'N'), Some(b'G'), Some(0x0D), Some(0x0A), Some(0x1A), Some(0x0A)], "image/png"),
+    (&[Some(b'G'), Some(b'I'), Some(b'F'), Some(b'8'), Some(b'7'), Some(b'a')], "image/gif"),
+    (&[Some(b'G'), Some(b'I'), Some(b'F'), Some(b'8'), Some(b'9'), Some(b'a')], "image/gif"),
+    (
+        &[Some(b'R'), Some(b'I'), Some(b'F'), Some(b'F'), None, None, None, None, Some(b'W'), Some(b'E'), Some(b'B'), Some(b'P')],
+        "image/webp",
+    ),
+    (&[Some(b'%'), Some(b'P'), Some(b'D'), Some(b'F'), Some(b'-')], "application/pdf"),
+];
+
+// enough of a file to match every signature
+pub const SNIFF_LEN:usize = 12;
+
+// what `POST /upload` accepts, every type a key may carry
+pub const UPLOAD_TYPES:[&str; 7] = ["image/jpeg", "image/png", "image/gif", "image/webp", "application/pdf", "text/plain", "application/json"];
+
+// what an author's profile picture may be
+pub const PROFILE_IMAGE_TYPES:[&str; 3] = ["image/jpeg", "image/png", "image/webp"];
+
+// The key of an upload: the hex SHA-256 of its content, with the usual extension of its type
+// when it has one. Identical files get the same key whatever they were called.
+pub fn content_key(digest:&str, content_type:&str) -> String {
+    match KNOWN_TYPES.iter().find(|(_, known)| *known == content_type) {
+        Some((extension, _)) => format!("{}.{}", digest, extension),
+        None => digest.to_string(),
+    }
+}
+
+// ids of keys, the content digests and the random ids files used to be stored under
+fn is_valid_id(id:&str) -> bool {
+    matches!(id.len(), 32 | 64) && id.bytes().all(|b| b.is_ascii_hexdigit() && !b.is_ascii_uppercase())
+}
+
+// Keys come back in request paths, only the shape `content_key` produces is accepted, with
+// the suffix of `variant_key` for renditions of pictures
+pub fn is_valid_key(key:&str) -> bool {
+    let (id, extension) = key.split_once('.').unwrap_or((key, ""));
+    let id = ImageSize::RESIZED
+        .iter()
+        .find_map(|size| id.strip_suffix(size.as_str()).and_then(|id| id.strip_suffix('-')))
+        .unwrap_or(id);
+    is_valid_id(id) && (extension.is_empty() || KNOWN_TYPES.iter().any(|(known, _)| *known == extension))
+}
+
+// Where the `size` rendition of the picture stored under `key` goes, `None` for the
+// original and for keys of renditions
+pub fn variant_key(key:&str, size:ImageSize) -> Option<String> {
+    if size == ImageSize::Original {
+        return None;
+    }
+    let (id, extension) = key.split_once('.').map_or((key, None), |(id, extension)| (id, Some(extension)));
+    if !is_valid_id(id) {
+        return None;
+    }
+    Some(match extension {
+        Some(extension) => format!("{}-{}.{}", id, size.as_str(), extension),
+        None => format!("{}-{}", id, size.as_str()),
+    })
+}
+
+pub fn content_type_of(key:&str) -> &'static str {
+    key.rsplit_once('.')
+        .and_then(|(_, extension)| KNOWN_TYPES.iter().find(|(known, _)| *known == extension))
+        .map_or(DEFAULT_CONTENT_TYPE, |(_, content_type)| content_type)
+}
+
+// The media type announced by the first bytes of a file, if it is one of `SIGNATURES`
+pub fn sniff_content_type(head:&[u8]) -> Option<&'static str> {
+    SIGNATURES
+        .iter()
+        .find(|(signature, _)| {
+            head.len() >= signature.len()
+                && signature.iter().zip(head).all(|(expected, byte)| expected.is_none_or(|expected| expected == *byte))
+        })
+        .map(|(_, content_type)| *content_type)
+}
+
+// What a file is served as: its content decides, the extension of the key is the fallback
+pub fn detect_content_type(key:&str, head:&[u8]) -> &'static str {
+    sniff_content_type(head).unwrap_or_else(|| content_type_of(key))
+}
+
+// The media type of an upload from its first bytes and the extension of the client's file
+// name, unknown extensions count as none. A type with a signature has to show it and the
+// extension may not claim another type, types without one are taken from the extension.
+pub fn upload_content_type(file_name:Option<&str>, head:&[u8]) -> Result<&'static str, AppError> {
+    let extension = file_name
+        .and_then(|name| name.rsplit_once('.'))
+        .map(|(_, extension)| extension.to_ascii_lowercase());
+    let claimed = extension
+        .as_deref()
+        .and_then(|extension| KNOWN_TYPES.iter().find(|(known, _)| *known == extension))
+        .map(|(_, content_type)| *content_type);
+    match (sniff_content_type(head), claimed) {
+        (Some(found), Some(claimed)) if found != claimed => Err(AppError::UnsupportedMediaTypeError(format!(
+            "the file holds {} but is named .{}",
+            found,
+            extension.unwrap_or_default()
+        ))),
+        (Some(found), _) => Ok(found),
+        (None, Some(claimed)) if SIGNATURES.iter().any(|(_, content_type)| *content_type == claimed) => {
+            Err(AppError::UnsupportedMediaTypeError(format!("the file is not a valid {}", claimed)))
+        },
+        (None, Some(claimed)) => Ok(claimed),
+        (None, None) => Err(AppError::UnsupportedMediaTypeError("the type of the file is not recognised".to_string())),
+    }
+}
+
+fn io_error(e:io::Error) -> AppError {
+    match e.kind() {
+        io::ErrorKind::NotFound => AppError::NotFoundError("File not found".to_string()),
+        _ => AppError::StorageError(e.to_string()),
+    }
+}
+
+// Files below `root`, sharded into two levels of directories by the first characters of
+// the key so no single directory grows too large. The media type is sniffed when a file is
+// looked up, the local disk keeps no metadata of its own.
+pub struct LocalFileStorage {
+    root:PathBuf,
+}
+
+impl LocalFileStorage {
+    pub fn init(root:&str) -> io::Result<Self> {
+        fs::create_dir_all(root)?;
+        Ok(LocalFileStorage { root: PathBuf::from(root) })
+    }
+
+    fn path_of(&self, key:&str) -> Result<PathBuf, AppError> {
+        if !is_valid_key(key) {
+            return Err(AppError::ValidationError("invalid file key".to_string()));
+        }
+        Ok(self.root.join(&key[..2]).join(&key[2..4]).join(key))
+    }
+}
+
+// blocking file system calls are moved off the worker threads
+async fn blocking<T:Send + 'static>(f:impl FnOnce() -> io::Result<T> + Send + 'static) -> Result<T, AppError> {
+    web::block(f)
+        .await
+        .map_err(|e| AppError::InternalError(e.to_string()))?
+        .map_err(io_error)
+}
+
+#[async_trait]
+impl FileStorage for LocalFileStorage {
+    async fn put(&self, key:&str, data:Vec<u8>, _content_type:&str) -> Result<(), AppError> {
+        let path = self.path_of(key)?;
+        blocking(move || {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            // written next to the target and renamed, readers never see half a file
+            let partial = path.with_extension("partial");
+            fs::write(&partial, data)?;
+            fs::rename(&partial, &path)
+        })
+        .await
+    }
+
+    // the staging directory is below the root, so this is a rename on the same file system
+    async fn put_file(&self, key:&str, staged:&Path, _content_type:&str) -> Result<(), AppError> {
+        let path = self.path_of(key)?;
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await.map_err(io_error)?;
+        }
+        tokio::fs::rename(staged, &path).await.map_err(io_error)
+    }
+
+    async fn stat(&self, key:&str) -> Result<FileInfo, AppError> {
+        let path = self.path_of(key)?;
+        let (metadata, head) = blocking(move || {
+            let file = File::open(path)?;
+            let metadata = file.metadata()?;
+            let mut head = Vec::with_capacity(SNIFF_LEN);
+            file.take(SNIFF_LEN as u64).read_to_end(&mut head)?;
+            Ok((metadata, head))
+        })
+        .await?;
+        Ok(FileInfo {
+            size: metadata.len(),
+            content_type: detect_content_type(key, &head).to_string(),
+            modified: metadata.modified().ok(),
+        })
+    }
+
+    async fn read(&self, key:&str, range:Range<u64>) -> Result<FileStream, AppError> {
+        let path = self.path_of(key)?;
+        let mut file = tokio::fs::File::open(path).await.map_err(io_error)?;
+        file.seek(SeekFrom::Start(range.start)).await.map_err(io_error)?;
+        let reader = file.take(range.end - range.start);
+        Ok(ReaderStream::with_capacity(reader, READ_CHUNK).map_err(io_error).boxed())
+    }
+
+    async fn delete(&self, key:&str) -> Result<(), AppError> {
+        let path = self.path_of(key)?;
+        blocking(move || fs::remove_file(path)).await
+    }
+
+    async fn check(&self) -> Result<(), AppError> {
+        let path = self.root.join(format!(".readyz-{}", random_token()));
+        let dir = self.root.display().to_string();
+        blocking(move || {
+            let written = fs::write(&path, b"ok");
+            // removed even if the write failed half way
+            let _ = fs::remove_file(&path);
+            written
+        })
+        .await
+        .map_err(|e| AppError::UnavailableError(format!("{}: {}", dir, e)))
+    }
+}
@@ -0,0 +1,100 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use mongodb::{bson::doc, Database};
+use sqlx::AnyPool;
+
+use crate::api::app_errors::AppError;
+use super::store::FileStorage;
+
+// A dependency `/readyz` checks before reporting the server as ready
+#[async_trait]
+pub trait HealthProbe: Send + Sync {
+    // key of the dependency in the readiness report
+    fn name(&self) -> &'static str;
+    async fn check(&self) -> Result<(), AppError>;
+}
+
+pub struct MongoProbe {
+    db:Database,
+}
+
+impl MongoProbe {
+    pub fn init(db:Database) -> Self {
+        MongoProbe { db }
+    }
+}
+
+#[async_trait]
+impl HealthProbe for MongoProbe {
+    fn name(&self) -> &'static str {
+        "mongodb"
+    }
+
+    async fn check(&self) -> Result<(), AppError> {
+        self.db.run_command(doc! {"ping": 1}, None).await?;
+        Ok(())
+    }
+}
+
+pub struct SqlProbe {
+    pool:AnyPool,
+}
+
+impl SqlProbe {
+    pub fn init(pool:AnyPool) -> Self {
+        SqlProbe { pool }
+    }
+}
+
+#[async_trait]
+impl HealthProbe for SqlProbe {
+    fn name(&self) -> &'static str {
+        "sql"
+    }
+
+    async fn check(&self) -> Result<(), AppError> {
+        sqlx::query("SELECT 1")
+            .execute(&self.pool)
+            .await
+            .map_err(|e| AppError::UnavailableError(e.to_string()))?;
+        Ok(())
+    }
+}
+
+// The configured file storage has to take new files
+pub struct FileStorageProbe {
+    files:Arc<dyn FileStorage>,
+}
+
+impl FileStorageProbe {
+    pub fn init(files:Arc<dyn FileStorage>) -> Self {
+        FileStorageProbe { files }
+    }
+}
+
+#[async_trait]
+impl HealthProbe for FileStorageProbe {
+    fn name(&self) -> &'static str {
+        "uploads"
+    }
+
+    async fn check(&self) -> Result<(), AppError> {
+        self.files.check().await
+    }
+}
+
+// Everything `/readyz` checks, built at startup for the configured backend
+pub struct ReadinessChecks {
+    probes:Vec<Box<dyn HealthProbe>>,
+}
+
+impl ReadinessChecks {
+    pub fn init(probes:Vec<Box<dyn HealthProbe>>) -> Self {
+        ReadinessChecks { probes }
+    }
+
+    pub fn probes(&self) -> &[Box<dyn HealthProbe>] {
+        &self.probes
+    }
+}
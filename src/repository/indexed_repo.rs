@@ -0,0 +1,182 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use mongodb::bson::oid::ObjectId;
+
+use crate::{api::app_errors::AppError, model::{author_model::{Author, AuthorPatch, AuthorProfile}, book_model::{Book, BookPatch}}, utils::pagination::{ListQuery, Page, MAX_PER_PAGE}};
+use super::search_index::{DocKind, SearchIndex};
+use super::store::{AuthorStore, BookStore, DeleteResult, InsertResult, UpdateResult};
+
+// Wrappers around any book/author backend that keep the search index in step with
+// every successful create, update and delete.
+
+pub struct IndexedBookStore {
+    inner:Arc<dyn BookStore>,
+    index:Arc<SearchIndex>
+}
+
+pub struct IndexedAuthorStore {
+    inner:Arc<dyn AuthorStore>,
+    index:Arc<SearchIndex>
+}
+
+impl IndexedBookStore {
+    pub fn init(inner:Arc<dyn BookStore>, index:Arc<SearchIndex>) -> Self {
+        IndexedBookStore { inner, index }
+    }
+}
+
+impl IndexedAuthorStore {
+    pub fn init(inner:Arc<dyn AuthorStore>, index:Arc<SearchIndex>) -> Self {
+        IndexedAuthorStore { inner, index }
+    }
+}
+
+// Loads everything already stored into the index, used once at startup
+pub async fn rebuild_index(index:&SearchIndex, books:&dyn BookStore, authors:&dyn AuthorStore) -> Result<(), AppError> {
+    let mut after:Option<ObjectId> = None;
+    loop {
+        let page = books.get_books(&ListQuery::batch(after, MAX_PER_PAGE)).await?;
+        for book in &page.items {
+            if let Some(id) = book.id {
+                index.index_book(id, book);
+            }
+        }
+        after = page.items.last().and_then(|book| book.id);
+        if (page.items.len() as u64) < MAX_PER_PAGE || after.is_none() {
+            break;
+        }
+    }
+
+    let mut after:Option<ObjectId> = None;
+    loop {
+        let page = authors.get_authors(&ListQuery::batch(after, MAX_PER_PAGE)).await?;
+        for author in &page.items {
+            if let Some(id) = author.id {
+                index.index_author(id, author);
+            }
+        }
+        after = page.items.last().and_then(|author| author.id);
+        if (page.items.len() as u64) < MAX_PER_PAGE || after.is_none() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl BookStore for IndexedBookStore {
+    async fn create_book(&self, new_book:Book) -> Result<InsertResult, AppError> {
+        let book = new_book.clone();
+        let result = self.inner.create_book(new_book).await?;
+        self.index.index_book(result.inserted_id, &book);
+        Ok(result)
+    }
+
+    async fn get_book(&self, id:ObjectId) -> Result<Book, AppError> {
+        self.inner.get_book(id).await
+    }
+
+    async fn get_books(&self, query:&ListQuery) -> Result<Page<Book>, AppError> {
+        self.inner.get_books(query).await
+    }
+
+    async fn update_book(&self, id:ObjectId, data:Book) -> Result<UpdateResult, AppError> {
+        let book = data.clone();
+        let result = self.inner.update_book(id, data).await?;
+        if result.matched_count > 0 {
+            self.index.index_book(id, &book);
+        }
+        Ok(result)
+    }
+
+    async fn patch_book(&self, id:ObjectId, patch:BookPatch) -> Result<UpdateResult, AppError> {
+        let result = self.inner.patch_book(id, patch).await?;
+        if result.matched_count > 0 {
+            let book = self.inner.get_book(id).await?;
+            self.index.index_book(id, &book);
+        }
+        Ok(result)
+    }
+
+    async fn delete_book(&self, id:ObjectId) -> Result<DeleteResult, AppError> {
+        let result = self.inner.delete_book(id).await?;
+        if result.deleted_count > 0 {
+            self.index.remove(DocKind::Book, id);
+        }
+        Ok(result)
+    }
+
+    async fn get_book_by_author(&self, id:String) -> Result<Vec<Book>, AppError> {
+        self.inner.get_book_by_author(id).await
+    }
+
+    async fn delete_books_by_author(&self, author_id:String) -> Result<DeleteResult, AppError> {
+        let books = self.inner.get_book_by_author(author_id.clone()).await?;
+        let result = self.inner.delete_books_by_author(author_id).await?;
+        for id in books.iter().filter_map(|book| book.id) {
+            self.index.remove(DocKind::Book, id);
+        }
+        Ok(result)
+    }
+
+    async fn unset_book_author(&self, author_id:String) -> Result<UpdateResult, AppError> {
+        self.inner.unset_book_author(author_id).await
+    }
+}
+
+#[async_trait]
+impl AuthorStore for IndexedAuthorStore {
+    async fn create_author(&self, author:Author) -> Result<InsertResult, AppError> {
+        let new_author = author.clone();
+        let result = self.inner.create_author(author).await?;
+        self.index.index_author(result.inserted_id, &new_author);
+        Ok(result)
+    }
+
+    async fn get_authors(&self, query:&ListQuery) -> Result<Page<Author>, AppError> {
+        self.inner.get_authors(query).await
+    }
+
+    async fn get_author(&self, id:ObjectId) -> Result<Author, AppError> {
+        self.inner.get_author(id).await
+    }
+
+    async fn update_author(&self, id:ObjectId, author:Author) -> Result<UpdateResult, AppError> {
+        let new_author = author.clone();
+        let result = self.inner.update_author(id, author).await?;
+        if result.matched_count > 0 {
+            self.index.index_author(id, &new_author);
+        }
+        Ok(result)
+    }
+
+    async fn patch_author(&self, id:ObjectId, patch:AuthorPatch) -> Result<UpdateResult, AppError> {
+        let result = self.inner.patch_author(id, patch).await?;
+        if result.matched_count > 0 {
+            let author = self.inner.get_author(id).await?;
+            self.index.index_author(id, &author);
+        }
+        Ok(result)
+    }
+
+    async fn delete_author(&self, id:ObjectId) -> Result<DeleteResult, AppError> {
+        let result = self.inner.delete_author(id).await?;
+        if result.deleted_count > 0 {
+            self.index.remove(DocKind::Author, id);
+        }
+        Ok(result)
+    }
+
+    async fn upload_profile_pic(&self, author:AuthorProfile) -> Result<Vec<AuthorProfile>, AppError> {
+        self.inner.upload_profile_pic(author).await
+    }
+
+    async fn get_profile_pic(&self, id:ObjectId) -> Result<AuthorProfile, AppError> {
+        self.inner.get_profile_pic(id).await
+    }
+
+    async fn delete_profile_pics(&self, author_id:ObjectId) -> Result<Vec<AuthorProfile>, AppError> {
+        self.inner.delete_profile_pics(author_id).await
+    }
+}
@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use mongodb::{
+    bson::{self, doc, Document},
+    options::{FindOneAndUpdateOptions, IndexOptions, ReturnDocument},
+    Collection, Database, IndexModel
+};
+
+use crate::api::app_errors::AppError;
+use super::store::{LoginAttemptStore, LOCKOUT_WINDOW, MAX_FAILED_LOGINS};
+
+// `{_id: name, failures, window_start, locked_until, expires_at: date}`, times in unix
+// seconds. The TTL index drops a name once neither its window nor its lock matter anymore.
+pub struct LoginAttemptRepo {
+    col:Collection<Document>,
+}
+
+impl LoginAttemptRepo {
+    pub async fn init(db:Database) -> Result<Self, AppError> {
+        let col:Collection<Document> = db.collection("LoginAttempt");
+        let expiry = IndexModel::builder()
+            .keys(doc! {"expires_at": 1})
+            .options(IndexOptions::builder().expire_after(Duration::ZERO).build())
+            .build();
+        col.create_index(expiry, None).await?;
+        Ok(LoginAttemptRepo {col})
+    }
+}
+
+#[async_trait]
+impl LoginAttemptStore for LoginAttemptRepo {
+    async fn login_locked_for(&self, name:&str) -> Result<Option<u64>, AppError> {
+        let now = Utc::now().timestamp();
+        let attempts = self.col.find_one(doc! {"_id":name}, None).await?;
+        Ok(attempts
+            .and_then(|attempts| attempts.get_i64("locked_until").ok())
+            .filter(|locked_until| *locked_until > now)
+            .map(|locked_until| (locked_until - now) as u64))
+    }
+
+    // Every step is a single atomic update, so concurrent failures are all counted
+    async fn record_login_failure(&self, name:&str) -> Result<(), AppError> {
+        let now = Utc::now().timestamp();
+        let expires_at = bson::DateTime::from_millis((now + LOCKOUT_WINDOW) * 1000);
+
+        // a window that has run out starts over
+        let stale = doc! {"_id":name, "window_start":{"$lte":now - LOCKOUT_WINDOW}, "locked_until":{"$lte":now}};
+        self.col.update_one(stale, doc! {"$set":{"failures":0_i64, "window_start":now}}, None).await?;
+
+        let options = FindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .build();
+        let update = doc! {
+            "$inc":{"failures":1_i64},
+            "$set":{"expires_at":expires_at},
+            "$setOnInsert":{"window_start":now, "locked_until":0_i64},
+        };
+        let attempts = self.col.find_one_and_update(doc! {"_id":name}, update, options).await?;
+        let failures = attempts.and_then(|attempts| attempts.get_i64("failures").ok()).unwrap_or(0);
+        if failures < MAX_FAILED_LOGINS {
+            return Ok(());
+        }
+
+        let lock = doc! {"$set":{"failures":0_i64, "window_start":now, "locked_until":now + LOCKOUT_WINDOW, "expires_at":expires_at}};
+        let locked = self.col.update_one(doc! {"_id":name, "failures":{"$gte":MAX_FAILED_LOGINS}}, lock, None).await?;
+        if locked.modified_count == 1 {
+            log::warn!("login for {} locked after {} failed attempts", name, MAX_FAILED_LOGINS);
+        }
+        Ok(())
+    }
+
+    async fn clear_login_failures(&self, name:&str) -> Result<(), AppError> {
+        self.col.delete_one(doc! {"_id":name}, None).await?;
+        Ok(())
+    }
+}
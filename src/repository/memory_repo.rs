@@ -0,0 +1,549 @@
+use std::{collections::BTreeMap, sync::{PoisonError, RwLock}};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use mongodb::bson::{self, oid::ObjectId};
+
+use crate::{api::app_errors::AppError, model::{api_key_model::ApiKey, author_model::{Author, AuthorPatch, AuthorProfile}, book_model::{Book, BookPatch}, user_model::{Role, User, UserPatch}}};
+use crate::utils::pagination::{ListQuery, Page};
+use super::store::{ApiKeyStore, AuthorStore, BlobStore, BookStore, DeleteResult, InsertResult, LoginAttemptStore, PasswordResetStore, TokenRevocationStore, UpdateResult, UserStore};
+use super::store::{LOCKOUT_WINDOW, MAX_FAILED_LOGINS};
+
+// In-memory collections keyed by ObjectId, used for tests and local development
+// when no MongoDB is available. Data lives only as long as the process.
+
+#[derive(Default)]
+pub struct MemoryBookRepo {
+    books:RwLock<BTreeMap<ObjectId, Book>>
+}
+
+#[derive(Default)]
+pub struct MemoryAuthorRepo {
+    authors:RwLock<BTreeMap<ObjectId, Author>>,
+    profiles:RwLock<BTreeMap<ObjectId, AuthorProfile>>
+}
+
+#[derive(Default)]
+pub struct MemoryUserRepo {
+    users:RwLock<BTreeMap<ObjectId, User>>
+}
+
+#[derive(Default)]
+pub struct MemoryApiKeyRepo {
+    api_keys:RwLock<BTreeMap<ObjectId, ApiKey>>
+}
+
+// reference counts by file key
+#[derive(Default)]
+pub struct MemoryBlobRepo {
+    refs:RwLock<BTreeMap<String, u64>>
+}
+
+// revoked ids mapped to when they were revoked and when they can be dropped
+#[derive(Default)]
+pub struct MemoryTokenRevocationRepo {
+    revoked:RwLock<BTreeMap<String, (i64, i64)>>
+}
+
+// reset token hashes mapped to the user id and expiry
+#[derive(Default)]
+pub struct MemoryPasswordResetRepo {
+    resets:RwLock<BTreeMap<String, (String, i64)>>
+}
+
+struct LoginAttempts {
+    failures:i64,
+    window_start:i64,
+    locked_until:i64,
+}
+
+#[derive(Default)]
+pub struct MemoryLoginAttemptRepo {
+    attempts:RwLock<BTreeMap<String, LoginAttempts>>
+}
+
+impl MemoryBookRepo {
+    pub fn init() -> Self {
+        MemoryBookRepo::default()
+    }
+}
+
+impl MemoryAuthorRepo {
+    pub fn init() -> Self {
+        MemoryAuthorRepo::default()
+    }
+}
+
+impl MemoryUserRepo {
+    pub fn init() -> Self {
+        MemoryUserRepo::default()
+    }
+}
+
+impl MemoryApiKeyRepo {
+    pub fn init() -> Self {
+        MemoryApiKeyRepo::default()
+    }
+}
+
+impl MemoryBlobRepo {
+    pub fn init() -> Self {
+        MemoryBlobRepo::default()
+    }
+}
+
+impl MemoryTokenRevocationRepo {
+    pub fn init() -> Self {
+        MemoryTokenRevocationRepo::default()
+    }
+}
+
+impl MemoryPasswordResetRepo {
+    pub fn init() -> Self {
+        MemoryPasswordResetRepo::default()
+    }
+}
+
+impl MemoryLoginAttemptRepo {
+    pub fn init() -> Self {
+        MemoryLoginAttemptRepo::default()
+    }
+}
+
+#[async_trait]
+impl BookStore for MemoryBookRepo {
+    async fn create_book(&self, new_book:Book) -> Result<InsertResult, AppError> {
+        let id = ObjectId::new();
+        let book = Book {
+            id:Some(id),
+            book_name:new_book.book_name,
+            book_author:new_book.book_author,
+            total_page:new_book.total_page,
+            created_by:new_book.created_by,
+        };
+
+        self.books.write().unwrap_or_else(PoisonError::into_inner).insert(id, book);
+        Ok(InsertResult { inserted_id: id })
+    }
+
+    async fn get_book(&self, id:ObjectId) -> Result<Book, AppError> {
+        match self.books.read().unwrap_or_else(PoisonError::into_inner).get(&id) {
+            Some(book) => Ok(book.clone()),
+            None => Err(AppError::NotFoundError("book not found for this id".to_string())),
+        }
+    }
+
+    async fn get_books(&self, query:&ListQuery) -> Result<Page<Book>, AppError> {
+        let books = self.books.read().unwrap_or_else(PoisonError::into_inner);
+        Ok(query.apply(books.values().cloned().collect()))
+    }
+
+    async fn update_book(&self, id:ObjectId, data:Book) -> Result<UpdateResult, AppError> {
+        let mut books = self.books.write().unwrap_or_else(PoisonError::into_inner);
+
+        match books.get_mut(&id) {
+            Some(book) => {
+                book.book_name = data.book_name;
+                book.book_author = data.book_author;
+                book.total_page = data.total_page;
+                Ok(UpdateResult { matched_count: 1 })
+            },
+            None => Ok(UpdateResult::default()),
+        }
+    }
+
+    async fn patch_book(&self, id:ObjectId, patch:BookPatch) -> Result<UpdateResult, AppError> {
+        let mut books = self.books.write().unwrap_or_else(PoisonError::into_inner);
+
+        match books.get_mut(&id) {
+            Some(book) => {
+                if let Some(book_name) = patch.book_name { book.book_name = book_name; }
+                if let Some(book_author) = patch.book_author { book.book_author = book_author; }
+                if let Some(total_page) = patch.total_page { book.total_page = total_page; }
+                Ok(UpdateResult { matched_count: 1 })
+            },
+            None => Ok(UpdateResult::default()),
+        }
+    }
+
+    async fn delete_book(&self, id:ObjectId) -> Result<DeleteResult, AppError> {
+        let removed = self.books.write().unwrap_or_else(PoisonError::into_inner).remove(&id);
+        Ok(DeleteResult { deleted_count: removed.is_some() as u64 })
+    }
+
+    async fn get_book_by_author(&self, id:String) -> Result<Vec<Book>, AppError> {
+        let books = self.books.read().unwrap_or_else(PoisonError::into_inner);
+        Ok(books.values().filter(|book| book.book_author == id).cloned().collect())
+    }
+
+    async fn delete_books_by_author(&self, author_id:String) -> Result<DeleteResult, AppError> {
+        let mut books = self.books.write().unwrap_or_else(PoisonError::into_inner);
+        let before = books.len();
+        books.retain(|_, book| book.book_author != author_id);
+        Ok(DeleteResult { deleted_count: (before - books.len()) as u64 })
+    }
+
+    async fn unset_book_author(&self, author_id:String) -> Result<UpdateResult, AppError> {
+        let mut books = self.books.write().unwrap_or_else(PoisonError::into_inner);
+        let mut matched_count = 0;
+        for book in books.values_mut().filter(|book| book.book_author == author_id) {
+            book.book_author.clear();
+            matched_count += 1;
+        }
+        Ok(UpdateResult { matched_count })
+    }
+}
+
+#[async_trait]
+impl AuthorStore for MemoryAuthorRepo {
+    async fn create_author(&self, mut author:Author) -> Result<InsertResult, AppError> {
+        let id = ObjectId::new();
+        author.id = Some(id);
+
+        if author.created_at.is_none() {
+            author.created_at = Some(bson::DateTime::now());
+        }
+
+        self.authors.write().unwrap_or_else(PoisonError::into_inner).insert(id, author);
+        Ok(InsertResult { inserted_id: id })
+    }
+
+    async fn get_authors(&self, query:&ListQuery) -> Result<Page<Author>, AppError> {
+        let authors = self.authors.read().unwrap_or_else(PoisonError::into_inner);
+        Ok(query.apply(authors.values().cloned().collect()))
+    }
+
+    async fn get_author(&self, id:ObjectId) -> Result<Author, AppError> {
+        match self.authors.read().unwrap_or_else(PoisonError::into_inner).get(&id) {
+            Some(author) => Ok(author.clone()),
+            None => Err(AppError::NotFoundError("Data not found".to_string())),
+        }
+    }
+
+    async fn update_author(&self, id:ObjectId, author:Author) -> Result<UpdateResult, AppError> {
+        let mut authors = self.authors.write().unwrap_or_else(PoisonError::into_inner);
+
+        match authors.get_mut(&id) {
+            Some(old_author) => {
+                old_author.author_name = author.author_name;
+                old_author.author_email = author.author_email;
+                old_author.contact = author.contact;
+                Ok(UpdateResult { matched_count: 1 })
+            },
+            None => Ok(UpdateResult::default()),
+        }
+    }
+
+    async fn patch_author(&self, id:ObjectId, patch:AuthorPatch) -> Result<UpdateResult, AppError> {
+        let mut authors = self.authors.write().unwrap_or_else(PoisonError::into_inner);
+
+        match authors.get_mut(&id) {
+            Some(author) => {
+                if let Some(author_name) = patch.author_name { author.author_name = author_name; }
+                if let Some(author_email) = patch.author_email { author.author_email = author_email; }
+                if let Some(contact) = patch.contact { author.contact = contact; }
+                Ok(UpdateResult { matched_count: 1 })
+            },
+            None => Ok(UpdateResult::default()),
+        }
+    }
+
+    async fn delete_author(&self, id:ObjectId) -> Result<DeleteResult, AppError> {
+        let removed = self.authors.write().unwrap_or_else(PoisonError::into_inner).remove(&id);
+        Ok(DeleteResult { deleted_count: removed.is_some() as u64 })
+    }
+
+    async fn upload_profile_pic(&self, mut author:AuthorProfile) -> Result<Vec<AuthorProfile>, AppError> {
+        let id = ObjectId::new();
+        author.id = Some(id);
+
+        if author.created_at.is_none() {
+            author.created_at = Some(bson::DateTime::now());
+        }
+
+        let mut profiles = self.profiles.write().unwrap_or_else(PoisonError::into_inner);
+        let older:Vec<ObjectId> = profiles.iter().filter(|(_, profile)| profile.author_id == author.author_id).map(|(id, _)| *id).collect();
+        let replaced = older.iter().filter_map(|id| profiles.remove(id)).collect();
+        profiles.insert(id, author);
+        Ok(replaced)
+    }
+
+    async fn get_profile_pic(&self, id:ObjectId) -> Result<AuthorProfile, AppError> {
+        let profiles = self.profiles.read().unwrap_or_else(PoisonError::into_inner);
+
+        match profiles.values().rev().find(|profile| profile.author_id == id) {
+            Some(profile) => Ok(profile.clone()),
+            None => Err(AppError::NotFoundError("Author profile not found".to_string())),
+        }
+    }
+
+    async fn delete_profile_pics(&self, author_id:ObjectId) -> Result<Vec<AuthorProfile>, AppError> {
+        let mut profiles = self.profiles.write().unwrap_or_else(PoisonError::into_inner);
+        let ids:Vec<ObjectId> = profiles.iter().filter(|(_, profile)| profile.author_id == author_id).map(|(id, _)| *id).collect();
+        Ok(ids.iter().filter_map(|id| profiles.remove(id)).collect())
+    }
+}
+
+// user names are unique, like the index on them in the other backends
+fn check_name_free(users:&BTreeMap<ObjectId, User>, name:&str, id:ObjectId) -> Result<(), AppError> {
+    if users.iter().any(|(other, user)| *other != id && user.name == name) {
+        return Err(AppError::ConflictError("user name is already taken".to_string()));
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl UserStore for MemoryUserRepo {
+    async fn create_user(&self, new_user:User) -> Result<InsertResult, AppError> {
+        let id = ObjectId::new();
+        let user = User {
+            id:Some(id),
+            name:new_user.name,
+            location:new_user.location,
+            title:new_user.title,
+            password_hash:new_user.password_hash,
+            role:new_user.role,
+        };
+
+        let mut users = self.users.write().unwrap_or_else(PoisonError::into_inner);
+        check_name_free(&users, &user.name, id)?;
+        users.insert(id, user);
+        Ok(InsertResult { inserted_id: id })
+    }
+
+    async fn get_user(&self, id:&str) -> Result<User, AppError> {
+        let obj_id = match ObjectId::parse_str(id) {
+            Ok(obj) => obj,
+            Err(_) => {
+                return Err(AppError::InvalidIdError);
+            }
+        };
+
+        match self.users.read().unwrap_or_else(PoisonError::into_inner).get(&obj_id) {
+            Some(user) => Ok(user.clone()),
+            None => Err(AppError::NotFoundError("user not found".to_string())),
+        }
+    }
+
+    async fn check_user_exists(&self, user_name:&str) -> Result<User, AppError> {
+        self.get_user_by_name(user_name).await
+    }
+
+    async fn get_all_users(&self, query:&ListQuery) -> Result<Page<User>, AppError> {
+        let users = self.users.read().unwrap_or_else(PoisonError::into_inner);
+        Ok(query.apply(users.values().cloned().collect()))
+    }
+
+    async fn update_user(&self, id:&str, new_user:User) -> Result<UpdateResult, AppError> {
+        let obj_id = match ObjectId::parse_str(id) {
+            Ok(id) => id,
+            Err(_) => {
+                return Err(AppError::InvalidIdError);
+            }
+        };
+
+        let mut users = self.users.write().unwrap_or_else(PoisonError::into_inner);
+        check_name_free(&users, &new_user.name, obj_id)?;
+        match users.get_mut(&obj_id) {
+            Some(user) => {
+                user.name = new_user.name;
+                user.location = new_user.location;
+                user.title = new_user.title;
+                Ok(UpdateResult { matched_count: 1 })
+            },
+            None => Ok(UpdateResult::default()),
+        }
+    }
+
+    async fn patch_user(&self, id:&str, patch:UserPatch) -> Result<UpdateResult, AppError> {
+        let obj_id = ObjectId::parse_str(id).map_err(|_| AppError::InvalidIdError)?;
+
+        let mut users = self.users.write().unwrap_or_else(PoisonError::into_inner);
+        if let Some(name) = &patch.name {
+            check_name_free(&users, name, obj_id)?;
+        }
+        match users.get_mut(&obj_id) {
+            Some(user) => {
+                if let Some(name) = patch.name { user.name = name; }
+                if let Some(location) = patch.location { user.location = location; }
+                if let Some(title) = patch.title { user.title = title; }
+                Ok(UpdateResult { matched_count: 1 })
+            },
+            None => Ok(UpdateResult::default()),
+        }
+    }
+
+    async fn set_password_hash(&self, id:&str, password_hash:String) -> Result<UpdateResult, AppError> {
+        let obj_id = ObjectId::parse_str(id).map_err(|_| AppError::InvalidIdError)?;
+
+        match self.users.write().unwrap_or_else(PoisonError::into_inner).get_mut(&obj_id) {
+            Some(user) => {
+                user.password_hash = Some(password_hash);
+                Ok(UpdateResult { matched_count: 1 })
+            },
+            None => Ok(UpdateResult::default()),
+        }
+    }
+
+    async fn set_role(&self, id:&str, role:Role) -> Result<UpdateResult, AppError> {
+        let obj_id = ObjectId::parse_str(id).map_err(|_| AppError::InvalidIdError)?;
+
+        match self.users.write().unwrap_or_else(PoisonError::into_inner).get_mut(&obj_id) {
+            Some(user) => {
+                user.role = role;
+                Ok(UpdateResult { matched_count: 1 })
+            },
+            None => Ok(UpdateResult::default()),
+        }
+    }
+
+    async fn delete_user(&self, id:&ObjectId) -> Result<DeleteResult, AppError> {
+        let removed = self.users.write().unwrap_or_else(PoisonError::into_inner).remove(id);
+        Ok(DeleteResult { deleted_count: removed.is_some() as u64 })
+    }
+
+    async fn get_user_by_name(&self, name:&str) -> Result<User, AppError> {
+        let users = self.users.read().unwrap_or_else(PoisonError::into_inner);
+
+        match users.values().find(|user| user.name == name) {
+            Some(user) => Ok(user.clone()),
+            None => Err(AppError::NotFoundError("user not found".to_string())),
+        }
+    }
+}
+
+#[async_trait]
+impl ApiKeyStore for MemoryApiKeyRepo {
+    async fn create_api_key(&self, mut api_key:ApiKey) -> Result<InsertResult, AppError> {
+        let id = ObjectId::new();
+        api_key.id = Some(id);
+
+        self.api_keys.write().unwrap_or_else(PoisonError::into_inner).insert(id, api_key);
+        Ok(InsertResult { inserted_id: id })
+    }
+
+    async fn get_api_keys(&self) -> Result<Vec<ApiKey>, AppError> {
+        Ok(self.api_keys.read().unwrap_or_else(PoisonError::into_inner).values().cloned().collect())
+    }
+
+    async fn get_api_key_by_hash(&self, key_hash:&str) -> Result<ApiKey, AppError> {
+        let api_keys = self.api_keys.read().unwrap_or_else(PoisonError::into_inner);
+        match api_keys.values().find(|api_key| api_key.key_hash == key_hash) {
+            Some(api_key) => Ok(api_key.clone()),
+            None => Err(AppError::NotFoundError("API key not found".to_string())),
+        }
+    }
+
+    async fn revoke_api_key(&self, id:ObjectId) -> Result<UpdateResult, AppError> {
+        match self.api_keys.write().unwrap_or_else(PoisonError::into_inner).get_mut(&id) {
+            Some(api_key) => {
+                api_key.revoked = true;
+                Ok(UpdateResult { matched_count: 1 })
+            },
+            None => Ok(UpdateResult::default()),
+        }
+    }
+
+    async fn set_api_key_last_used(&self, id:ObjectId, used_at:bson::DateTime) -> Result<UpdateResult, AppError> {
+        match self.api_keys.write().unwrap_or_else(PoisonError::into_inner).get_mut(&id) {
+            Some(api_key) => {
+                api_key.last_used_at = Some(used_at);
+                Ok(UpdateResult { matched_count: 1 })
+            },
+            None => Ok(UpdateResult::default()),
+        }
+    }
+}
+
+#[async_trait]
+impl BlobStore for MemoryBlobRepo {
+    async fn acquire_blob(&self, key:&str) -> Result<u64, AppError> {
+        let mut refs = self.refs.write().unwrap_or_else(PoisonError::into_inner);
+        let count = refs.entry(key.to_string()).or_insert(0);
+        *count += 1;
+        Ok(*count)
+    }
+
+    async fn release_blob(&self, key:&str) -> Result<u64, AppError> {
+        let mut refs = self.refs.write().unwrap_or_else(PoisonError::into_inner);
+        let count = match refs.get_mut(key) {
+            Some(count) => {
+                *count -= 1;
+                *count
+            },
+            None => 0,
+        };
+        if count == 0 {
+            refs.remove(key);
+        }
+        Ok(count)
+    }
+}
+
+#[async_trait]
+impl TokenRevocationStore for MemoryTokenRevocationRepo {
+    async fn revoke_token(&self, id:&str, until:i64) -> Result<(), AppError> {
+        let now = Utc::now().timestamp();
+
+        let mut revoked = self.revoked.write().unwrap_or_else(PoisonError::into_inner);
+        revoked.retain(|_, (_, expires_at)| *expires_at > now);
+        let entry = revoked.entry(id.to_string()).or_insert((now, until));
+        *entry = (now, entry.1.max(until));
+        Ok(())
+    }
+
+    async fn revoked_at(&self, id:&str) -> Result<Option<i64>, AppError> {
+        let now = Utc::now().timestamp();
+        let revoked = self.revoked.read().unwrap_or_else(PoisonError::into_inner);
+        Ok(revoked.get(id).filter(|(_, expires_at)| *expires_at > now).map(|(revoked_at, _)| *revoked_at))
+    }
+}
+
+#[async_trait]
+impl PasswordResetStore for MemoryPasswordResetRepo {
+    async fn insert_reset(&self, token_hash:&str, user_id:&str, expires_at:i64) -> Result<(), AppError> {
+        let now = Utc::now().timestamp();
+
+        let mut resets = self.resets.write().unwrap_or_else(PoisonError::into_inner);
+        resets.retain(|_, (_, expires_at)| *expires_at > now);
+        resets.insert(token_hash.to_string(), (user_id.to_string(), expires_at));
+        Ok(())
+    }
+
+    async fn take_reset(&self, token_hash:&str) -> Result<Option<(String, i64)>, AppError> {
+        Ok(self.resets.write().unwrap_or_else(PoisonError::into_inner).remove(token_hash))
+    }
+}
+
+#[async_trait]
+impl LoginAttemptStore for MemoryLoginAttemptRepo {
+    async fn login_locked_for(&self, name:&str) -> Result<Option<u64>, AppError> {
+        let now = Utc::now().timestamp();
+        let attempts = self.attempts.read().unwrap_or_else(PoisonError::into_inner);
+        Ok(attempts
+            .get(name)
+            .filter(|attempt| attempt.locked_until > now)
+            .map(|attempt| (attempt.locked_until - now) as u64))
+    }
+
+    async fn record_login_failure(&self, name:&str) -> Result<(), AppError> {
+        let now = Utc::now().timestamp();
+
+        let mut attempts = self.attempts.write().unwrap_or_else(PoisonError::into_inner);
+        attempts.retain(|_, attempt| attempt.locked_until > now || attempt.window_start + LOCKOUT_WINDOW > now);
+        let attempt = attempts
+            .entry(name.to_string())
+            .or_insert(LoginAttempts { failures: 0, window_start: now, locked_until: 0 });
+        attempt.failures += 1;
+        if attempt.failures >= MAX_FAILED_LOGINS {
+            *attempt = LoginAttempts { failures: 0, window_start: now, locked_until: now + LOCKOUT_WINDOW };
+            log::warn!("login for {} locked after {} failed attempts", name, MAX_FAILED_LOGINS);
+        }
+        Ok(())
+    }
+
+    async fn clear_login_failures(&self, name:&str) -> Result<(), AppError> {
+        self.attempts.write().unwrap_or_else(PoisonError::into_inner).remove(name);
+        Ok(())
+    }
+}
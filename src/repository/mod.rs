@@ -1,3 +1,16 @@
+pub mod store;
 pub mod mongodb_repo;
 pub mod book_repo;
-pub mod author_repo;
\ No newline at end of file
+pub mod author_repo;
+pub mod api_key_repo;
+pub mod blob_repo;
+pub mod token_revocation_repo;
+pub mod password_reset_repo;
+pub mod login_attempt_repo;
+pub mod memory_repo;
+pub mod sql_repo;
+pub mod search_index;
+pub mod indexed_repo;
+pub mod health;
+pub mod file_storage;
+pub mod s3_storage;
@@ -3,113 +3,119 @@ use actix_web:: Result;
 
 use futures::stream::TryStreamExt; //add this
 
+use async_trait::async_trait;
 use mongodb::{
-    bson::{doc, extjson::de::Error, oid::ObjectId},
-    results::{DeleteResult, InsertOneResult, UpdateResult},
-     Collection, Database
+    bson::{self, doc, oid::ObjectId, Bson, Document},
+    error::{self, ErrorKind, WriteFailure},
+    options::{FindOptions, IndexOptions},
+    results, Collection, Database, IndexModel
 };
-use crate::model::user_model::User;
+use crate::{api::app_errors::AppError, model::user_model::{Role, User, UserPatch}, utils::pagination::{FilterOp, FilterValue, ListQuery, Page}};
+use super::store::{DeleteResult, InsertResult, UpdateResult, UserStore};
 
 pub struct MongoRepo {
     col: Collection<User>
 }
 
+// duplicate key, reported when a write breaks a unique index
+const DUPLICATE_KEY:i32 = 11000;
+
 impl MongoRepo {
-    pub async fn init(db:Database) -> Self {
+    // Logins and lookups go by name, so the index keeps it unique even when two
+    // registrations race past the check of `create_user`
+    pub async fn init(db:Database) -> Result<Self, AppError> {
 
         let col: Collection<User> = db.collection("User");
-        MongoRepo { col }
+        let unique_name = IndexModel::builder()
+            .keys(doc! {"name": 1})
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        col.create_index(unique_name, None).await?;
+        Ok(MongoRepo { col })
     }
+}
 
-    pub async fn create_user(&self, new_user:User) ->Result<InsertOneResult, Error> {
+#[async_trait]
+impl UserStore for MongoRepo {
+    async fn create_user(&self, new_user:User) ->Result<InsertResult, AppError> {
         let new_doc = User {
             id:None,
             name:new_user.name,
             location:new_user.location,
             title:new_user.title,
+            password_hash:new_user.password_hash,
+            role:new_user.role,
         };
 
         let user = self
             .col
             .insert_one(new_doc,None)
-            .await
-            .ok()
-            .expect("Error creating user");
+            .await?;
 
-        Ok(user)
+        Ok(user.into())
     } 
 
-    pub async fn get_user(&self, id:&String) -> Result<User, Error> {
-        let obj_id:ObjectId;
-        match ObjectId::parse_str(id) {
-            Ok(obj) => {
-                obj_id = obj;
-            },
+    async fn get_user(&self, id:&str) -> Result<User, AppError> {
+        let obj_id = match ObjectId::parse_str(id) {
+            Ok(obj) => obj,
             Err(_) => {
-                return Err(Error::DeserializationError { message: format!("Invalid id has been passed") });
+                return Err(AppError::InvalidIdError);
             }
         };
         let filter = doc! {"_id":obj_id};
         let user_details = self
             .col
             .find_one(filter, None)
-            .await
-            .ok()
-            .expect("Error getting user's detail");
+            .await?;
 
 
         if user_details.is_none() {
-            return Err(Error::DeserializationError { message: format!("user not found") });
+            return Err(AppError::NotFoundError("user not found".to_string()));
         }
         Ok(user_details.unwrap_or_default())    
     }
 
-    pub async fn check_user_exists(&self, user_name:&String) -> Result<User, Error> {
+    async fn check_user_exists(&self, user_name:&str) -> Result<User, AppError> {
         let filter =doc! {"name":user_name};
 
         let user = self
             .col
             .find_one(filter, None)
-            .await
-            .ok()
-            .expect("Error getting user details");
+            .await?;
 
         if user.is_none() {
-            return Err(Error::DeserializationError { message: format!("user not found") });
+            return Err(AppError::NotFoundError("user not found".to_string()));
         }
 
         Ok(user.unwrap_or_default())
     }
 
-    pub async fn get_all_users(&self) -> Result<Vec<User>, Error> {
+    async fn get_all_users(&self, query:&ListQuery) -> Result<Page<User>, AppError> {
+        let total = self
+            .col
+            .count_documents(list_filter(query, false), None)
+            .await?;
         let mut cursors = self
             .col
-            .find(None, None)
-            .await
-            .ok()
-            .expect("Error getting list of users");
+            .find(list_filter(query, true), list_options(query))
+            .await?;
         let mut users: Vec<User> = Vec::new();
         while let Some(user) = cursors
             .try_next()
-            .await
-            .ok()
-            .expect("Error mapping through cursor")
+            .await?
         {
             users.push(user)
         }
-        Ok(users)
+        Ok(Page { items: users, total })
         }
     
-    pub async fn update_user(&self, id:&String, new_user:User) -> Result<UpdateResult, Error> {
-        let obj_id:ObjectId;
-        match ObjectId::parse_str(id) {
-            Ok(id) => {
-                obj_id = id
-            },
+    async fn update_user(&self, id:&str, new_user:User) -> Result<UpdateResult, AppError> {
+        let obj_id = match ObjectId::parse_str(id) {
+            Ok(id) => id,
             Err(_) => {
-                return Err(Error::DeserializationError { message: format!("invalid id has been passed") });
+                return Err(AppError::InvalidIdError);
             }
-        }
+        };
 
         let filter = doc! {"_id":obj_id};
         let new_doc = doc! {
@@ -122,39 +128,181 @@ impl MongoRepo {
         let updated_doc = self
             .col
             .update_one(filter, new_doc, None)
-            .await
-            .ok()
-            .expect("Error updating user");
-        Ok(updated_doc)
+            .await?;
+        Ok(updated_doc.into())
+    }
+
+    async fn patch_user(&self, id:&str, patch:UserPatch) -> Result<UpdateResult, AppError> {
+        let obj_id = ObjectId::parse_str(id).map_err(|_| AppError::InvalidIdError)?;
+
+        let filter = doc! {"_id":obj_id};
+        let new_doc = doc! {
+            "$set": bson::to_document(&patch)?
+        };
+        let updated_doc = self
+            .col
+            .update_one(filter, new_doc, None)
+            .await?;
+        Ok(updated_doc.into())
+    }
+
+    async fn set_password_hash(&self, id:&str, password_hash:String) -> Result<UpdateResult, AppError> {
+        let obj_id = ObjectId::parse_str(id).map_err(|_| AppError::InvalidIdError)?;
+
+        let filter = doc! {"_id":obj_id};
+        let new_doc = doc! {
+            "$set":{
+                "password_hash":password_hash
+            },
+        };
+        let updated_doc = self
+            .col
+            .update_one(filter, new_doc, None)
+            .await?;
+        Ok(updated_doc.into())
+    }
+
+    async fn set_role(&self, id:&str, role:Role) -> Result<UpdateResult, AppError> {
+        let obj_id = ObjectId::parse_str(id).map_err(|_| AppError::InvalidIdError)?;
+
+        let filter = doc! {"_id":obj_id};
+        let new_doc = doc! {
+            "$set":{
+                "role":role.as_str()
+            },
+        };
+        let updated_doc = self
+            .col
+            .update_one(filter, new_doc, None)
+            .await?;
+        Ok(updated_doc.into())
     }
 
-    pub async fn delete_user(&self, id:&ObjectId) -> Result<DeleteResult, Error> {
+    async fn delete_user(&self, id:&ObjectId) -> Result<DeleteResult, AppError> {
         let filter = doc! {"_id":id};
         let user_details = self
             .col
             .delete_one(filter, None)
-            .await
-            .ok()
-            .expect("Error deleting user");
+            .await?;
 
-        Ok(user_details)
+        Ok(user_details.into())
     }
 
-    pub async fn get_user_by_name(&self, name:&str) -> Result<User, Error> {
+    async fn get_user_by_name(&self, name:&str) -> Result<User, AppError> {
         let filter = doc! {"name": name};
         let result = self
             .col
             .find_one(filter, None)
-            .await
-            .ok()
-            .expect("Failed to fetch user");
+            .await?;
 
         match result {
             Some(user) => {Ok(user)},
             None => {
-                return Err(Error::DeserializationError { message: format!("user not found") });
+                Err(AppError::NotFoundError("user not found".to_string()))
             },
         }
     }
 
-}
\ No newline at end of file
+}
+
+// Network failures, an unreachable server and errors the driver labels as transient
+// are worth retrying, a broken unique index is a conflict, everything else is reported
+// as a plain storage failure
+impl From<error::Error> for AppError {
+    fn from(e: error::Error) -> Self {
+        let duplicate = match e.kind.as_ref() {
+            ErrorKind::Write(WriteFailure::WriteError(failure)) => failure.code == DUPLICATE_KEY,
+            ErrorKind::Command(failure) => failure.code == DUPLICATE_KEY,
+            _ => false,
+        };
+        if duplicate {
+            return AppError::ConflictError("a record with the same key already exists".to_string());
+        }
+
+        let retryable = matches!(
+            e.kind.as_ref(),
+            ErrorKind::Io(_) | ErrorKind::ConnectionPoolCleared { .. } | ErrorKind::ServerSelection { .. }
+        ) || e.contains_label(error::RETRYABLE_WRITE_ERROR) || e.contains_label(error::TRANSIENT_TRANSACTION_ERROR);
+
+        if retryable {
+            return AppError::UnavailableError(e.to_string());
+        }
+        AppError::StorageError(e.to_string())
+    }
+}
+
+impl From<bson::de::Error> for AppError {
+    fn from(e: bson::de::Error) -> Self {
+        AppError::StorageError(format!("malformed document: {}", e))
+    }
+}
+
+impl From<bson::ser::Error> for AppError {
+    fn from(e: bson::ser::Error) -> Self {
+        AppError::StorageError(e.to_string())
+    }
+}
+
+impl From<results::InsertOneResult> for InsertResult {
+    fn from(result: results::InsertOneResult) -> Self {
+        InsertResult { inserted_id: result.inserted_id.as_object_id().unwrap_or_default() }
+    }
+}
+
+impl From<results::UpdateResult> for UpdateResult {
+    fn from(result: results::UpdateResult) -> Self {
+        UpdateResult { matched_count: result.matched_count }
+    }
+}
+
+impl From<results::DeleteResult> for DeleteResult {
+    fn from(result: results::DeleteResult) -> Self {
+        DeleteResult { deleted_count: result.deleted_count }
+    }
+}
+
+// Translates list filters into a query document, `with_cursor` adds the `after` bound
+// which must be left out when counting the total
+pub fn list_filter(query:&ListQuery, with_cursor:bool) -> Document {
+    let mut conditions:Vec<Document> = query.filters.iter().map(|filter| {
+        let value = match &filter.value {
+            FilterValue::Text(text) => Bson::String(text.to_owned()),
+            FilterValue::Int(number) => Bson::Int64(*number),
+        };
+        let op = match filter.op {
+            FilterOp::Eq => "$eq",
+            FilterOp::Ne => "$ne",
+            FilterOp::Gt => "$gt",
+            FilterOp::Gte => "$gte",
+            FilterOp::Lt => "$lt",
+            FilterOp::Lte => "$lte",
+        };
+        doc! { filter.field.as_str(): { op: value } }
+    }).collect();
+
+    if let (true, Some(after)) = (with_cursor, query.after) {
+        conditions.push(doc! {"_id": {"$gt": after}});
+    }
+
+    if conditions.is_empty() {
+        return doc! {};
+    }
+    doc! {"$and": conditions}
+}
+
+pub fn list_options(query:&ListQuery) -> FindOptions {
+    let mut sort = Document::new();
+    for field in &query.sort {
+        let name = if field.field == "id" { "_id" } else { field.field.as_str() };
+        sort.insert(name, if field.descending { -1 } else { 1 });
+    }
+    if !sort.contains_key("_id") {
+        sort.insert("_id", 1);
+    }
+
+    FindOptions::builder()
+        .sort(sort)
+        .skip(query.skip())
+        .limit(query.per_page as i64)
+        .build()
+}
@@ -0,0 +1,50 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use mongodb::{
+    bson::{self, doc, Document},
+    options::IndexOptions,
+    Collection, Database, IndexModel
+};
+
+use crate::api::app_errors::AppError;
+use super::store::PasswordResetStore;
+
+// `{_id: token hash, user_id, expires_at: date}`, the TTL index drops expired tokens
+pub struct PasswordResetRepo {
+    col:Collection<Document>,
+}
+
+impl PasswordResetRepo {
+    pub async fn init(db:Database) -> Result<Self, AppError> {
+        let col:Collection<Document> = db.collection("PasswordReset");
+        let expiry = IndexModel::builder()
+            .keys(doc! {"expires_at": 1})
+            .options(IndexOptions::builder().expire_after(Duration::ZERO).build())
+            .build();
+        col.create_index(expiry, None).await?;
+        Ok(PasswordResetRepo {col})
+    }
+}
+
+#[async_trait]
+impl PasswordResetStore for PasswordResetRepo {
+    async fn insert_reset(&self, token_hash:&str, user_id:&str, expires_at:i64) -> Result<(), AppError> {
+        let reset = doc! {
+            "_id":token_hash,
+            "user_id":user_id,
+            "expires_at":bson::DateTime::from_millis(expires_at.saturating_mul(1000)),
+        };
+        self.col.insert_one(reset, None).await?;
+        Ok(())
+    }
+
+    async fn take_reset(&self, token_hash:&str) -> Result<Option<(String, i64)>, AppError> {
+        let reset = self.col.find_one_and_delete(doc! {"_id":token_hash}, None).await?;
+        Ok(reset.and_then(|reset| {
+            let user_id = reset.get_str("user_id").ok()?.to_string();
+            let expires_at = reset.get_datetime("expires_at").ok()?.timestamp_millis() / 1000;
+            Some((user_id, expires_at))
+        }))
+    }
+}
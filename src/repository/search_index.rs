@@ -0,0 +1,184 @@
+use std::{collections::{BTreeMap, HashMap}, sync::{PoisonError, RwLock}};
+
+use mongodb::bson::oid::ObjectId;
+use serde::{Serialize, Deserialize};
+
+use crate::model::{author_model::Author, book_model::Book};
+
+// Embedded full text index over book names and author names/emails. It is kept in
+// memory and updated by the indexed stores on every write, so it needs no extra service.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DocKind {
+    Book,
+    Author
+}
+
+type DocKey = (DocKind, ObjectId);
+
+// score multipliers for how a query term matched an indexed term
+const EXACT_SCORE:f64 = 3.0;
+const PREFIX_SCORE:f64 = 2.0;
+const FUZZY_SCORE:f64 = 1.0;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub kind:DocKind,
+    pub id:String,
+    pub score:f64,
+    pub fields:BTreeMap<String, String>
+}
+
+#[derive(Default)]
+struct IndexData {
+    // term -> document -> weight of the best field holding the term
+    terms:BTreeMap<String, HashMap<DocKey, f64>>,
+    // indexed fields of every document, used for removal and for the hits
+    docs:HashMap<DocKey, Vec<(&'static str, String, f64)>>
+}
+
+#[derive(Default)]
+pub struct SearchIndex {
+    data:RwLock<IndexData>
+}
+
+impl SearchIndex {
+    pub fn init() -> Self {
+        SearchIndex::default()
+    }
+
+    pub fn index_book(&self, id:ObjectId, book:&Book) {
+        self.insert((DocKind::Book, id), vec![("book_name", book.book_name.to_owned(), 2.0)]);
+    }
+
+    pub fn index_author(&self, id:ObjectId, author:&Author) {
+        self.insert((DocKind::Author, id), vec![
+            ("author_name", author.author_name.to_owned(), 2.0),
+            ("author_email", author.author_email.to_owned(), 1.0),
+        ]);
+    }
+
+    pub fn remove(&self, kind:DocKind, id:ObjectId) {
+        let mut data = self.data.write().unwrap_or_else(PoisonError::into_inner);
+        remove_doc(&mut data, &(kind, id));
+    }
+
+    fn insert(&self, key:DocKey, fields:Vec<(&'static str, String, f64)>) {
+        let mut data = self.data.write().unwrap_or_else(PoisonError::into_inner);
+        remove_doc(&mut data, &key);
+
+        for (_, value, weight) in &fields {
+            for term in tokenize(value) {
+                let postings = data.terms.entry(term).or_default();
+                let best = postings.entry(key).or_insert(0.0);
+                *best = best.max(*weight);
+            }
+        }
+        data.docs.insert(key, fields);
+    }
+
+    // Every query term has to match (exactly, as a prefix or within the typo budget);
+    // documents are ranked by the sum of their best match per term
+    pub fn search(&self, query:&str, kind:Option<DocKind>, limit:usize) -> Vec<SearchHit> {
+        let query_terms = tokenize(query);
+        if query_terms.is_empty() {
+            return Vec::new();
+        }
+
+        let data = self.data.read().unwrap_or_else(PoisonError::into_inner);
+        let mut scores:Option<HashMap<DocKey, f64>> = None;
+
+        for query_term in &query_terms {
+            let mut term_scores:HashMap<DocKey, f64> = HashMap::new();
+            let max_typos = match query_term.chars().count() {
+                0..=3 => 0,
+                4..=7 => 1,
+                _ => 2,
+            };
+
+            for (term, postings) in data.terms.iter() {
+                let factor = if term == query_term {
+                    EXACT_SCORE
+                } else if term.starts_with(query_term.as_str()) {
+                    PREFIX_SCORE
+                } else if max_typos > 0 && edit_distance(term, query_term) <= max_typos {
+                    FUZZY_SCORE
+                } else {
+                    continue;
+                };
+
+                for (key, weight) in postings {
+                    if kind.is_some_and(|k| k != key.0) {
+                        continue;
+                    }
+                    let score = term_scores.entry(*key).or_insert(0.0);
+                    *score = score.max(factor * weight);
+                }
+            }
+
+            scores = Some(match scores {
+                None => term_scores,
+                Some(previous) => previous
+                    .into_iter()
+                    .filter_map(|(key, score)| term_scores.get(&key).map(|s| (key, score + s)))
+                    .collect(),
+            });
+        }
+
+        let mut hits:Vec<SearchHit> = scores
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(key, score)| SearchHit {
+                kind: key.0,
+                id: key.1.to_hex(),
+                score,
+                fields: data.docs.get(&key)
+                    .map(|fields| fields.iter().map(|(name, value, _)| (name.to_string(), value.to_owned())).collect())
+                    .unwrap_or_default(),
+            })
+            .collect();
+
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.id.cmp(&b.id)));
+        hits.truncate(limit);
+        hits
+    }
+}
+
+fn remove_doc(data:&mut IndexData, key:&DocKey) {
+    if let Some(fields) = data.docs.remove(key) {
+        for (_, value, _) in fields {
+            for term in tokenize(&value) {
+                if let Some(postings) = data.terms.get_mut(&term) {
+                    postings.remove(key);
+                    if postings.is_empty() {
+                        data.terms.remove(&term);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn tokenize(text:&str) -> Vec<String> {
+    text.split(|c:char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(|term| term.to_lowercase())
+        .collect()
+}
+
+// Levenshtein distance, counted in chars
+fn edit_distance(a:&str, b:&str) -> usize {
+    let b:Vec<char> = b.chars().collect();
+    let mut previous:Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            current[j + 1] = (previous[j] + cost).min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
@@ -0,0 +1,1046 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use mongodb::bson::{self, oid::ObjectId};
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::{any::AnyRow, AnyPool, Row, TypeInfo, ValueRef};
+
+use crate::{api::app_errors::AppError, model::{api_key_model::ApiKey, author_model::{Author, AuthorPatch, AuthorProfile}, book_model::{Book, BookPatch}, user_model::{Role, User, UserPatch}}};
+use crate::utils::pagination::{FilterOp, FilterValue, ListQuery, Page};
+use super::store::{ApiKeyStore, AuthorStore, BlobStore, BookStore, DeleteResult, InsertResult, LoginAttemptStore, PasswordResetStore, TokenRevocationStore, UpdateResult, UserStore};
+use super::store::{LOCKOUT_WINDOW, MAX_FAILED_LOGINS};
+
+// Relational backend working on both SQLite and PostgreSQL through the sqlx Any driver.
+// Ids are kept as ObjectId hex strings so the API looks the same as with MongoDB.
+
+pub struct SqlBookRepo {
+    pool:AnyPool
+}
+
+pub struct SqlAuthorRepo {
+    pool:AnyPool
+}
+
+pub struct SqlUserRepo {
+    pool:AnyPool
+}
+
+pub struct SqlApiKeyRepo {
+    pool:AnyPool
+}
+
+pub struct SqlBlobRepo {
+    pool:AnyPool
+}
+
+// Revocations, resets and login attempts carry an `expires_at`, rows past it are removed
+// whenever one is written
+pub struct SqlTokenRevocationRepo {
+    pool:AnyPool
+}
+
+pub struct SqlPasswordResetRepo {
+    pool:AnyPool
+}
+
+pub struct SqlLoginAttemptRepo {
+    pool:AnyPool
+}
+
+impl SqlBookRepo {
+    pub fn init(pool:AnyPool) -> Self {
+        SqlBookRepo { pool }
+    }
+}
+
+impl SqlAuthorRepo {
+    pub fn init(pool:AnyPool) -> Self {
+        SqlAuthorRepo { pool }
+    }
+}
+
+impl SqlUserRepo {
+    pub fn init(pool:AnyPool) -> Self {
+        SqlUserRepo { pool }
+    }
+}
+
+impl SqlApiKeyRepo {
+    pub fn init(pool:AnyPool) -> Self {
+        SqlApiKeyRepo { pool }
+    }
+}
+
+impl SqlBlobRepo {
+    pub fn init(pool:AnyPool) -> Self {
+        SqlBlobRepo { pool }
+    }
+}
+
+impl SqlTokenRevocationRepo {
+    pub fn init(pool:AnyPool) -> Self {
+        SqlTokenRevocationRepo { pool }
+    }
+}
+
+impl SqlPasswordResetRepo {
+    pub fn init(pool:AnyPool) -> Self {
+        SqlPasswordResetRepo { pool }
+    }
+}
+
+impl SqlLoginAttemptRepo {
+    pub fn init(pool:AnyPool) -> Self {
+        SqlLoginAttemptRepo { pool }
+    }
+}
+
+// Connection and pool failures are worth retrying, query and decoding errors are not. A
+// broken unique constraint is a conflict.
+fn sql_error(e:sqlx::Error) -> AppError {
+    match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+            AppError::ConflictError("a record with the same key already exists".to_string())
+        },
+        sqlx::Error::Io(_) | sqlx::Error::Tls(_) | sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed | sqlx::Error::WorkerCrashed => {
+            AppError::UnavailableError(e.to_string())
+        },
+        _ => AppError::StorageError(e.to_string()),
+    }
+}
+
+// The Any driver reports NULL values as non-null of type NULL, so `Option<T>` can't be
+// decoded directly and nullable columns go through here
+fn nullable_text(row:&AnyRow, column:&str) -> Result<Option<String>, sqlx::Error> {
+    let value = row.try_get_raw(column)?;
+    if value.type_info().name() == "NULL" {
+        return Ok(None);
+    }
+    row.try_get(column).map(Some)
+}
+
+fn parse_id(id:&str) -> Result<ObjectId, AppError> {
+    ObjectId::parse_str(id).map_err(|e| AppError::StorageError(e.to_string()))
+}
+
+// timestamps are stored as RFC 3339 text, which sorts and reads the same on every driver
+fn to_sql_time(time:bson::DateTime) -> String {
+    time.try_to_rfc3339_string().unwrap_or_default()
+}
+
+fn from_sql_time(time:Option<String>) -> Option<bson::DateTime> {
+    time.and_then(|t| bson::DateTime::parse_rfc3339_str(t).ok())
+}
+
+// Builds the WHERE clause of a list query, column names come from the model LIST_FIELDS
+// whitelist checked while parsing so only the values need to be bound
+fn list_where(query:&ListQuery, with_cursor:bool) -> (String, Vec<FilterValue>) {
+    let mut conditions:Vec<String> = Vec::new();
+    let mut values:Vec<FilterValue> = Vec::new();
+
+    for filter in &query.filters {
+        let op = match filter.op {
+            FilterOp::Eq => "=",
+            FilterOp::Ne => "<>",
+            FilterOp::Gt => ">",
+            FilterOp::Gte => ">=",
+            FilterOp::Lt => "<",
+            FilterOp::Lte => "<=",
+        };
+        values.push(filter.value.clone());
+        conditions.push(format!("{} {} ${}", filter.field, op, values.len()));
+    }
+
+    if let (true, Some(after)) = (with_cursor, query.after) {
+        values.push(FilterValue::Text(after.to_hex()));
+        conditions.push(format!("id > ${}", values.len()));
+    }
+
+    if conditions.is_empty() {
+        return (String::new(), values);
+    }
+    (format!(" WHERE {}", conditions.join(" AND ")), values)
+}
+
+fn list_order(query:&ListQuery) -> String {
+    let mut order:Vec<String> = query.sort.iter()
+        .map(|field| format!("{} {}", field.field, if field.descending { "DESC" } else { "ASC" }))
+        .collect();
+    if !query.sort.iter().any(|field| field.field == "id") {
+        order.push("id ASC".to_string());
+    }
+    format!(" ORDER BY {} LIMIT {} OFFSET {}", order.join(", "), query.per_page, query.skip())
+}
+
+// Updates only the fields present in the serialized patch, the column names are the
+// patch struct field names so nothing coming from the client ends up in the SQL text
+async fn patch_row<P:Serialize>(pool:&AnyPool, table:&str, id:ObjectId, patch:&P, mut extra:Vec<(&str, FilterValue)>) -> Result<UpdateResult, AppError> {
+    let fields = match serde_json::to_value(patch).map_err(|e| AppError::StorageError(e.to_string()))? {
+        Value::Object(fields) => fields,
+        _ => return Ok(UpdateResult::default()),
+    };
+
+    let mut columns:Vec<(&str, FilterValue)> = Vec::new();
+    for (column, value) in fields.iter() {
+        match value {
+            Value::String(text) => columns.push((column.as_str(), FilterValue::Text(text.to_owned()))),
+            Value::Number(number) => columns.push((column.as_str(), FilterValue::Int(number.as_i64().unwrap_or_default()))),
+            _ => {},
+        }
+    }
+    columns.append(&mut extra);
+
+    let assignments:Vec<String> = columns.iter().enumerate().map(|(i, (column, _))| format!("{} = ${}", column, i + 1)).collect();
+    let sql = format!("UPDATE {} SET {} WHERE id = ${}", table, assignments.join(", "), columns.len() + 1);
+
+    let mut query = sqlx::query(&sql);
+    for (_, value) in columns {
+        query = match value {
+            FilterValue::Text(text) => query.bind(text),
+            FilterValue::Int(number) => query.bind(number),
+        };
+    }
+    let result = query.bind(id.to_hex()).execute(pool).await.map_err(sql_error)?;
+
+    Ok(UpdateResult { matched_count: result.rows_affected() })
+}
+
+async fn fetch_page(pool:&AnyPool, table:&str, query:&ListQuery) -> Result<(Vec<AnyRow>, u64), AppError> {
+    let (count_where, count_values) = list_where(query, false);
+    let count_sql = format!("SELECT COUNT(*) AS total FROM {}{}", table, count_where);
+    let mut count_query = sqlx::query(&count_sql);
+    for value in count_values {
+        count_query = match value {
+            FilterValue::Text(text) => count_query.bind(text),
+            FilterValue::Int(number) => count_query.bind(number),
+        };
+    }
+    let total:i64 = count_query.fetch_one(pool).await.map_err(sql_error)?.try_get("total").map_err(sql_error)?;
+
+    let (select_where, select_values) = list_where(query, true);
+    let select_sql = format!("SELECT * FROM {}{}{}", table, select_where, list_order(query));
+    let mut select_query = sqlx::query(&select_sql);
+    for value in select_values {
+        select_query = match value {
+            FilterValue::Text(text) => select_query.bind(text),
+            FilterValue::Int(number) => select_query.bind(number),
+        };
+    }
+    let rows = select_query.fetch_all(pool).await.map_err(sql_error)?;
+
+    Ok((rows, total as u64))
+}
+
+fn book_from_row(row:&AnyRow) -> Result<Book, AppError> {
+    let id:String = row.try_get("id").map_err(sql_error)?;
+    Ok(Book {
+        id:Some(parse_id(&id)?),
+        book_name:row.try_get("book_name").map_err(sql_error)?,
+        book_author:row.try_get("book_author").map_err(sql_error)?,
+        total_page:row.try_get("total_page").map_err(sql_error)?,
+        created_by:nullable_text(row, "created_by").map_err(sql_error)?,
+    })
+}
+
+fn author_from_row(row:&AnyRow) -> Result<Author, AppError> {
+    let id:String = row.try_get("id").map_err(sql_error)?;
+    let created_at = nullable_text(row, "created_at").map_err(sql_error)?;
+    Ok(Author {
+        id:Some(parse_id(&id)?),
+        author_name:row.try_get("author_name").map_err(sql_error)?,
+        contact:row.try_get("contact").map_err(sql_error)?,
+        author_email:row.try_get("author_email").map_err(sql_error)?,
+        created_at:from_sql_time(created_at),
+        created_by:nullable_text(row, "created_by").map_err(sql_error)?,
+    })
+}
+
+fn profile_from_row(row:&AnyRow) -> Result<AuthorProfile, AppError> {
+    let id:String = row.try_get("id").map_err(sql_error)?;
+    let author_id:String = row.try_get("author_id").map_err(sql_error)?;
+    let created_at = nullable_text(row, "created_at").map_err(sql_error)?;
+    Ok(AuthorProfile {
+        id:Some(parse_id(&id)?),
+        author_id:parse_id(&author_id)?,
+        profile_imgae:row.try_get("profile_image").map_err(sql_error)?,
+        digest:row.try_get("digest").map_err(sql_error)?,
+        size:row.try_get::<i64, _>("size").map_err(sql_error)?.max(0) as u64,
+        content_type:row.try_get("content_type").map_err(sql_error)?,
+        created_at:from_sql_time(created_at),
+    })
+}
+
+fn user_from_row(row:&AnyRow) -> Result<User, AppError> {
+    let id:String = row.try_get("id").map_err(sql_error)?;
+    let role:String = row.try_get("role").map_err(sql_error)?;
+    Ok(User {
+        id:Some(parse_id(&id)?),
+        name:row.try_get("name").map_err(sql_error)?,
+        location:row.try_get("location").map_err(sql_error)?,
+        title:row.try_get("title").map_err(sql_error)?,
+        password_hash:nullable_text(row, "password_hash").map_err(sql_error)?,
+        role:Role::parse(&role).ok_or_else(|| AppError::StorageError(format!("unknown role {}", role)))?,
+    })
+}
+
+// scopes are kept as a JSON array of their names
+fn api_key_from_row(row:&AnyRow) -> Result<ApiKey, AppError> {
+    let id:String = row.try_get("id").map_err(sql_error)?;
+    let scopes:String = row.try_get("scopes").map_err(sql_error)?;
+    let created_at = nullable_text(row, "created_at").map_err(sql_error)?;
+    let last_used_at = nullable_text(row, "last_used_at").map_err(sql_error)?;
+    let revoked:i32 = row.try_get("revoked").map_err(sql_error)?;
+    Ok(ApiKey {
+        id:Some(parse_id(&id)?),
+        name:row.try_get("name").map_err(sql_error)?,
+        prefix:row.try_get("prefix").map_err(sql_error)?,
+        key_hash:row.try_get("key_hash").map_err(sql_error)?,
+        scopes:serde_json::from_str(&scopes).map_err(|e| AppError::StorageError(e.to_string()))?,
+        created_by:row.try_get("created_by").map_err(sql_error)?,
+        created_at:from_sql_time(created_at).unwrap_or_else(bson::DateTime::now),
+        last_used_at:from_sql_time(last_used_at),
+        revoked:revoked != 0,
+    })
+}
+
+#[async_trait]
+impl BookStore for SqlBookRepo {
+    async fn create_book(&self, new_book:Book) -> Result<InsertResult, AppError> {
+        let id = ObjectId::new();
+
+        sqlx::query("INSERT INTO books (id, book_name, book_author, total_page, created_by) VALUES ($1, $2, $3, $4, $5)")
+            .bind(id.to_hex())
+            .bind(new_book.book_name)
+            .bind(new_book.book_author)
+            .bind(new_book.total_page)
+            .bind(new_book.created_by)
+            .execute(&self.pool)
+            .await
+            .map_err(sql_error)?;
+
+        Ok(InsertResult { inserted_id: id })
+    }
+
+    async fn get_book(&self, id:ObjectId) -> Result<Book, AppError> {
+        let row = sqlx::query("SELECT * FROM books WHERE id = $1")
+            .bind(id.to_hex())
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(sql_error)?;
+
+        match row {
+            Some(row) => book_from_row(&row),
+            None => Err(AppError::NotFoundError("book not found for this id".to_string())),
+        }
+    }
+
+    async fn get_books(&self, query:&ListQuery) -> Result<Page<Book>, AppError> {
+        let (rows, total) = fetch_page(&self.pool, "books", query).await?;
+        let items = rows.iter().map(book_from_row).collect::<Result<Vec<Book>, AppError>>()?;
+        Ok(Page { items, total })
+    }
+
+    async fn update_book(&self, id:ObjectId, data:Book) -> Result<UpdateResult, AppError> {
+        let result = sqlx::query("UPDATE books SET book_name = $1, book_author = $2, total_page = $3 WHERE id = $4")
+            .bind(data.book_name)
+            .bind(data.book_author)
+            .bind(data.total_page)
+            .bind(id.to_hex())
+            .execute(&self.pool)
+            .await
+            .map_err(sql_error)?;
+
+        Ok(UpdateResult { matched_count: result.rows_affected() })
+    }
+
+    async fn patch_book(&self, id:ObjectId, patch:BookPatch) -> Result<UpdateResult, AppError> {
+        patch_row(&self.pool, "books", id, &patch, Vec::new()).await
+    }
+
+    async fn delete_book(&self, id:ObjectId) -> Result<DeleteResult, AppError> {
+        let result = sqlx::query("DELETE FROM books WHERE id = $1")
+            .bind(id.to_hex())
+            .execute(&self.pool)
+            .await
+            .map_err(sql_error)?;
+
+        Ok(DeleteResult { deleted_count: result.rows_affected() })
+    }
+
+    async fn get_book_by_author(&self, id:String) -> Result<Vec<Book>, AppError> {
+        let rows = sqlx::query("SELECT * FROM books WHERE book_author = $1 ORDER BY id")
+            .bind(id)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(sql_error)?;
+
+        rows.iter().map(book_from_row).collect()
+    }
+
+    async fn delete_books_by_author(&self, author_id:String) -> Result<DeleteResult, AppError> {
+        let result = sqlx::query("DELETE FROM books WHERE book_author = $1")
+            .bind(author_id)
+            .execute(&self.pool)
+            .await
+            .map_err(sql_error)?;
+
+        Ok(DeleteResult { deleted_count: result.rows_affected() })
+    }
+
+    async fn unset_book_author(&self, author_id:String) -> Result<UpdateResult, AppError> {
+        let result = sqlx::query("UPDATE books SET book_author = '' WHERE book_author = $1")
+            .bind(author_id)
+            .execute(&self.pool)
+            .await
+            .map_err(sql_error)?;
+
+        Ok(UpdateResult { matched_count: result.rows_affected() })
+    }
+}
+
+#[async_trait]
+impl AuthorStore for SqlAuthorRepo {
+    async fn create_author(&self, author:Author) -> Result<InsertResult, AppError> {
+        let id = ObjectId::new();
+        let created_at = author.created_at.unwrap_or_else(bson::DateTime::now);
+
+        sqlx::query("INSERT INTO authors (id, author_name, contact, author_email, created_at, created_by) VALUES ($1, $2, $3, $4, $5, $6)")
+            .bind(id.to_hex())
+            .bind(author.author_name)
+            .bind(author.contact)
+            .bind(author.author_email)
+            .bind(to_sql_time(created_at))
+            .bind(author.created_by)
+            .execute(&self.pool)
+            .await
+            .map_err(sql_error)?;
+
+        Ok(InsertResult { inserted_id: id })
+    }
+
+    async fn get_authors(&self, query:&ListQuery) -> Result<Page<Author>, AppError> {
+        let (rows, total) = fetch_page(&self.pool, "authors", query).await?;
+        let items = rows.iter().map(author_from_row).collect::<Result<Vec<Author>, AppError>>()?;
+        Ok(Page { items, total })
+    }
+
+    async fn get_author(&self, id:ObjectId) -> Result<Author, AppError> {
+        let row = sqlx::query("SELECT * FROM authors WHERE id = $1")
+            .bind(id.to_hex())
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(sql_error)?;
+
+        match row {
+            Some(row) => author_from_row(&row),
+            None => Err(AppError::NotFoundError("Data not found".to_string())),
+        }
+    }
+
+    async fn update_author(&self, id:ObjectId, author:Author) -> Result<UpdateResult, AppError> {
+        let result = sqlx::query("UPDATE authors SET author_name = $1, author_email = $2, contact = $3, updated_at = $4 WHERE id = $5")
+            .bind(author.author_name)
+            .bind(author.author_email)
+            .bind(author.contact)
+            .bind(to_sql_time(bson::DateTime::now()))
+            .bind(id.to_hex())
+            .execute(&self.pool)
+            .await
+            .map_err(sql_error)?;
+
+        Ok(UpdateResult { matched_count: result.rows_affected() })
+    }
+
+    async fn patch_author(&self, id:ObjectId, patch:AuthorPatch) -> Result<UpdateResult, AppError> {
+        let updated_at = FilterValue::Text(to_sql_time(bson::DateTime::now()));
+        patch_row(&self.pool, "authors", id, &patch, vec![("updated_at", updated_at)]).await
+    }
+
+    async fn delete_author(&self, id:ObjectId) -> Result<DeleteResult, AppError> {
+        let result = sqlx::query("DELETE FROM authors WHERE id = $1")
+            .bind(id.to_hex())
+            .execute(&self.pool)
+            .await
+            .map_err(sql_error)?;
+
+        Ok(DeleteResult { deleted_count: result.rows_affected() })
+    }
+
+    // ids are ObjectIds in hex, so they sort in the order the profiles were stored
+    async fn upload_profile_pic(&self, author:AuthorProfile) -> Result<Vec<AuthorProfile>, AppError> {
+        let id = ObjectId::new();
+        let created_at = author.created_at.unwrap_or_else(bson::DateTime::now);
+
+        let mut tx = self.pool.begin().await.map_err(sql_error)?;
+        sqlx::query("INSERT INTO author_profiles (id, author_id, profile_image, digest, size, content_type, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7)")
+            .bind(id.to_hex())
+            .bind(author.author_id.to_hex())
+            .bind(author.profile_imgae)
+            .bind(author.digest)
+            .bind(author.size as i64)
+            .bind(author.content_type)
+            .bind(to_sql_time(created_at))
+            .execute(&mut *tx)
+            .await
+            .map_err(sql_error)?;
+
+        let rows = sqlx::query("SELECT * FROM author_profiles WHERE author_id = $1 AND id < $2 ORDER BY id")
+            .bind(author.author_id.to_hex())
+            .bind(id.to_hex())
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(sql_error)?;
+        // one by one, so a profile removed concurrently is returned by only one caller
+        let mut replaced = Vec::new();
+        for row in &rows {
+            let profile = profile_from_row(row)?;
+            let result = sqlx::query("DELETE FROM author_profiles WHERE id = $1")
+                .bind(profile.id.unwrap_or_default().to_hex())
+                .execute(&mut *tx)
+                .await
+                .map_err(sql_error)?;
+            if result.rows_affected() > 0 {
+                replaced.push(profile);
+            }
+        }
+        tx.commit().await.map_err(sql_error)?;
+        Ok(replaced)
+    }
+
+    async fn get_profile_pic(&self, id:ObjectId) -> Result<AuthorProfile, AppError> {
+        let row = sqlx::query("SELECT * FROM author_profiles WHERE author_id = $1 ORDER BY id DESC LIMIT 1")
+            .bind(id.to_hex())
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(sql_error)?;
+
+        match row {
+            Some(row) => profile_from_row(&row),
+            None => Err(AppError::NotFoundError("Author profile not found".to_string())),
+        }
+    }
+
+    async fn delete_profile_pics(&self, author_id:ObjectId) -> Result<Vec<AuthorProfile>, AppError> {
+        let rows = sqlx::query("SELECT * FROM author_profiles WHERE author_id = $1 ORDER BY id")
+            .bind(author_id.to_hex())
+            .fetch_all(&self.pool)
+            .await
+            .map_err(sql_error)?;
+
+        // one by one, so a profile removed concurrently is returned by only one caller
+        let mut deleted = Vec::new();
+        for row in &rows {
+            let profile = profile_from_row(row)?;
+            let result = sqlx::query("DELETE FROM author_profiles WHERE id = $1")
+                .bind(profile.id.unwrap_or_default().to_hex())
+                .execute(&self.pool)
+                .await
+                .map_err(sql_error)?;
+            if result.rows_affected() > 0 {
+                deleted.push(profile);
+            }
+        }
+        Ok(deleted)
+    }
+}
+
+#[async_trait]
+impl UserStore for SqlUserRepo {
+    async fn create_user(&self, new_user:User) -> Result<InsertResult, AppError> {
+        let id = ObjectId::new();
+
+        sqlx::query("INSERT INTO users (id, name, location, title, password_hash, role) VALUES ($1, $2, $3, $4, $5, $6)")
+            .bind(id.to_hex())
+            .bind(new_user.name)
+            .bind(new_user.location)
+            .bind(new_user.title)
+            .bind(new_user.password_hash)
+            .bind(new_user.role.as_str())
+            .execute(&self.pool)
+            .await
+            .map_err(sql_error)?;
+
+        Ok(InsertResult { inserted_id: id })
+    }
+
+    async fn get_user(&self, id:&str) -> Result<User, AppError> {
+        let obj_id = match ObjectId::parse_str(id) {
+            Ok(obj) => obj,
+            Err(_) => {
+                return Err(AppError::InvalidIdError);
+            }
+        };
+
+        let row = sqlx::query("SELECT * FROM users WHERE id = $1")
+            .bind(obj_id.to_hex())
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(sql_error)?;
+
+        match row {
+            Some(row) => user_from_row(&row),
+            None => Err(AppError::NotFoundError("user not found".to_string())),
+        }
+    }
+
+    async fn check_user_exists(&self, user_name:&str) -> Result<User, AppError> {
+        self.get_user_by_name(user_name).await
+    }
+
+    async fn get_all_users(&self, query:&ListQuery) -> Result<Page<User>, AppError> {
+        let (rows, total) = fetch_page(&self.pool, "users", query).await?;
+        let items = rows.iter().map(user_from_row).collect::<Result<Vec<User>, AppError>>()?;
+        Ok(Page { items, total })
+    }
+
+    async fn update_user(&self, id:&str, new_user:User) -> Result<UpdateResult, AppError> {
+        let obj_id = match ObjectId::parse_str(id) {
+            Ok(id) => id,
+            Err(_) => {
+                return Err(AppError::InvalidIdError);
+            }
+        };
+
+        let result = sqlx::query("UPDATE users SET name = $1, location = $2, title = $3 WHERE id = $4")
+            .bind(new_user.name)
+            .bind(new_user.location)
+            .bind(new_user.title)
+            .bind(obj_id.to_hex())
+            .execute(&self.pool)
+            .await
+            .map_err(sql_error)?;
+
+        Ok(UpdateResult { matched_count: result.rows_affected() })
+    }
+
+    async fn patch_user(&self, id:&str, patch:UserPatch) -> Result<UpdateResult, AppError> {
+        let obj_id = ObjectId::parse_str(id).map_err(|_| AppError::InvalidIdError)?;
+        patch_row(&self.pool, "users", obj_id, &patch, Vec::new()).await
+    }
+
+    async fn set_password_hash(&self, id:&str, password_hash:String) -> Result<UpdateResult, AppError> {
+        let obj_id = ObjectId::parse_str(id).map_err(|_| AppError::InvalidIdError)?;
+
+        let result = sqlx::query("UPDATE users SET password_hash = $1 WHERE id = $2")
+            .bind(password_hash)
+            .bind(obj_id.to_hex())
+            .execute(&self.pool)
+            .await
+            .map_err(sql_error)?;
+
+        Ok(UpdateResult { matched_count: result.rows_affected() })
+    }
+
+    async fn set_role(&self, id:&str, role:Role) -> Result<UpdateResult, AppError> {
+        let obj_id = ObjectId::parse_str(id).map_err(|_| AppError::InvalidIdError)?;
+
+        let result = sqlx::query("UPDATE users SET role = $1 WHERE id = $2")
+            .bind(role.as_str())
+            .bind(obj_id.to_hex())
+            .execute(&self.pool)
+            .await
+            .map_err(sql_error)?;
+
+        Ok(UpdateResult { matched_count: result.rows_affected() })
+    }
+
+    async fn delete_user(&self, id:&ObjectId) -> Result<DeleteResult, AppError> {
+        let result = sqlx::query("DELETE FROM users WHERE id = $1")
+            .bind(id.to_hex())
+            .execute(&self.pool)
+            .await
+            .map_err(sql_error)?;
+
+        Ok(DeleteResult { deleted_count: result.rows_affected() })
+    }
+
+    async fn get_user_by_name(&self, name:&str) -> Result<User, AppError> {
+        let row = sqlx::query("SELECT * FROM users WHERE name = $1 LIMIT 1")
+            .bind(name.to_string())
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(sql_error)?;
+
+        match row {
+            Some(row) => user_from_row(&row),
+            None => Err(AppError::NotFoundError("user not found".to_string())),
+        }
+    }
+}
+
+#[async_trait]
+impl ApiKeyStore for SqlApiKeyRepo {
+    async fn create_api_key(&self, api_key:ApiKey) -> Result<InsertResult, AppError> {
+        let id = ObjectId::new();
+        let scopes = serde_json::to_string(&api_key.scopes).map_err(|e| AppError::StorageError(e.to_string()))?;
+
+        sqlx::query("INSERT INTO api_keys (id, name, prefix, key_hash, scopes, created_by, created_at, revoked) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)")
+            .bind(id.to_hex())
+            .bind(api_key.name)
+            .bind(api_key.prefix)
+            .bind(api_key.key_hash)
+            .bind(scopes)
+            .bind(api_key.created_by)
+            .bind(to_sql_time(api_key.created_at))
+            .bind(api_key.revoked as i32)
+            .execute(&self.pool)
+            .await
+            .map_err(sql_error)?;
+
+        Ok(InsertResult { inserted_id: id })
+    }
+
+    async fn get_api_keys(&self) -> Result<Vec<ApiKey>, AppError> {
+        let rows = sqlx::query("SELECT * FROM api_keys ORDER BY id")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(sql_error)?;
+
+        rows.iter().map(api_key_from_row).collect()
+    }
+
+    async fn get_api_key_by_hash(&self, key_hash:&str) -> Result<ApiKey, AppError> {
+        let row = sqlx::query("SELECT * FROM api_keys WHERE key_hash = $1")
+            .bind(key_hash.to_string())
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(sql_error)?;
+
+        match row {
+            Some(row) => api_key_from_row(&row),
+            None => Err(AppError::NotFoundError("API key not found".to_string())),
+        }
+    }
+
+    async fn revoke_api_key(&self, id:ObjectId) -> Result<UpdateResult, AppError> {
+        let result = sqlx::query("UPDATE api_keys SET revoked = 1 WHERE id = $1")
+            .bind(id.to_hex())
+            .execute(&self.pool)
+            .await
+            .map_err(sql_error)?;
+
+        Ok(UpdateResult { matched_count: result.rows_affected() })
+    }
+
+    async fn set_api_key_last_used(&self, id:ObjectId, used_at:bson::DateTime) -> Result<UpdateResult, AppError> {
+        let result = sqlx::query("UPDATE api_keys SET last_used_at = $1 WHERE id = $2")
+            .bind(to_sql_time(used_at))
+            .bind(id.to_hex())
+            .execute(&self.pool)
+            .await
+            .map_err(sql_error)?;
+
+        Ok(UpdateResult { matched_count: result.rows_affected() })
+    }
+}
+
+#[async_trait]
+impl BlobStore for SqlBlobRepo {
+    async fn acquire_blob(&self, key:&str) -> Result<u64, AppError> {
+        let refs:i64 = sqlx::query_scalar("INSERT INTO blobs (file_key, refs) VALUES ($1, 1) ON CONFLICT (file_key) DO UPDATE SET refs = blobs.refs + 1 RETURNING refs")
+            .bind(key)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(sql_error)?;
+        Ok(refs.max(0) as u64)
+    }
+
+    async fn release_blob(&self, key:&str) -> Result<u64, AppError> {
+        let refs:Option<i64> = sqlx::query_scalar("UPDATE blobs SET refs = refs - 1 WHERE file_key = $1 AND refs > 0 RETURNING refs")
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(sql_error)?;
+        // files stored before counting started have no row, nothing else refers to them
+        let refs = refs.map_or(0, |refs| refs.max(0) as u64);
+        if refs == 0 {
+            sqlx::query("DELETE FROM blobs WHERE file_key = $1 AND refs <= 0")
+                .bind(key)
+                .execute(&self.pool)
+                .await
+                .map_err(sql_error)?;
+        }
+        Ok(refs)
+    }
+}
+
+// `table` is one of the tables with an `expires_at` column, never user input
+async fn delete_expired(pool:&AnyPool, table:&str, now:i64) -> Result<(), AppError> {
+    sqlx::query(&format!("DELETE FROM {} WHERE expires_at <= $1", table))
+        .bind(now)
+        .execute(pool)
+        .await
+        .map_err(sql_error)?;
+    Ok(())
+}
+
+#[async_trait]
+impl TokenRevocationStore for SqlTokenRevocationRepo {
+    async fn revoke_token(&self, id:&str, until:i64) -> Result<(), AppError> {
+        let now = Utc::now().timestamp();
+        delete_expired(&self.pool, "token_revocations", now).await?;
+        sqlx::query("INSERT INTO token_revocations (id, revoked_at, expires_at) VALUES ($1, $2, $3) ON CONFLICT (id) DO UPDATE SET revoked_at = excluded.revoked_at, expires_at = CASE WHEN token_revocations.expires_at > excluded.expires_at THEN token_revocations.expires_at ELSE excluded.expires_at END")
+            .bind(id)
+            .bind(now)
+            .bind(until)
+            .execute(&self.pool)
+            .await
+            .map_err(sql_error)?;
+        Ok(())
+    }
+
+    async fn revoked_at(&self, id:&str) -> Result<Option<i64>, AppError> {
+        sqlx::query_scalar("SELECT revoked_at FROM token_revocations WHERE id = $1 AND expires_at > $2")
+            .bind(id)
+            .bind(Utc::now().timestamp())
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(sql_error)
+    }
+}
+
+#[async_trait]
+impl PasswordResetStore for SqlPasswordResetRepo {
+    async fn insert_reset(&self, token_hash:&str, user_id:&str, expires_at:i64) -> Result<(), AppError> {
+        delete_expired(&self.pool, "password_resets", Utc::now().timestamp()).await?;
+        sqlx::query("INSERT INTO password_resets (token_hash, user_id, expires_at) VALUES ($1, $2, $3)")
+            .bind(token_hash)
+            .bind(user_id)
+            .bind(expires_at)
+            .execute(&self.pool)
+            .await
+            .map_err(sql_error)?;
+        Ok(())
+    }
+
+    async fn take_reset(&self, token_hash:&str) -> Result<Option<(String, i64)>, AppError> {
+        let row = sqlx::query("DELETE FROM password_resets WHERE token_hash = $1 RETURNING user_id, expires_at")
+            .bind(token_hash)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(sql_error)?;
+        match row {
+            Some(row) => Ok(Some((row.try_get("user_id").map_err(sql_error)?, row.try_get("expires_at").map_err(sql_error)?))),
+            None => Ok(None),
+        }
+    }
+}
+
+#[async_trait]
+impl LoginAttemptStore for SqlLoginAttemptRepo {
+    async fn login_locked_for(&self, name:&str) -> Result<Option<u64>, AppError> {
+        let now = Utc::now().timestamp();
+        let locked_until:Option<i64> = sqlx::query_scalar("SELECT locked_until FROM login_attempts WHERE name = $1")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(sql_error)?;
+        Ok(locked_until.filter(|locked_until| *locked_until > now).map(|locked_until| (locked_until - now) as u64))
+    }
+
+    // Every step is a single statement, so concurrent failures are all counted
+    async fn record_login_failure(&self, name:&str) -> Result<(), AppError> {
+        let now = Utc::now().timestamp();
+        delete_expired(&self.pool, "login_attempts", now).await?;
+
+        // a window that has run out starts over
+        sqlx::query("UPDATE login_attempts SET failures = 0, window_start = $1 WHERE name = $2 AND window_start <= $3 AND locked_until <= $4")
+            .bind(now)
+            .bind(name)
+            .bind(now - LOCKOUT_WINDOW)
+            .bind(now)
+            .execute(&self.pool)
+            .await
+            .map_err(sql_error)?;
+
+        let failures:i64 = sqlx::query_scalar("INSERT INTO login_attempts (name, failures, window_start, locked_until, expires_at) VALUES ($1, 1, $2, 0, $3) ON CONFLICT (name) DO UPDATE SET failures = login_attempts.failures + 1, expires_at = excluded.expires_at RETURNING failures")
+            .bind(name)
+            .bind(now)
+            .bind(now + LOCKOUT_WINDOW)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(sql_error)?;
+        if failures < MAX_FAILED_LOGINS {
+            return Ok(());
+        }
+
+        let locked = sqlx::query("UPDATE login_attempts SET failures = 0, window_start = $1, locked_until = $2, expires_at = $3 WHERE name = $4 AND failures >= $5")
+            .bind(now)
+            .bind(now + LOCKOUT_WINDOW)
+            .bind(now + LOCKOUT_WINDOW)
+            .bind(name)
+            .bind(MAX_FAILED_LOGINS)
+            .execute(&self.pool)
+            .await
+            .map_err(sql_error)?;
+        if locked.rows_affected() == 1 {
+            log::warn!("login for {} locked after {} failed attempts", name, MAX_FAILED_LOGINS);
+        }
+        Ok(())
+    }
+
+    async fn clear_login_failures(&self, name:&str) -> Result<(), AppError> {
+        sqlx::query("DELETE FROM login_attempts WHERE name = $1")
+            .bind(name)
+            .execute(&self.pool)
+            .await
+            .map_err(sql_error)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::any::{install_default_drivers, AnyPoolOptions};
+
+    use crate::utils::password::hash_token;
+    use super::*;
+
+    // a private in-memory database, one connection so every query sees the same one
+    async fn test_pool() -> AnyPool {
+        install_default_drivers();
+        let pool = AnyPoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        pool
+    }
+
+    #[actix_web::test]
+    async fn reads_users_without_password_hash() {
+        let pool = test_pool().await;
+        // as left behind by accounts created before passwords were required
+        sqlx::query("INSERT INTO users (id, name, location, title) VALUES ($1, 'legacy', 'here', 'reader')")
+            .bind(ObjectId::new().to_hex())
+            .execute(&pool)
+            .await
+            .unwrap();
+        let users = SqlUserRepo::init(pool);
+
+        let user = users.get_user_by_name("legacy").await.unwrap();
+        assert_eq!(user.password_hash, None);
+        assert_eq!(user.role, Role::Reader);
+        let page = users.get_all_users(&ListQuery::default()).await.unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.items[0].password_hash, None);
+
+        users.set_password_hash(&user.id.unwrap().to_hex(), "hash".to_string()).await.unwrap();
+        assert_eq!(users.get_user_by_name("legacy").await.unwrap().password_hash.as_deref(), Some("hash"));
+    }
+
+    #[actix_web::test]
+    async fn reads_books_and_authors_without_creator_or_timestamp() {
+        let pool = test_pool().await;
+        let author_id = ObjectId::new();
+        sqlx::query("INSERT INTO authors (id, author_name, contact, author_email) VALUES ($1, 'Ann', '123', 'ann@example.com')")
+            .bind(author_id.to_hex())
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO books (id, book_name, book_author, total_page) VALUES ($1, 'Book', $2, 10)")
+            .bind(ObjectId::new().to_hex())
+            .bind(author_id.to_hex())
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let author = SqlAuthorRepo::init(pool.clone()).get_author(author_id).await.unwrap();
+        assert_eq!((author.created_at, author.created_by), (None, None));
+        let books = SqlBookRepo::init(pool).get_book_by_author(author_id.to_hex()).await.unwrap();
+        assert_eq!(books[0].created_by, None);
+    }
+
+    #[actix_web::test]
+    async fn duplicate_user_names_conflict() {
+        let users = SqlUserRepo::init(test_pool().await);
+        let user = || User { id: None, name: "ann".to_string(), location: "here".to_string(), title: "dev".to_string(), password_hash: None, role: Role::Reader };
+
+        users.create_user(user()).await.unwrap();
+        assert!(matches!(users.create_user(user()).await, Err(AppError::ConflictError(_))));
+    }
+
+    #[actix_web::test]
+    async fn revocations_outlive_a_restart_until_they_expire() {
+        let pool = test_pool().await;
+        let now = Utc::now().timestamp();
+        let revocations = SqlTokenRevocationRepo::init(pool.clone());
+        revocations.revoke_token("session", now + 60).await.unwrap();
+        revocations.revoke_token("session", now + 30).await.unwrap();
+        revocations.revoke_token("expired", now - 1).await.unwrap();
+        revocations.revoke_user("ann", now + 60).await.unwrap();
+
+        let revocations = SqlTokenRevocationRepo::init(pool);
+        assert!(revocations.is_token_revoked("session").await.unwrap());
+        assert!(!revocations.is_token_revoked("expired").await.unwrap());
+        assert!(!revocations.is_token_revoked("other").await.unwrap());
+        assert!(revocations.is_user_revoked("ann", now - 10).await.unwrap());
+        assert!(!revocations.is_user_revoked("ann", now + 10).await.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn tokens_issued_in_the_second_of_a_user_revocation_stay_valid() {
+        let revocations = SqlTokenRevocationRepo::init(test_pool().await);
+        assert!(revocations.user_token_time("ann").await.unwrap() <= Utc::now().timestamp());
+
+        revocations.revoke_user("ann", Utc::now().timestamp() + 60).await.unwrap();
+        let revoked_at = revocations.revoked_at("user:ann").await.unwrap().unwrap();
+        let issued_at = revocations.user_token_time("ann").await.unwrap();
+        assert!(revocations.is_user_revoked("ann", revoked_at).await.unwrap());
+        assert!(!revocations.is_user_revoked("ann", issued_at).await.unwrap());
+        assert!(issued_at <= Utc::now().timestamp() + 1);
+    }
+
+    #[actix_web::test]
+    async fn reset_tokens_are_stored_hashed_and_redeemed_once() {
+        let pool = test_pool().await;
+        let resets = SqlPasswordResetRepo::init(pool.clone());
+        let token = resets.issue_reset("user").await.unwrap();
+
+        let stored:String = sqlx::query_scalar("SELECT token_hash FROM password_resets").fetch_one(&pool).await.unwrap();
+        assert_ne!(stored, token);
+        assert_eq!(resets.redeem_reset(&token).await.unwrap().as_deref(), Some("user"));
+        assert_eq!(resets.redeem_reset(&token).await.unwrap(), None);
+
+        resets.insert_reset(&hash_token("old"), "user", Utc::now().timestamp() - 1).await.unwrap();
+        assert_eq!(resets.redeem_reset("old").await.unwrap(), None);
+    }
+
+    #[actix_web::test]
+    async fn failed_logins_lock_the_name() {
+        let attempts = SqlLoginAttemptRepo::init(test_pool().await);
+        for _ in 1..MAX_FAILED_LOGINS {
+            attempts.record_login_failure("ann").await.unwrap();
+        }
+        assert_eq!(attempts.login_locked_for("ann").await.unwrap(), None);
+
+        attempts.record_login_failure("ann").await.unwrap();
+        let locked_for = attempts.login_locked_for("ann").await.unwrap().unwrap();
+        assert!(locked_for > 0 && locked_for <= LOCKOUT_WINDOW as u64);
+        assert_eq!(attempts.login_locked_for("bob").await.unwrap(), None);
+
+        attempts.clear_login_failures("ann").await.unwrap();
+        assert_eq!(attempts.login_locked_for("ann").await.unwrap(), None);
+    }
+
+    #[actix_web::test]
+    async fn a_new_profile_replaces_the_older_ones() {
+        let authors = SqlAuthorRepo::init(test_pool().await);
+        let author = Author { id: None, author_name: "Ann".to_string(), contact: String::new(), author_email: "ann@example.com".to_string(), created_at: None, created_by: None };
+        let author_id = authors.create_author(author).await.unwrap().inserted_id;
+        let profile = |key:&str| AuthorProfile {
+            id: None,
+            author_id,
+            profile_imgae: key.to_string(),
+            digest: String::new(),
+            size: 1,
+            content_type: "image/png".to_string(),
+            created_at: None,
+        };
+
+        assert!(authors.upload_profile_pic(profile("first.png")).await.unwrap().is_empty());
+        let replaced = authors.upload_profile_pic(profile("second.png")).await.unwrap();
+        assert_eq!(replaced.iter().map(|p| p.profile_imgae.as_str()).collect::<Vec<_>>(), ["first.png"]);
+        assert_eq!(authors.get_profile_pic(author_id).await.unwrap().profile_imgae, "second.png");
+
+        let deleted = authors.delete_profile_pics(author_id).await.unwrap();
+        assert_eq!(deleted.len(), 1);
+    }
+}
@@ -0,0 +1,192 @@
+use std::{ops::Range, path::Path, time::SystemTime};
+
+use actix_web::web::Bytes;
+use async_trait::async_trait;
+use chrono::Utc;
+use futures::stream::BoxStream;
+use mongodb::bson::{self, oid::ObjectId};
+use serde::{Serialize, Deserialize};
+
+use crate::api::app_errors::AppError;
+use crate::model::{api_key_model::ApiKey, author_model::{Author, AuthorPatch, AuthorProfile}, book_model::{Book, BookPatch}, user_model::{Role, User, UserPatch}};
+use crate::utils::{pagination::{ListQuery, Page}, password::{hash_token, random_token}};
+
+// Storage agnostic results returned by every backend
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InsertResult {
+    pub inserted_id:ObjectId
+}
+
+#[derive(Debug, Default)]
+pub struct UpdateResult {
+    pub matched_count:u64
+}
+
+#[derive(Debug, Default)]
+pub struct DeleteResult {
+    pub deleted_count:u64
+}
+
+#[async_trait]
+pub trait BookStore: Send + Sync {
+    async fn create_book(&self, new_book:Book) -> Result<InsertResult, AppError>;
+    async fn get_book(&self, id:ObjectId) -> Result<Book, AppError>;
+    async fn get_books(&self, query:&ListQuery) -> Result<Page<Book>, AppError>;
+    async fn update_book(&self, id:ObjectId, data:Book) -> Result<UpdateResult, AppError>;
+    async fn patch_book(&self, id:ObjectId, patch:BookPatch) -> Result<UpdateResult, AppError>;
+    async fn delete_book(&self, id:ObjectId) -> Result<DeleteResult, AppError>;
+    async fn get_book_by_author(&self, id:String) -> Result<Vec<Book>, AppError>;
+    async fn delete_books_by_author(&self, author_id:String) -> Result<DeleteResult, AppError>;
+    // clears `book_author` on every book of the author, leaving it empty
+    async fn unset_book_author(&self, author_id:String) -> Result<UpdateResult, AppError>;
+}
+
+#[async_trait]
+pub trait AuthorStore: Send + Sync {
+    async fn create_author(&self, author:Author) -> Result<InsertResult, AppError>;
+    async fn get_authors(&self, query:&ListQuery) -> Result<Page<Author>, AppError>;
+    async fn get_author(&self, id:ObjectId) -> Result<Author, AppError>;
+    async fn update_author(&self, id:ObjectId, author:Author) -> Result<UpdateResult, AppError>;
+    async fn patch_author(&self, id:ObjectId, patch:AuthorPatch) -> Result<UpdateResult, AppError>;
+    async fn delete_author(&self, id:ObjectId) -> Result<DeleteResult, AppError>;
+    // stores the profile in place of the older ones of the author and returns those, so their
+    // files can be released
+    async fn upload_profile_pic(&self, author:AuthorProfile) -> Result<Vec<AuthorProfile>, AppError>;
+    // the newest profile of the author
+    async fn get_profile_pic(&self, id:ObjectId) -> Result<AuthorProfile, AppError>;
+    // removes every profile of the author and returns them, so their files can be released
+    async fn delete_profile_pics(&self, author_id:ObjectId) -> Result<Vec<AuthorProfile>, AppError>;
+}
+
+#[async_trait]
+pub trait UserStore: Send + Sync {
+    async fn create_user(&self, new_user:User) -> Result<InsertResult, AppError>;
+    async fn get_user(&self, id:&str) -> Result<User, AppError>;
+    async fn check_user_exists(&self, user_name:&str) -> Result<User, AppError>;
+    async fn get_all_users(&self, query:&ListQuery) -> Result<Page<User>, AppError>;
+    async fn update_user(&self, id:&str, new_user:User) -> Result<UpdateResult, AppError>;
+    async fn patch_user(&self, id:&str, patch:UserPatch) -> Result<UpdateResult, AppError>;
+    async fn set_password_hash(&self, id:&str, password_hash:String) -> Result<UpdateResult, AppError>;
+    async fn set_role(&self, id:&str, role:Role) -> Result<UpdateResult, AppError>;
+    async fn delete_user(&self, id:&ObjectId) -> Result<DeleteResult, AppError>;
+    async fn get_user_by_name(&self, name:&str) -> Result<User, AppError>;
+}
+
+#[async_trait]
+pub trait ApiKeyStore: Send + Sync {
+    async fn create_api_key(&self, api_key:ApiKey) -> Result<InsertResult, AppError>;
+    async fn get_api_keys(&self) -> Result<Vec<ApiKey>, AppError>;
+    async fn get_api_key_by_hash(&self, key_hash:&str) -> Result<ApiKey, AppError>;
+    async fn revoke_api_key(&self, id:ObjectId) -> Result<UpdateResult, AppError>;
+    async fn set_api_key_last_used(&self, id:ObjectId, used_at:bson::DateTime) -> Result<UpdateResult, AppError>;
+}
+
+// how long a password reset token can be redeemed, in seconds
+pub const RESET_TOKEN_TTL:i64 = 15 * 60;
+// failed logins within LOCKOUT_WINDOW that lock the account
+pub const MAX_FAILED_LOGINS:i64 = 5;
+// window failures are counted in and how long a locked account stays locked, in seconds
+pub const LOCKOUT_WINDOW:i64 = 15 * 60;
+
+// Revoked token ids (`jti`), session ids (`sid`) and users. An entry is kept until every
+// token it covers has expired anyway, backends drop it after that.
+#[async_trait]
+pub trait TokenRevocationStore: Send + Sync {
+    // revokes `id` as of now and keeps it at least until `until`, a unix timestamp
+    async fn revoke_token(&self, id:&str, until:i64) -> Result<(), AppError>;
+    // when `id` was last revoked, if it still is
+    async fn revoked_at(&self, id:&str) -> Result<Option<i64>, AppError>;
+
+    async fn is_token_revoked(&self, id:&str) -> Result<bool, AppError> {
+        Ok(self.revoked_at(id).await?.is_some())
+    }
+    // Ends every session of the user: tokens issued up to now are refused, new logins work
+    async fn revoke_user(&self, user_id:&str, until:i64) -> Result<(), AppError> {
+        self.revoke_token(&format!("user:{}", user_id), until).await
+    }
+    async fn is_user_revoked(&self, user_id:&str, issued_at:i64) -> Result<bool, AppError> {
+        let revoked_at = self.revoked_at(&format!("user:{}", user_id)).await?;
+        Ok(revoked_at.is_some_and(|revoked_at| issued_at <= revoked_at))
+    }
+    // What new tokens of the user are dated. Revocations only know the second, so a token
+    // issued in the same second as the last one is dated to the next and stays valid.
+    async fn user_token_time(&self, user_id:&str) -> Result<i64, AppError> {
+        let now = Utc::now().timestamp();
+        let revoked_at = self.revoked_at(&format!("user:{}", user_id)).await?;
+        Ok(revoked_at.map_or(now, |revoked_at| now.max(revoked_at + 1)))
+    }
+}
+
+// Outstanding password reset tokens. Only the hash of a token is stored, like for API keys.
+#[async_trait]
+pub trait PasswordResetStore: Send + Sync {
+    async fn insert_reset(&self, token_hash:&str, user_id:&str, expires_at:i64) -> Result<(), AppError>;
+    // removes the token and returns its user and expiry
+    async fn take_reset(&self, token_hash:&str) -> Result<Option<(String, i64)>, AppError>;
+
+    async fn issue_reset(&self, user_id:&str) -> Result<String, AppError> {
+        let token = random_token();
+        self.insert_reset(&hash_token(&token), user_id, Utc::now().timestamp() + RESET_TOKEN_TTL).await?;
+        Ok(token)
+    }
+    // A token can only be used once, it is removed whether or not it is still valid
+    async fn redeem_reset(&self, token:&str) -> Result<Option<String>, AppError> {
+        let now = Utc::now().timestamp();
+        let reset = self.take_reset(&hash_token(token)).await?;
+        Ok(reset.filter(|(_, expires_at)| *expires_at > now).map(|(user_id, _)| user_id))
+    }
+}
+
+// Failed logins per user name. Unknown names are counted as well, so a lockout says
+// nothing about whether the account exists.
+#[async_trait]
+pub trait LoginAttemptStore: Send + Sync {
+    // seconds the name is still locked for
+    async fn login_locked_for(&self, name:&str) -> Result<Option<u64>, AppError>;
+    // MAX_FAILED_LOGINS failures within LOCKOUT_WINDOW lock the name for LOCKOUT_WINDOW, the
+    // next failure after the lock starts a new count
+    async fn record_login_failure(&self, name:&str) -> Result<(), AppError>;
+    async fn clear_login_failures(&self, name:&str) -> Result<(), AppError>;
+}
+
+// How many references there are to each stored file. Uploads are keyed by their content, so
+// the same file is stored once however often it is uploaded.
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    // adds a reference to `key` and returns how many there are now
+    async fn acquire_blob(&self, key:&str) -> Result<u64, AppError>;
+    // drops a reference and returns how many are left, 0 when the file can be deleted
+    async fn release_blob(&self, key:&str) -> Result<u64, AppError>;
+}
+
+// What is known about a stored file without reading it
+#[derive(Debug)]
+pub struct FileInfo {
+    pub size:u64,
+    pub content_type:String,
+    pub modified:Option<SystemTime>,
+}
+
+// The bytes of a stored file, read while the response is sent
+pub type FileStream = BoxStream<'static, Result<Bytes, AppError>>;
+
+// Uploaded files, addressed by keys from `file_storage::content_key` so client file names
+// never reach a path or an object name
+#[async_trait]
+pub trait FileStorage: Send + Sync {
+    async fn put(&self, key:&str, data:Vec<u8>, content_type:&str) -> Result<(), AppError>;
+    // Stores the file `staged` below `UploadConfig::staging_dir`. It may be moved, the
+    // caller removes whatever is left of it.
+    async fn put_file(&self, key:&str, staged:&Path, content_type:&str) -> Result<(), AppError> {
+        let data = tokio::fs::read(staged).await.map_err(|e| AppError::StorageError(e.to_string()))?;
+        self.put(key, data, content_type).await
+    }
+    async fn stat(&self, key:&str) -> Result<FileInfo, AppError>;
+    // the bytes of `range`, which has to lie within the size reported by `stat`. A missing
+    // file is reported here, errors while reading end the stream.
+    async fn read(&self, key:&str, range:Range<u64>) -> Result<FileStream, AppError>;
+    async fn delete(&self, key:&str) -> Result<(), AppError>;
+    // whether new files can be stored right now, for the readiness check
+    async fn check(&self) -> Result<(), AppError>;
+}
@@ -0,0 +1,50 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use mongodb::{
+    bson::{self, doc, Document},
+    options::{IndexOptions, UpdateOptions},
+    Collection, Database, IndexModel
+};
+
+use crate::api::app_errors::AppError;
+use super::store::TokenRevocationStore;
+
+// `{_id: id, revoked_at: unix time, expires_at: date}`, the TTL index drops an entry once
+// `expires_at` has passed
+pub struct TokenRevocationRepo {
+    col:Collection<Document>,
+}
+
+impl TokenRevocationRepo {
+    pub async fn init(db:Database) -> Result<Self, AppError> {
+        let col:Collection<Document> = db.collection("TokenRevocation");
+        let expiry = IndexModel::builder()
+            .keys(doc! {"expires_at": 1})
+            .options(IndexOptions::builder().expire_after(Duration::ZERO).build())
+            .build();
+        col.create_index(expiry, None).await?;
+        Ok(TokenRevocationRepo {col})
+    }
+}
+
+#[async_trait]
+impl TokenRevocationStore for TokenRevocationRepo {
+    async fn revoke_token(&self, id:&str, until:i64) -> Result<(), AppError> {
+        let update = doc! {
+            "$set":{"revoked_at":Utc::now().timestamp()},
+            "$max":{"expires_at":bson::DateTime::from_millis(until.saturating_mul(1000))},
+        };
+        let options = UpdateOptions::builder().upsert(true).build();
+        self.col.update_one(doc! {"_id":id}, update, options).await?;
+        Ok(())
+    }
+
+    async fn revoked_at(&self, id:&str) -> Result<Option<i64>, AppError> {
+        // the TTL monitor only runs once a minute, so expired entries may still be there
+        let filter = doc! {"_id":id, "expires_at":{"$gt":bson::DateTime::now()}};
+        let revocation = self.col.find_one(filter, None).await?;
+        Ok(revocation.and_then(|revocation| revocation.get_i64("revoked_at").ok()))
+    }
+}
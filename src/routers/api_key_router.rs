@@ -0,0 +1,11 @@
+use actix_web::web;
+
+use crate::api::api_key_api::*;
+
+pub fn api_key_router() -> actix_web::Scope {
+
+    web::scope("apikey")
+        .service(create_api_key)
+        .service(get_api_keys)
+        .service(revoke_api_key)
+}
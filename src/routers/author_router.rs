@@ -1,5 +1,5 @@
 use actix_web::web;
-use crate::api::author_api::*;
+use crate::api::{author_api::*, file_api::get_file};
 
 pub fn author_router() -> actix_web::Scope {
     web::scope("author")
@@ -7,9 +7,12 @@ pub fn author_router() -> actix_web::Scope {
         .route("/author/{id}", web::get().to(get_author))
         .route("/authors", web::get().to(get_authors))
         .route("/author/{id}", web::put().to(update_author))
-        .route("/author/{id}", web::put().to(delete_author))
+        .route("/author/{id}", web::patch().to(patch_author))
+        .route("/author/{id}", web::delete().to(delete_author))
         .route("/author/profile", web::post().to(upload_author_profile))
         .route("/auther_profile/{id}", web::get().to(get_author_profile))
-        .route("/get_file/{directory}/{file_name}", web::get().to(get_files))
+        .route("/auther_profile/{id}", web::delete().to(delete_author_profile))
+        .route("/get_file/{key}", web::get().to(get_file))
+        .route("/get_file/{key}", web::head().to(get_file))
 
 }
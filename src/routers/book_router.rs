@@ -8,6 +8,7 @@ pub fn book_router() -> actix_web::Scope{
         .service(create_book)            
         .service(get_books)
         .service(update_book)
+        .service(patch_book)
         .service(delete_book)
         .service(get_author_books)
         .service(delete_books_by_author)
@@ -0,0 +1,10 @@
+use actix_web::web;
+
+use crate::api::health_api::*;
+
+// Registered at the root rather than as a scope, an empty scope would shadow every route
+// added after it
+pub fn health_router(cfg:&mut web::ServiceConfig) {
+    cfg.route("/healthz", web::get().to(healthz))
+        .route("/readyz", web::get().to(readyz));
+}
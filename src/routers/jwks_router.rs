@@ -0,0 +1,7 @@
+use actix_web::web;
+use crate::api::jwks_api::*;
+
+pub fn jwks_router() -> actix_web::Scope {
+    web::scope(".well-known")
+        .route("/jwks.json", web::get().to(jwks))
+}
@@ -1,3 +1,7 @@
 pub mod user_router;
 pub mod book_router;
-pub mod author_router;
\ No newline at end of file
+pub mod author_router;
+pub mod search_router;
+pub mod jwks_router;
+pub mod api_key_router;
+pub mod health_router;
\ No newline at end of file
@@ -0,0 +1,7 @@
+use actix_web::web;
+use crate::api::search_api::*;
+
+pub fn search_router() -> actix_web::Scope {
+    web::scope("search")
+        .route("", web::get().to(search))
+}
@@ -9,6 +9,13 @@ pub  fn user_router() -> actix_web::Scope {
         .service(get_user)
         .service(get_users)
         .service(update_user)
+        .service(patch_user)
         .service(delete_user)
+        .service(set_user_role)
         .service(login_user)
+        .service(refresh_token)
+        .service(logout_user)
+        .service(change_password)
+        .service(forgot_password)
+        .service(reset_password)
 }
\ No newline at end of file
@@ -0,0 +1,154 @@
+// Removes what an uploader may not mean to publish (Exif with its GPS position, XMP,
+// comments, text chunks) from a picture without touching the bytes of its pixels. Only the
+// containers are walked here, decoding is left to the `image` crate.
+
+use crate::api::app_errors::AppError;
+
+// JPEG markers
+const SOS:u8 = 0xDA;
+const EOI:u8 = 0xD9;
+const APP0:u8 = 0xE0;
+const APP2:u8 = 0xE2;
+const APP14:u8 = 0xEE;
+const COM:u8 = 0xFE;
+
+// the body of an APP2 segment holding a colour profile starts with this
+const ICC_PROFILE:&[u8] = b"ICC_PROFILE\0";
+
+// PNG chunks left out, everything else is needed to show the picture the same way
+const PNG_SIGNATURE:&[u8] = b"\x89PNG\r\n\x1a\n";
+const PNG_DROPPED:[&[u8; 4]; 5] = [b"tEXt", b"zTXt", b"iTXt", b"eXIf", b"tIME"];
+
+// VP8X flags announcing the metadata chunks
+const EXIF_FLAG:u8 = 0x08;
+const XMP_FLAG:u8 = 0x04;
+
+fn invalid(format:&str, msg:&str) -> AppError {
+    AppError::UnprocessableError(format!("the {} image could not be read: {}", format, msg))
+}
+
+// The file of `content_type` without its metadata
+pub fn strip_metadata(data:&[u8], content_type:&str) -> Result<Vec<u8>, AppError> {
+    match content_type {
+        "image/jpeg" => strip_jpeg(data),
+        "image/png" => strip_png(data),
+        "image/webp" => strip_webp(data),
+        _ => Err(AppError::UnsupportedMediaTypeError(format!("{} images can't be processed", content_type))),
+    }
+}
+
+// A JPEG is a list of segments, each a marker and a big endian length counting itself. The
+// entropy coded data after a start of scan runs up to the next marker that is neither a
+// stuffed 0xFF00 nor a restart. JFIF, Adobe and colour profile segments are kept, the other
+// application segments and comments are dropped.
+fn strip_jpeg(data:&[u8]) -> Result<Vec<u8>, AppError> {
+    let invalid = |msg:&str| invalid("JPEG", msg);
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return Err(invalid("missing start of image"));
+    }
+
+    let mut out = data[..2].to_vec();
+    let mut at = 2;
+    loop {
+        // markers may be preceded by any number of 0xFF fill bytes
+        while data.get(at) == Some(&0xFF) && data.get(at + 1) == Some(&0xFF) {
+            at += 1;
+        }
+        let marker = match data.get(at..at + 2) {
+            Some([0xFF, marker]) => *marker,
+            Some(_) => return Err(invalid("expected a marker")),
+            None => return Err(invalid("missing end of image")),
+        };
+        if marker == EOI {
+            out.extend_from_slice(&data[at..at + 2]);
+            return Ok(out);
+        }
+
+        let length = data.get(at + 2..at + 4).ok_or_else(|| invalid("truncated segment"))?;
+        let end = at + 2 + u16::from_be_bytes([length[0], length[1]]) as usize;
+        let segment = data.get(at..end).filter(|segment| segment.len() >= 4).ok_or_else(|| invalid("truncated segment"))?;
+        let keep = match marker {
+            APP0 | APP14 => true,
+            APP2 => segment[4..].starts_with(ICC_PROFILE),
+            APP0..=0xEF | COM => false,
+            _ => true,
+        };
+        if keep {
+            out.extend_from_slice(segment);
+        }
+        at = end;
+
+        if marker == SOS {
+            let scan = data[at..]
+                .windows(2)
+                .position(|pair| pair[0] == 0xFF && !matches!(pair[1], 0x00 | 0xD0..=0xD7 | 0xFF))
+                .ok_or_else(|| invalid("missing end of image"))?;
+            out.extend_from_slice(&data[at..at + scan]);
+            at += scan;
+        }
+    }
+}
+
+// A PNG is its signature, then chunks of a big endian length, a type, the body and a CRC.
+// Nothing after IEND is kept.
+fn strip_png(data:&[u8]) -> Result<Vec<u8>, AppError> {
+    let invalid = |msg:&str| invalid("PNG", msg);
+    if !data.starts_with(PNG_SIGNATURE) {
+        return Err(invalid("missing signature"));
+    }
+
+    let mut out = PNG_SIGNATURE.to_vec();
+    let mut at = PNG_SIGNATURE.len();
+    loop {
+        let header = data.get(at..at + 8).ok_or_else(|| invalid("missing IEND"))?;
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let end = at.checked_add(12 + length).ok_or_else(|| invalid("truncated chunk"))?;
+        let chunk = data.get(at..end).ok_or_else(|| invalid("truncated chunk"))?;
+        let kind = &header[4..8];
+        if !PNG_DROPPED.iter().any(|dropped| dropped.as_slice() == kind) {
+            out.extend_from_slice(chunk);
+        }
+        if kind == b"IEND" {
+            return Ok(out);
+        }
+        at = end;
+    }
+}
+
+// A RIFF container is "RIFF", the size of what follows, "WEBP", then chunks of a fourcc, a
+// little endian size and a body padded to an even length. The EXIF and XMP chunks are
+// dropped, along with their flags in VP8X.
+fn strip_webp(data:&[u8]) -> Result<Vec<u8>, AppError> {
+    let invalid = |msg:&str| invalid("WebP", msg);
+    if data.len() < 12 || &data[..4] != b"RIFF" || &data[8..12] != b"WEBP" {
+        return Err(invalid("missing RIFF header"));
+    }
+
+    let mut out = data[..12].to_vec();
+    let mut at = 12;
+    while at < data.len() {
+        let header = data.get(at..at + 8).ok_or_else(|| invalid("truncated chunk"))?;
+        let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let end = at + 8 + size + size % 2;
+        let chunk = data.get(at..end.min(data.len())).ok_or_else(|| invalid("truncated chunk"))?;
+        if chunk.len() < 8 + size {
+            return Err(invalid("truncated chunk"));
+        }
+        match &header[..4] {
+            b"EXIF" | b"XMP " => {},
+            b"VP8X" => {
+                let flags_at = out.len() + 8;
+                out.extend_from_slice(chunk);
+                if let Some(flags) = out.get_mut(flags_at) {
+                    *flags &= !(EXIF_FLAG | XMP_FLAG);
+                }
+            },
+            _ => out.extend_from_slice(chunk),
+        }
+        at = end;
+    }
+
+    let riff_size = (out.len() - 8) as u32;
+    out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    Ok(out)
+}
@@ -0,0 +1,336 @@
+// Decoding, cleaning up and resizing of uploaded pictures. The `image` crate does the
+// decoding and encoding, `metadata` takes what is not part of the picture out of the files.
+
+mod metadata;
+
+use std::io::Cursor;
+
+use image::{
+    codecs::{jpeg::JpegEncoder, png::PngEncoder, webp::WebPEncoder}, metadata::Orientation, DynamicImage, ImageDecoder, ImageError, ImageFormat, ImageReader
+};
+
+use crate::{api::app_errors::AppError, model::file_model::ImageSize};
+use metadata::strip_metadata;
+
+// decoders refuse anything larger before allocating, a few bytes of header can claim gigapixels
+const MAX_PIXELS:u64 = 25_000_000;
+
+const ORIGINAL_QUALITY:u8 = 90;
+const RESIZED_QUALITY:u8 = 85;
+
+pub struct ProcessedImage {
+    pub content_type:&'static str,
+    pub original:Vec<u8>,
+    // the smaller sizes, an image already fitting one has no such variant
+    pub variants:Vec<(ImageSize, Vec<u8>)>,
+}
+
+fn format_of(content_type:&str) -> Result<ImageFormat, AppError> {
+    match content_type {
+        "image/jpeg" => Ok(ImageFormat::Jpeg),
+        "image/png" => Ok(ImageFormat::Png),
+        "image/webp" => Ok(ImageFormat::WebP),
+        _ => Err(AppError::UnsupportedMediaTypeError(format!("{} images can't be processed", content_type))),
+    }
+}
+
+fn unreadable(e:ImageError) -> AppError {
+    AppError::UnprocessableError(format!("the image could not be decoded: {}", e))
+}
+
+// The pixels and the Exif orientation they are meant to be shown with
+fn decode(data:&[u8], format:ImageFormat) -> Result<(DynamicImage, Orientation), AppError> {
+    let mut decoder = ImageReader::with_format(Cursor::new(data), format).into_decoder().map_err(unreadable)?;
+    let (width, height) = decoder.dimensions();
+    if width == 0 || height == 0 {
+        return Err(AppError::UnprocessableError("the image has no pixels".to_string()));
+    }
+    if u64::from(width) * u64::from(height) > MAX_PIXELS {
+        return Err(AppError::UnprocessableError(format!(
+            "the image is {}x{}, at most {} pixels are accepted",
+            width, height, MAX_PIXELS
+        )));
+    }
+    // a broken Exif block only costs the orientation
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let image = DynamicImage::from_decoder(decoder).map_err(unreadable)?;
+    Ok((image, orientation))
+}
+
+// JPEG has no alpha channel, the WebP encoder takes 8 bit samples only
+fn encode(image:&DynamicImage, format:ImageFormat, quality:u8) -> Result<Vec<u8>, AppError> {
+    let mut out = Vec::new();
+    let encoded = match format {
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8()).write_with_encoder(JpegEncoder::new_with_quality(&mut out, quality)),
+        ImageFormat::WebP if image.color().has_alpha() => {
+            DynamicImage::ImageRgba8(image.to_rgba8()).write_with_encoder(WebPEncoder::new_lossless(&mut out))
+        },
+        ImageFormat::WebP => DynamicImage::ImageRgb8(image.to_rgb8()).write_with_encoder(WebPEncoder::new_lossless(&mut out)),
+        _ => image.write_with_encoder(PngEncoder::new(&mut out)),
+    };
+    encoded.map_err(|e| AppError::InternalError(format!("the image could not be encoded: {}", e)))?;
+    Ok(out)
+}
+
+// Cleans up a profile picture of `content_type` and renders its smaller sizes in the same
+// format. An upright original is kept byte for byte, only its metadata is dropped. One with
+// an Exif orientation is turned upright and encoded again instead, since dropping the Exif
+// would show it turned otherwise: losslessly for PNG and WebP, at ORIGINAL_QUALITY for JPEG.
+// Files that end early are refused even where a decoder would fill in the missing pixels.
+pub fn process_profile_image(data:&[u8], content_type:&'static str) -> Result<ProcessedImage, AppError> {
+    let format = format_of(content_type)?;
+    let stripped = strip_metadata(data, content_type)?;
+    let (mut image, orientation) = decode(data, format)?;
+    let original = match orientation {
+        Orientation::NoTransforms => stripped,
+        orientation => {
+            image.apply_orientation(orientation);
+            encode(&image, format, ORIGINAL_QUALITY)?
+        },
+    };
+
+    let mut variants = Vec::new();
+    for size in ImageSize::RESIZED {
+        let max_side = size.max_side() as u32;
+        if image.width() > max_side || image.height() > max_side {
+            variants.push((size, encode(&image.thumbnail(max_side, max_side), format, RESIZED_QUALITY)?));
+        }
+    }
+    Ok(ProcessedImage { content_type, original, variants })
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgb, RgbImage, Rgba, RgbaImage};
+
+    use super::*;
+
+    // VP8X flags of a WebP with both kinds of metadata
+    const EXIF_AND_XMP:u8 = 0x08 | 0x04;
+
+    // a little endian TIFF structure holding nothing but the orientation
+    fn exif(orientation:u16) -> Vec<u8> {
+        let mut tiff = b"II*\0\x08\0\0\0\x01\0\x12\x01\x03\0\x01\0\0\0".to_vec();
+        tiff.extend_from_slice(&orientation.to_le_bytes());
+        tiff.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        tiff
+    }
+
+    fn gradient(width:u32, height:u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| Rgb([(x * 7) as u8, (y * 5) as u8, 128])))
+    }
+
+    fn jpeg(image:&DynamicImage) -> Vec<u8> {
+        encode(image, ImageFormat::Jpeg, 90).unwrap()
+    }
+
+    // `segments` inserted right after the start of image
+    fn jpeg_with(plain:&[u8], segments:&[(u8, &[u8])]) -> Vec<u8> {
+        let mut data = plain[..2].to_vec();
+        for (marker, body) in segments {
+            data.extend_from_slice(&[0xFF, *marker]);
+            data.extend_from_slice(&(body.len() as u16 + 2).to_be_bytes());
+            data.extend_from_slice(body);
+        }
+        data.extend_from_slice(&plain[2..]);
+        data
+    }
+
+    fn png_chunk(kind:&[u8; 4], body:&[u8]) -> Vec<u8> {
+        let mut chunk = (body.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(kind);
+        chunk.extend_from_slice(body);
+        let crc = crc32(&chunk[4..]);
+        chunk.extend_from_slice(&crc.to_be_bytes());
+        chunk
+    }
+
+    fn crc32(data:&[u8]) -> u32 {
+        !data.iter().fold(!0u32, |crc, byte| {
+            (0..8).fold(crc ^ u32::from(*byte), |crc, _| if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 })
+        })
+    }
+
+    // `chunks` inserted right after IHDR
+    fn png_with(plain:&[u8], chunks:&[Vec<u8>]) -> Vec<u8> {
+        let ihdr_end = 8 + 12 + 13;
+        let mut data = plain[..ihdr_end].to_vec();
+        chunks.iter().for_each(|chunk| data.extend_from_slice(chunk));
+        data.extend_from_slice(&plain[ihdr_end..]);
+        data
+    }
+
+    fn webp_chunk(kind:&[u8; 4], body:&[u8]) -> Vec<u8> {
+        let mut chunk = kind.to_vec();
+        chunk.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(body);
+        if body.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn riff(chunks:&[Vec<u8>]) -> Vec<u8> {
+        let body:Vec<u8> = chunks.concat();
+        let mut data = b"RIFF".to_vec();
+        data.extend_from_slice(&(body.len() as u32 + 4).to_le_bytes());
+        data.extend_from_slice(b"WEBP");
+        data.extend_from_slice(&body);
+        data
+    }
+
+    // an extended WebP around the image of a simple one, with `flags` and `extra` chunks
+    fn webp_with(simple:&[u8], width:u32, height:u32, flags:u8, extra:&[Vec<u8>]) -> Vec<u8> {
+        let mut vp8x = vec![flags, 0, 0, 0];
+        vp8x.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+        vp8x.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+        let mut chunks = vec![webp_chunk(b"VP8X", &vp8x), simple[12..].to_vec()];
+        chunks.extend_from_slice(extra);
+        riff(&chunks)
+    }
+
+    fn dimensions(data:&[u8], content_type:&str) -> (u32, u32) {
+        let (image, _) = decode(data, format_of(content_type).unwrap()).unwrap();
+        (image.width(), image.height())
+    }
+
+    fn orientation_of(data:&[u8], content_type:&str) -> Orientation {
+        decode(data, format_of(content_type).unwrap()).unwrap().1
+    }
+
+    #[test]
+    fn upright_jpegs_keep_their_bytes_without_metadata() {
+        let plain = jpeg(&gradient(40, 30));
+        let mut app1 = b"Exif\0\0".to_vec();
+        app1.extend_from_slice(&exif(1));
+        let icc = b"ICC_PROFILE\0\x01\x01profile";
+        let data = jpeg_with(&plain, &[(0xE1, &app1), (0xE2, icc), (0xED, b"Photoshop 3.0\0"), (0xFE, b"taken at home")]);
+
+        let processed = process_profile_image(&data, "image/jpeg").unwrap();
+        assert_eq!(processed.original, jpeg_with(&plain, &[(0xE2, icc)]));
+        assert!(processed.variants.is_empty());
+    }
+
+    #[test]
+    fn turned_jpegs_are_encoded_upright() {
+        let plain = jpeg(&gradient(40, 20));
+        let mut app1 = b"Exif\0\0".to_vec();
+        app1.extend_from_slice(&exif(6));
+        let data = jpeg_with(&plain, &[(0xE1, &app1)]);
+        assert_eq!(orientation_of(&data, "image/jpeg"), Orientation::Rotate90);
+
+        let processed = process_profile_image(&data, "image/jpeg").unwrap();
+        assert_eq!(dimensions(&processed.original, "image/jpeg"), (20, 40));
+        assert_eq!(orientation_of(&processed.original, "image/jpeg"), Orientation::NoTransforms);
+    }
+
+    #[test]
+    fn pngs_lose_text_and_exif_chunks() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(600, 300, |x, y| Rgba([x as u8, y as u8, 0, 200])));
+        let plain = encode(&image, ImageFormat::Png, 0).unwrap();
+        let data = png_with(&plain, &[png_chunk(b"tEXt", b"Author\0someone"), png_chunk(b"eXIf", &exif(1)), png_chunk(b"gAMA", &45455u32.to_be_bytes())]);
+
+        let processed = process_profile_image(&data, "image/png").unwrap();
+        assert_eq!(processed.original, png_with(&plain, &[png_chunk(b"gAMA", &45455u32.to_be_bytes())]));
+        let sizes:Vec<(ImageSize, (u32, u32))> =
+            processed.variants.iter().map(|(size, data)| (*size, dimensions(data, "image/png"))).collect();
+        assert_eq!(sizes, [(ImageSize::Thumbnail, (128, 64)), (ImageSize::Medium, (512, 256))]);
+    }
+
+    #[test]
+    fn turned_pngs_are_encoded_upright_without_loss() {
+        let image = gradient(30, 10);
+        let plain = encode(&image, ImageFormat::Png, 0).unwrap();
+        let data = png_with(&plain, &[png_chunk(b"eXIf", &exif(3))]);
+
+        let processed = process_profile_image(&data, "image/png").unwrap();
+        let (upright, orientation) = decode(&processed.original, ImageFormat::Png).unwrap();
+        assert_eq!(orientation, Orientation::NoTransforms);
+        let mut expected = image.clone();
+        expected.apply_orientation(Orientation::Rotate180);
+        assert_eq!(upright.to_rgb8(), expected.to_rgb8());
+    }
+
+    #[test]
+    fn webps_lose_metadata_and_get_variants() {
+        let image = gradient(300, 150);
+        let simple = encode(&image, ImageFormat::WebP, 0).unwrap();
+        let data = webp_with(&simple, 300, 150, EXIF_AND_XMP, &[webp_chunk(b"EXIF", &exif(1)), webp_chunk(b"XMP ", b"<x:xmpmeta/>")]);
+
+        let processed = process_profile_image(&data, "image/webp").unwrap();
+        assert_eq!(processed.original, webp_with(&simple, 300, 150, 0, &[]));
+        assert_eq!(decode(&processed.original, ImageFormat::WebP).unwrap().0.to_rgb8(), image.to_rgb8());
+        let sizes:Vec<(ImageSize, (u32, u32))> =
+            processed.variants.iter().map(|(size, data)| (*size, dimensions(data, "image/webp"))).collect();
+        assert_eq!(sizes, [(ImageSize::Thumbnail, (128, 64))]);
+    }
+
+    fn samples() -> Vec<(Vec<u8>, &'static str)> {
+        let image = gradient(64, 48);
+        let mut app1 = b"Exif\0\0".to_vec();
+        app1.extend_from_slice(&exif(8));
+        vec![
+            (jpeg_with(&jpeg(&image), &[(0xE1, &app1), (0xFE, b"comment")]), "image/jpeg"),
+            (png_with(&encode(&image, ImageFormat::Png, 0).unwrap(), &[png_chunk(b"tEXt", b"k\0v")]), "image/png"),
+            (encode(&image, ImageFormat::WebP, 0).unwrap(), "image/webp"),
+        ]
+    }
+
+    #[test]
+    fn truncated_images_are_refused() {
+        for (data, content_type) in samples() {
+            for len in [0, 1, 2, 8, 12, 20, 40, data.len() / 2, data.len() - 3, data.len() - 2] {
+                let result = process_profile_image(&data[..len], content_type);
+                assert!(matches!(result, Err(AppError::UnprocessableError(_))), "{} cut at {} gave {:?}", content_type, len, result.err());
+            }
+        }
+    }
+
+    #[test]
+    fn garbage_is_refused() {
+        for content_type in ["image/jpeg", "image/png", "image/webp"] {
+            for data in [&b""[..], b"\xFF\xD8\xFF", b"\x89PNG\r\n\x1a\n", b"RIFF\0\0\0\0WEBP", b"not an image at all"] {
+                assert!(process_profile_image(data, content_type).is_err(), "{} accepted {:?}", content_type, data);
+            }
+        }
+        assert!(matches!(process_profile_image(b"GIF89a", "image/gif"), Err(AppError::UnsupportedMediaTypeError(_))));
+    }
+
+    #[test]
+    fn huge_dimensions_are_refused_before_decoding() {
+        let plain = encode(&gradient(1, 1), ImageFormat::Png, 0).unwrap();
+        let mut ihdr = 100_000u32.to_be_bytes().to_vec();
+        ihdr.extend_from_slice(&100_000u32.to_be_bytes());
+        ihdr.extend_from_slice(&plain[8 + 8 + 8..8 + 8 + 13]);
+        let mut data = plain[..8].to_vec();
+        data.extend_from_slice(&png_chunk(b"IHDR", &ihdr));
+        data.extend_from_slice(&plain[8 + 12 + 13..]);
+
+        let result = process_profile_image(&data, "image/png");
+        assert!(matches!(result, Err(AppError::UnprocessableError(msg)) if msg.contains("pixels are accepted")));
+    }
+
+    // Corrupted bytes may be refused or decoded into noise, but never crash the upload
+    #[test]
+    fn corrupted_images_do_not_panic() {
+        let mut seed = 0x2545_F491_4F6C_DD1Du64;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        for (data, content_type) in samples() {
+            for _ in 0..300 {
+                let mut corrupted = data.clone();
+                for _ in 0..1 + next() % 4 {
+                    let at = (next() % corrupted.len() as u64) as usize;
+                    corrupted[at] = next() as u8;
+                }
+                if let Ok(processed) = process_profile_image(&corrupted, content_type) {
+                    assert!(!processed.original.is_empty());
+                }
+            }
+        }
+    }
+}
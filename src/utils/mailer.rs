@@ -0,0 +1,34 @@
+use async_trait::async_trait;
+
+use crate::{api::app_errors::AppError, model::user_model::User};
+
+// Delivers messages meant only for the user, such as password reset tokens
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send_password_reset(&self, user:&User, token:&str) -> Result<(), AppError>;
+}
+
+// Stand-in until a mail service is wired up: nothing is delivered. With
+// `auth.log_reset_tokens` (LOG_RESET_TOKENS) set, tokens go to the debug log instead, which
+// is only meant for local development.
+pub struct DevMailer {
+    log_tokens:bool,
+}
+
+impl DevMailer {
+    pub fn init(log_tokens:bool) -> Self {
+        DevMailer { log_tokens }
+    }
+}
+
+#[async_trait]
+impl Mailer for DevMailer {
+    async fn send_password_reset(&self, user:&User, token:&str) -> Result<(), AppError> {
+        if self.log_tokens {
+            log::debug!("password reset token for user {}: {}", user.name, token);
+        } else {
+            log::warn!("no mailer is configured, the password reset token for user {} was not delivered", user.name);
+        }
+        Ok(())
+    }
+}
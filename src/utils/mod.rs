@@ -1 +1,6 @@
-pub mod response;
\ No newline at end of file
+pub mod response;
+pub mod pagination;
+pub mod validation;
+pub mod password;
+pub mod image;
+pub mod mailer;
\ No newline at end of file
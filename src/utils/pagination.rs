@@ -0,0 +1,340 @@
+use std::cmp::Ordering;
+
+use mongodb::bson::oid::ObjectId;
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+pub const DEFAULT_PER_PAGE:u64 = 20;
+pub const MAX_PER_PAGE:u64 = 100;
+
+// Type of a field that can be used for filtering and sorting on a list endpoint
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldKind {
+    Text,
+    Int
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterOp {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterValue {
+    Text(String),
+    Int(i64)
+}
+
+#[derive(Debug, Clone)]
+pub struct Filter {
+    pub field:String,
+    pub op:FilterOp,
+    pub value:FilterValue
+}
+
+#[derive(Debug, Clone)]
+pub struct SortField {
+    pub field:String,
+    pub descending:bool
+}
+
+// Parsed `page`, `per_page`, `after`, `sort` and filter parameters of a list request
+#[derive(Debug, Clone)]
+pub struct ListQuery {
+    pub page:u64,
+    pub per_page:u64,
+    pub after:Option<ObjectId>,
+    pub sort:Vec<SortField>,
+    pub filters:Vec<Filter>,
+    // sort and filter params as sent, replayed in the next page link
+    params:Vec<(String, String)>
+}
+
+// One page of a list together with the number of documents matching the filters
+pub struct Page<T> {
+    pub items:Vec<T>,
+    pub total:u64
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Pagination {
+    pub total:u64,
+    pub per_page:u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page:Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next:Option<String>
+}
+
+impl Default for ListQuery {
+    fn default() -> Self {
+        ListQuery { page: 1, per_page: DEFAULT_PER_PAGE, after: None, sort: Vec::new(), filters: Vec::new(), params: Vec::new() }
+    }
+}
+
+impl ListQuery {
+    // Cursor query in id order, for walking a whole collection batch by batch
+    pub fn batch(after:Option<ObjectId>, per_page:u64) -> Self {
+        ListQuery { after, per_page, ..ListQuery::default() }
+    }
+
+    // `fields` lists what may be filtered and sorted on, `id` is always sortable
+    pub fn parse(query_string:&str, fields:&[(&str, FieldKind)]) -> Result<Self, String> {
+        let pairs:Vec<(String, String)> = serde_urlencoded::from_str(query_string)
+            .map_err(|_| "invalid query string".to_string())?;
+
+        let mut query = ListQuery::default();
+        for (key, value) in pairs {
+            match key.as_str() {
+                "page" => {
+                    query.page = value.parse().ok().filter(|p| *p > 0).ok_or("page must be a positive number")?;
+                },
+                "per_page" => {
+                    query.per_page = value.parse().ok().filter(|p| *p > 0 && *p <= MAX_PER_PAGE)
+                        .ok_or(format!("per_page must be between 1 and {}", MAX_PER_PAGE))?;
+                },
+                "after" => {
+                    query.after = Some(ObjectId::parse_str(&value).map_err(|_| "after must be a valid id".to_string())?);
+                },
+                "sort" => {
+                    for name in value.split(',').filter(|s| !s.is_empty()) {
+                        let (field, descending) = match name.strip_prefix('-') {
+                            Some(field) => (field, true),
+                            None => (name, false),
+                        };
+                        if field != "id" && !fields.iter().any(|(f, _)| *f == field) {
+                            return Err(format!("cannot sort on field {}", field));
+                        }
+                        query.sort.push(SortField { field: field.to_string(), descending });
+                    }
+                    query.params.push((key, value));
+                },
+                _ => {
+                    query.filters.push(parse_filter(&key, &value, fields)?);
+                    query.params.push((key, value));
+                }
+            }
+        }
+
+        if query.after.is_some() && query.sort.iter().any(|s| s.field != "id" || s.descending) {
+            return Err("after can only be used with the default sort".to_string());
+        }
+        // the end of the page has to fit the signed offsets of the databases
+        if query.page.checked_mul(query.per_page).filter(|end| *end <= i64::MAX as u64).is_none() {
+            return Err("page is out of range".to_string());
+        }
+        Ok(query)
+    }
+
+    pub fn skip(&self) -> u64 {
+        if self.after.is_some() {
+            return 0;
+        }
+        self.page.saturating_sub(1).saturating_mul(self.per_page)
+    }
+
+    // Builds the envelope metadata, `last_id` is the id of the last item of the page
+    pub fn pagination<T>(&self, path:&str, page:&Page<T>, last_id:Option<ObjectId>) -> Pagination {
+        let mut params = self.params.clone();
+        params.push(("per_page".to_string(), self.per_page.to_string()));
+
+        let next = if self.after.is_some() {
+            match last_id {
+                Some(id) if page.items.len() as u64 == self.per_page => {
+                    params.push(("after".to_string(), id.to_hex()));
+                    Some(params)
+                },
+                _ => None,
+            }
+        } else if self.page.saturating_mul(self.per_page) < page.total {
+            params.push(("page".to_string(), (self.page + 1).to_string()));
+            Some(params)
+        } else {
+            None
+        };
+
+        Pagination {
+            total: page.total,
+            per_page: self.per_page,
+            page: if self.after.is_some() { None } else { Some(self.page) },
+            next: next.and_then(|p| serde_urlencoded::to_string(p).ok()).map(|q| format!("{}?{}", path, q)),
+        }
+    }
+
+    // In-process evaluation for backends without a query engine, items are matched
+    // on their serialized form so the field names are the same as in the API
+    pub fn apply<T: Serialize>(&self, items:Vec<T>) -> Page<T> {
+        let mut rows:Vec<(Value, T)> = items
+            .into_iter()
+            .map(|item| (serde_json::to_value(&item).unwrap_or(Value::Null), item))
+            .filter(|(value, _)| self.filters.iter().all(|f| f.matches(value)))
+            .collect();
+        let total = rows.len() as u64;
+
+        rows.sort_by(|(a, _), (b, _)| {
+            for sort in &self.sort {
+                let order = compare_values(field_value(a, &sort.field), field_value(b, &sort.field));
+                let order = if sort.descending { order.reverse() } else { order };
+                if order != Ordering::Equal {
+                    return order;
+                }
+            }
+            compare_values(field_value(a, "id"), field_value(b, "id"))
+        });
+
+        let items = rows
+            .into_iter()
+            .filter(|(value, _)| match self.after {
+                Some(after) => field_value(value, "id").and_then(Value::as_str).is_some_and(|id| id > after.to_hex().as_str()),
+                None => true,
+            })
+            .skip(self.skip() as usize)
+            .take(self.per_page as usize)
+            .map(|(_, item)| item)
+            .collect();
+
+        Page { items, total }
+    }
+}
+
+impl Filter {
+    fn matches(&self, item:&Value) -> bool {
+        let value = field_value(item, &self.field);
+        let expected = match &self.value {
+            FilterValue::Text(text) => Value::from(text.as_str()),
+            FilterValue::Int(number) => Value::from(*number),
+        };
+        let order = compare_values(value, Some(&expected));
+
+        match self.op {
+            FilterOp::Eq => order == Ordering::Equal,
+            FilterOp::Ne => order != Ordering::Equal,
+            FilterOp::Gt => order == Ordering::Greater,
+            FilterOp::Gte => order != Ordering::Less,
+            FilterOp::Lt => order == Ordering::Less,
+            FilterOp::Lte => order != Ordering::Greater,
+        }
+    }
+}
+
+// `total_page>=300` arrives as key `total_page>` and value `300`, `total_page>300` as a key without value
+fn parse_filter(key:&str, value:&str, fields:&[(&str, FieldKind)]) -> Result<Filter, String> {
+    let raw = if value.is_empty() { key.to_string() } else { format!("{}={}", key, value) };
+
+    let operators = [(">=", FilterOp::Gte), ("<=", FilterOp::Lte), ("!=", FilterOp::Ne), (">", FilterOp::Gt), ("<", FilterOp::Lt), ("=", FilterOp::Eq)];
+    // the first operator in the string wins, so values may themselves contain `=` or `>`
+    let (index, token, op) = operators
+        .iter()
+        .filter_map(|(token, op)| raw.find(token).map(|index| (index, *token, *op)))
+        .min_by_key(|(index, token, _)| (*index, usize::MAX - token.len()))
+        .ok_or(format!("invalid filter {}", raw))?;
+    let (field, value) = (&raw[..index], &raw[index + token.len()..]);
+
+    let kind = fields
+        .iter()
+        .find(|(f, _)| *f == field)
+        .map(|(_, kind)| *kind)
+        .ok_or(format!("cannot filter on field {}", field))?;
+
+    let value = match kind {
+        FieldKind::Text => FilterValue::Text(value.to_string()),
+        FieldKind::Int => FilterValue::Int(value.parse().map_err(|_| format!("{} must be a number", field))?),
+    };
+    Ok(Filter { field: field.to_string(), op, value })
+}
+
+fn field_value<'a>(item:&'a Value, field:&str) -> Option<&'a Value> {
+    if field == "id" {
+        return item.get("_id").map(|id| id.get("$oid").unwrap_or(id));
+    }
+    item.get(field)
+}
+
+fn compare_values(a:Option<&Value>, b:Option<&Value>) -> Ordering {
+    match (a, b) {
+        (Some(Value::Number(a)), Some(Value::Number(b))) => a.as_f64().partial_cmp(&b.as_f64()).unwrap_or(Ordering::Equal),
+        (Some(Value::String(a)), Some(Value::String(b))) => a.cmp(b),
+        (Some(_), None) => Ordering::Greater,
+        (None, Some(_)) => Ordering::Less,
+        _ => Ordering::Equal,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIELDS:&[(&str, FieldKind)] = &[("book_name", FieldKind::Text), ("total_page", FieldKind::Int)];
+
+    #[test]
+    fn rejects_pages_past_the_largest_offset() {
+        assert!(ListQuery::parse("page=18446744073709551615", FIELDS).is_err());
+        assert!(ListQuery::parse("per_page=100&page=92233720368547759", FIELDS).is_err());
+        assert!(ListQuery::parse("page=92233720368547758&per_page=100", FIELDS).is_ok());
+    }
+
+    #[test]
+    fn parses_filters_whatever_side_of_the_equals_sign_the_operator_is() {
+        let parsed = |key, value| parse_filter(key, value, FIELDS).map(|f| (f.field, f.op, f.value));
+
+        assert_eq!(parsed("total_page>", "300"), Ok(("total_page".to_string(), FilterOp::Gte, FilterValue::Int(300))));
+        assert_eq!(parsed("total_page>300", ""), Ok(("total_page".to_string(), FilterOp::Gt, FilterValue::Int(300))));
+        assert_eq!(parsed("total_page<", "10"), Ok(("total_page".to_string(), FilterOp::Lte, FilterValue::Int(10))));
+        assert_eq!(parsed("book_name!", "Dune"), Ok(("book_name".to_string(), FilterOp::Ne, FilterValue::Text("Dune".to_string()))));
+        // only the first operator counts, the rest belongs to the value
+        assert_eq!(parsed("book_name", "a>=b=c"), Ok(("book_name".to_string(), FilterOp::Eq, FilterValue::Text("a>=b=c".to_string()))));
+    }
+
+    #[test]
+    fn refuses_unknown_fields_and_malformed_filters() {
+        assert_eq!(parse_filter("book_name", "", FIELDS).unwrap_err(), "invalid filter book_name");
+        assert_eq!(parse_filter("created_by", "ann", FIELDS).unwrap_err(), "cannot filter on field created_by");
+        assert_eq!(parse_filter("total_page>", "many", FIELDS).unwrap_err(), "total_page must be a number");
+
+        assert!(ListQuery::parse("sort=created_by", FIELDS).is_err());
+        assert!(ListQuery::parse("per_page=0", FIELDS).is_err());
+        assert!(ListQuery::parse("per_page=101", FIELDS).is_err());
+        assert!(ListQuery::parse("after=nope", FIELDS).is_err());
+        assert!(ListQuery::parse(&format!("after={}&sort=-id", ObjectId::new()), FIELDS).is_err());
+    }
+
+    #[test]
+    fn filters_sorts_and_pages_items() {
+        let items:Vec<Value> = (1..=5)
+            .map(|n| serde_json::json!({ "_id": { "$oid": format!("{:024x}", n) }, "book_name": format!("book {}", n), "total_page": n * 100 }))
+            .collect();
+        let names = |page:Page<Value>| page.items.iter().map(|item| item["book_name"].as_str().unwrap().to_string()).collect::<Vec<_>>();
+
+        let query = ListQuery::parse("total_page>=200&sort=-total_page&per_page=2&page=2", FIELDS).unwrap();
+        let page = query.apply(items.clone());
+        assert_eq!(page.total, 4);
+        assert_eq!(names(page), ["book 3", "book 2"]);
+
+        let query = ListQuery::parse(&format!("after={:024x}&per_page=10", 3), FIELDS).unwrap();
+        assert_eq!(names(query.apply(items)), ["book 4", "book 5"]);
+    }
+
+    #[test]
+    fn next_page_links_keep_the_filters() {
+        let query = ListQuery::parse("total_page>=200&sort=book_name&per_page=2", FIELDS).unwrap();
+        let page:Page<()> = Page { items: vec![(), ()], total: 3 };
+
+        let pagination = query.pagination("/book/books", &page, None);
+        assert_eq!(pagination.page, Some(1));
+        assert_eq!(pagination.next.as_deref(), Some("/book/books?total_page%3E=200&sort=book_name&per_page=2&page=2"));
+    }
+
+    #[test]
+    fn skip_and_next_page_do_not_overflow() {
+        let query = ListQuery { page: u64::MAX, per_page: MAX_PER_PAGE, ..ListQuery::default() };
+        assert_eq!(query.skip(), u64::MAX);
+
+        let page:Page<()> = Page { items: Vec::new(), total: 10 };
+        assert!(query.pagination("/book/books", &page, None).next.is_none());
+    }
+}
@@ -0,0 +1,59 @@
+use actix_web::web;
+use argon2::{password_hash::{rand_core::{OsRng, RngCore}, PasswordHash, PasswordHasher, PasswordVerifier, SaltString}, Argon2};
+use lazy_static::lazy_static;
+use ring::digest;
+
+use crate::api::app_errors::AppError;
+
+// Argon2id with the crate defaults. Hashing is deliberately slow, so it runs on the
+// blocking thread pool instead of the worker serving the request.
+
+lazy_static! {
+    // checked instead when the user has no password, so unknown and known names take as long
+    static ref DUMMY_HASH: String = hash(b"dummy password").unwrap_or_default();
+}
+
+fn hash(password:&[u8]) -> Result<String, AppError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password, &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| AppError::InternalError(e.to_string()))
+}
+
+pub async fn hash_password(password:String) -> Result<String, AppError> {
+    web::block(move || hash(password.as_bytes()))
+        .await
+        .map_err(|e| AppError::InternalError(e.to_string()))?
+}
+
+// The comparison inside the argon2 verifier is constant-time
+pub async fn verify_password(password:String, password_hash:Option<String>) -> Result<bool, AppError> {
+    web::block(move || {
+        let known = password_hash.is_some();
+        let stored = password_hash.unwrap_or_else(|| DUMMY_HASH.to_owned());
+        let parsed = match PasswordHash::new(&stored) {
+            Ok(parsed) => parsed,
+            Err(_) => return false,
+        };
+        Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok() && known
+    })
+    .await
+    .map_err(|e| AppError::InternalError(e.to_string()))
+}
+
+// Random url safe token, hex encoded
+pub fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// tokens are long random strings, so a plain SHA-256 is enough to store and look them up
+pub fn hash_token(token:&str) -> String {
+    digest::digest(&digest::SHA256, token.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
@@ -1,20 +1,34 @@
+use std::collections::BTreeMap;
+
+use actix_web::http::StatusCode;
 use serde::{Serialize, Deserialize};
 
+use super::pagination::Pagination;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ResponseBuilder<T> {
     pub status:bool,
     pub error:String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
     pub msg:String,
     pub data: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: Option<BTreeMap<String, Vec<String>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pagination: Option<Pagination>,
 }
 #[allow(non_snake_case)]
 impl<T>  ResponseBuilder <T> {
-    pub fn BuildFailedResponse(err_msg:String) -> ResponseBuilder<T> {
+    pub fn BuildErrorResponse(status:StatusCode, code:&str, err_msg:String) -> ResponseBuilder<T> {
         ResponseBuilder {
             status:false,
-            error:String::from("Bad Request"),
+            error:status.canonical_reason().unwrap_or_default().to_string(),
+            code:Some(code.to_string()),
             msg:err_msg,
-            data:None
+            data:None,
+            fields:None,
+            pagination:None
         }   
     }
 
@@ -22,8 +36,23 @@ impl<T>  ResponseBuilder <T> {
         ResponseBuilder {
             status:true,
             error:String::from(""),
+            code:None,
+            msg:succ_msg,
+            data,
+            fields:None,
+            pagination:None
+        }
+    }
+
+    pub fn BuildPageResponse(succ_msg:String, data:Option<T>, pagination:Pagination) -> ResponseBuilder<T> {
+        ResponseBuilder {
+            status:true,
+            error:String::from(""),
+            code:None,
             msg:succ_msg,
-            data
+            data,
+            fields:None,
+            pagination:Some(pagination)
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,67 @@
+use std::{collections::BTreeMap, ops::Deref};
+
+use actix_web::{dev::Payload, web::Json, FromRequest, HttpRequest};
+use futures::future::LocalBoxFuture;
+use serde::de::DeserializeOwned;
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use crate::api::app_errors::AppError;
+
+// JSON body extractor that runs the `Validate` rules of the payload before the handler
+// is called, a failing body is answered with 422 and the messages of every bad field
+pub struct ValidJson<T>(pub T);
+
+impl<T> ValidJson<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for ValidJson<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: DeserializeOwned + Validate + 'static> FromRequest for ValidJson<T> {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let body = Json::<T>::from_request(req, payload);
+        Box::pin(async move {
+            let value = body.await?.into_inner();
+            value.validate().map_err(AppError::from)?;
+            Ok(ValidJson(value))
+        })
+    }
+}
+
+impl From<ValidationErrors> for AppError {
+    fn from(errors: ValidationErrors) -> Self {
+        let fields:BTreeMap<String, Vec<String>> = errors
+            .field_errors()
+            .into_iter()
+            .map(|(field, errors)| {
+                let messages = errors
+                    .iter()
+                    .map(|e| e.message.as_ref().map(|m| m.to_string()).unwrap_or_else(|| e.code.to_string()))
+                    .collect();
+                (field.to_string(), messages)
+            })
+            .collect();
+        AppError::FieldValidationError(fields)
+    }
+}
+
+// rejects strings made only of whitespace
+pub fn not_blank(value:&str) -> Result<(), ValidationError> {
+    if value.trim().is_empty() {
+        let mut error = ValidationError::new("blank");
+        error.message = Some("must not be blank".into());
+        return Err(error);
+    }
+    Ok(())
+}